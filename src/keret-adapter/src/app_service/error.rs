@@ -3,12 +3,18 @@ use std::backtrace::Backtrace;
 
 #[derive(Debug, Snafu)]
 #[snafu(visibility(pub(crate)))]
+#[allow(clippy::enum_variant_names)]
 pub(crate) enum Error {
     #[snafu(display("An error occurred while listening on input"))]
     FailedListeningForReport {
         source: Box<dyn std::error::Error + Send + Sync + 'static>,
         backtrace: Option<Backtrace>,
     },
+    #[snafu(display("An error occurred while keeping the report in the spool"))]
+    FailedSpoolingReport {
+        source: Box<dyn std::error::Error + Send + Sync + 'static>,
+        backtrace: Option<Backtrace>,
+    },
     #[snafu(display("An error occurred while trying to send report"))]
     FailedSendingToTarget {
         source: Box<dyn std::error::Error + Send + Sync + 'static>,
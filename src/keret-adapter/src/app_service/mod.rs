@@ -3,43 +3,249 @@ pub(crate) mod ports;
 #[cfg(test)]
 mod test;
 
-use crate::app_service::error::{FailedListeningForReportSnafu, FailedSendingToTargetSnafu};
-use crate::app_service::ports::{ReportMessaging, TrackResultInput};
+use crate::app_service::error::{
+    FailedListeningForReportSnafu, FailedSendingToTargetSnafu, FailedSpoolingReportSnafu,
+};
+use crate::app_service::ports::{ForwardingError, ReportMessaging, ReportSpool, TrackResultInput};
+use crate::model::{Report, ReportOrigin, SeenSequences, TrackResult};
 pub(crate) use error::Error;
 use snafu::ResultExt;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::Instant;
 
-pub(crate) struct ApplicationService<TInput, TOutput>
+/// wait after the first failed attempt to forward the spooled reports, unless configured
+pub(crate) const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// longest wait between two attempts, unless configured
+/// the backoff doubles with every failure up to this
+pub(crate) const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// most spooled activity reports forwarded at once, unless configured
+pub(crate) const BATCH_SIZE: usize = 100;
+
+/// longest wait for the next report, so the spooled reports are retried in time
+const RECEIVE_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub(crate) struct ApplicationService<TInput, TSpool, TOutput>
 where
     TInput: TrackResultInput + 'static,
+    TSpool: ReportSpool + 'static,
     TOutput: ReportMessaging + 'static,
 {
     input: TInput,
+    spool: TSpool,
     output: TOutput,
+    /// sequences of the activity and crash reports spooled per device, to drop repeated deliveries
+    spooled: HashMap<u64, SeenSequences>,
+    /// wait after the next failed attempt to forward
+    backoff: Duration,
+    /// wait after the first failed attempt to forward
+    initial_backoff: Duration,
+    /// longest wait between two attempts to forward
+    max_backoff: Duration,
+    /// no attempt to forward is made before, as the last one failed
+    retry_at: Option<Instant>,
+    /// most spooled activity reports forwarded at once
+    batch_size: usize,
 }
 
-impl<TInput, TOutput> ApplicationService<TInput, TOutput>
+impl<TInput, TSpool, TOutput> ApplicationService<TInput, TSpool, TOutput>
 where
     TInput: TrackResultInput + 'static,
+    TSpool: ReportSpool + 'static,
     TOutput: ReportMessaging + 'static,
 {
-    pub(crate) fn new(input: TInput, output: TOutput) -> Self {
-        Self { input, output }
+    pub(crate) fn new(input: TInput, spool: TSpool, output: TOutput) -> Self {
+        Self {
+            input,
+            spool,
+            output,
+            spooled: HashMap::new(),
+            backoff: INITIAL_BACKOFF,
+            initial_backoff: INITIAL_BACKOFF,
+            max_backoff: MAX_BACKOFF,
+            retry_at: None,
+            batch_size: BATCH_SIZE,
+        }
     }
 
+    /// wait `initial` after the first failed attempt to forward, doubling up to `max`
+    pub(crate) fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.backoff = initial;
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// forward up to `size` spooled activity reports at once, one at a time if 1
+    pub(crate) fn with_batch_size(mut self, size: usize) -> Self {
+        self.batch_size = size;
+        self
+    }
+
+    /// wait for the next report to put into the spool and forward all spooled reports
+    /// spooled reports are forwarded even if reading failed, so an unplugged controller
+    /// does not hold back the reports received before
     pub(crate) async fn read_and_forward(&mut self) -> Result<(), Error> {
-        let report = self
-            .input
-            .read_next_report()
-            .boxed()
-            .context(FailedListeningForReportSnafu)?;
-        if let Some(report) = report {
-            self.output
-                .send(report)
+        let received = self.receive().await;
+        let forwarded = self.forward_spooled().await;
+
+        received.and(forwarded)
+    }
+
+    /// wait for the next report, keeping it in the spool until it can be forwarded
+    async fn receive(&mut self) -> Result<(), Error> {
+        let report =
+            match tokio::time::timeout(RECEIVE_TIMEOUT, self.input.read_next_report()).await {
+                Ok(report) => report.boxed().context(FailedListeningForReportSnafu)?,
+                // nothing arrived, but the spooled reports may have to be retried
+                Err(_) => None,
+            };
+
+        let Some(report) = report else {
+            return Ok(());
+        };
+        if let Some(origin) = report.origin().filter(|origin| self.is_duplicate(origin)) {
+            eprintln!(
+                "[INFO] dropping repeated report #{} of boot {:08x} of {:016x}",
+                origin.sequence, origin.boot_id, origin.serial_number
+            );
+            // kept before, but the confirmation may not have reached the controller
+            self.input.confirm(origin);
+            return Ok(());
+        }
+
+        match report {
+            // outdated by the next heartbeat anyway, not worth keeping
+            status @ Report::Status(_) => self
+                .forward(status)
                 .await
                 .boxed()
-                .context(FailedSendingToTargetSnafu)?;
+                .context(FailedSendingToTargetSnafu),
+            report => self.spool(report),
+        }
+    }
+
+    /// keep the report in the spool, so it survives until forwarded
+    /// only then the controller is told it may forget the report
+    fn spool(&mut self, report: Report) -> Result<(), Error> {
+        let origin = report.origin().cloned();
+        let report = match report {
+            // it may be forwarded much later, so pin the time it arrived at least
+            Report::Activity(origin, result) if result.finished_at().is_none() => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |since| since.as_secs());
+                Report::Activity(origin, result.with_finished_at(Some(now)))
+            }
+            other => other,
+        };
+
+        self.spool
+            .push(report)
+            .boxed()
+            .context(FailedSpoolingReportSnafu)?;
+        if let Some(origin) = origin {
+            self.input.confirm(&origin);
+            self.spooled
+                .entry(origin.serial_number)
+                .and_modify(|seen| seen.insert(&origin))
+                .or_insert_with(|| SeenSequences::new(&origin));
+        }
+
+        Ok(())
+    }
+
+    /// forward the spooled reports in order, removing each one once it was sent
+    /// consecutive activity reports, as piled up during an outage, are sent in batches
+    /// after a failure no attempt is made until the backoff passed. A report refused for good
+    /// is put aside instead, so it does not hold back the ones behind it
+    async fn forward_spooled(&mut self) -> Result<(), Error> {
+        if self
+            .retry_at
+            .is_some_and(|retry_at| Instant::now() < retry_at)
+        {
+            return Ok(());
+        }
+
+        while let Some(report) = self.spool.oldest() {
+            let batch = self.activity_batch();
+            let forwarded = if batch.len() > 1 {
+                self.output.send_batch(batch).await
+            } else {
+                self.forward(report).await.map(|()| 1)
+            };
+
+            let count = match forwarded {
+                Ok(count) => count,
+                Err(e) if e.is_permanent() => {
+                    let reason = crate::error_chain(&e);
+                    eprintln!(
+                        "[SPOOL] putting aside a report refused for good: {}",
+                        reason
+                    );
+                    self.spool
+                        .reject_oldest(&reason)
+                        .boxed()
+                        .context(FailedSpoolingReportSnafu)?;
+                    continue;
+                }
+                Err(e) => {
+                    eprintln!(
+                        "[SPOOL] keeping {} report(s), retrying in {}s",
+                        self.spool.len(),
+                        self.backoff.as_secs()
+                    );
+                    self.retry_at = Some(Instant::now() + self.backoff);
+                    self.backoff = (self.backoff * 2).min(self.max_backoff);
+                    return Err(e).boxed().context(FailedSendingToTargetSnafu);
+                }
+            };
+
+            if count == 0 {
+                // nothing was taken without a failure, try again with the next report received
+                break;
+            }
+            for _ in 0..count {
+                self.spool
+                    .remove_oldest()
+                    .boxed()
+                    .context(FailedSpoolingReportSnafu)?;
+            }
+            self.retry_at = None;
+            self.backoff = self.initial_backoff;
         }
 
         Ok(())
     }
+
+    /// the activity reports at the front of the spool, up to the batch size
+    fn activity_batch(&self) -> Vec<(ReportOrigin, TrackResult)> {
+        self.spool
+            .oldest_n(self.batch_size)
+            .into_iter()
+            .map_while(|report| match report {
+                Report::Activity(origin, result) => Some((origin, result)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// send the report to the target, depending on its kind
+    async fn forward(&self, report: Report) -> Result<(), TOutput::Error> {
+        match report {
+            Report::Activity(origin, result) => self.output.send(origin, result).await,
+            Report::Crash(crash) => self.output.send_crash_report(crash).await,
+            Report::Identity(identity) => self.output.send_device_identity(identity).await,
+            Report::Status(status) => self.output.send_device_status(status).await,
+        }
+    }
+
+    /// whether the activity report was already received
+    fn is_duplicate(&self, origin: &ReportOrigin) -> bool {
+        self.spooled
+            .get(&origin.serial_number)
+            .is_some_and(|seen| seen.contains(origin))
+    }
 }
@@ -1,13 +1,62 @@
-use crate::model::TrackResult;
+use crate::model::{CrashReport, DeviceIdentity, DeviceStatus, Report, ReportOrigin, TrackResult};
 use async_trait::async_trait;
 
+#[async_trait]
 pub(crate) trait TrackResultInput {
     type Error: std::error::Error + Send + Sync + 'static;
-    fn read_next_report(&mut self) -> Result<Option<TrackResult>, Self::Error>;
+    /// wait for the next report, which may be nothing if the input had to tend to other things
+    async fn read_next_report(&mut self) -> Result<Option<Report>, Self::Error>;
+    /// the activity report was kept safely, so its controller may stop sending it again
+    fn confirm(&mut self, origin: &ReportOrigin);
 }
 
-#[async_trait]
-pub(crate) trait ReportMessaging {
+/// keeps the reports until they were forwarded, in the order they were received
+pub(crate) trait ReportSpool {
     type Error: std::error::Error + Send + Sync + 'static;
-    async fn send(&self, report: TrackResult) -> Result<(), Self::Error>;
+    /// keep the report after all reports kept so far
+    fn push(&mut self, report: Report) -> Result<(), Self::Error>;
+    /// the report kept the longest, to be forwarded next
+    fn oldest(&self) -> Option<Report>;
+    /// up to `count` of the reports kept the longest, in their order
+    fn oldest_n(&self, count: usize) -> Vec<Report>;
+    /// forget the report kept the longest, once it was forwarded
+    fn remove_oldest(&mut self) -> Result<(), Self::Error>;
+    /// put the report kept the longest aside with the reason, as it is never taken
+    fn reject_oldest(&mut self, reason: &str) -> Result<(), Self::Error>;
+    /// number of reports still to be forwarded
+    fn len(&self) -> usize;
+}
+
+/// a failure to forward a report
+pub(crate) trait ForwardingError: std::error::Error + Send + Sync + 'static {
+    /// whether the target refused the report for good, sending it again fails the same way
+    fn is_permanent(&self) -> bool;
+}
+
+#[async_trait]
+pub(crate) trait ReportMessaging: Sync {
+    type Error: ForwardingError;
+    async fn send(&self, origin: ReportOrigin, report: TrackResult) -> Result<(), Self::Error>;
+    async fn send_crash_report(&self, report: CrashReport) -> Result<(), Self::Error>;
+    async fn send_device_identity(&self, identity: DeviceIdentity) -> Result<(), Self::Error>;
+    async fn send_device_status(&self, status: DeviceStatus) -> Result<(), Self::Error>;
+
+    /// send several activity reports in their order, returning how many of them were taken,
+    /// counted from the first. Fails if not even the first one was taken
+    /// unless the target supports batches, the reports are sent one at a time
+    async fn send_batch(
+        &self,
+        reports: Vec<(ReportOrigin, TrackResult)>,
+    ) -> Result<usize, Self::Error> {
+        let mut sent = 0;
+        for (origin, report) in reports {
+            match self.send(origin, report).await {
+                Ok(()) => sent += 1,
+                Err(e) if sent == 0 => return Err(e),
+                // the failed report is the first one of the next attempt, failing there
+                Err(_) => break,
+            }
+        }
+        Ok(sent)
+    }
 }
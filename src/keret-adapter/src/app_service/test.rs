@@ -1,26 +1,116 @@
+use crate::app_service::ports::{ForwardingError, ReportSpool};
 use crate::app_service::{ApplicationService, Error};
-use crate::model::TrackResult;
+use crate::model::{
+    CrashReport, DeviceIdentity, DeviceMode, DeviceStatus, Report, ReportOrigin, TrackResult,
+};
 use async_trait::async_trait;
 use mockall::mock;
 use snafu::Snafu;
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
 
 #[derive(Debug, Snafu)]
 #[snafu(visibility(pub(crate)))]
 pub enum TestError {
     #[snafu(display("Test Error"))]
     ErrorForTest,
+    #[snafu(display("Refused for good"))]
+    RefusedForTest,
+}
+
+impl ForwardingError for TestError {
+    fn is_permanent(&self) -> bool {
+        matches!(self, TestError::RefusedForTest)
+    }
 }
 
 const DURATION: u64 = 10;
+const LINE: u32 = 42;
+const SERIAL_NUMBER: u64 = 0x0123_4567_89AB_CDEF;
+const BOOT_ID: u32 = 0xB007;
+
+fn activity(boot_id: u32, sequence: u32) -> Report {
+    let origin = ReportOrigin {
+        serial_number: SERIAL_NUMBER,
+        boot_id,
+        sequence,
+    };
+    Report::Activity(origin, TrackResult::from(DURATION))
+}
+
+fn crash(boot_id: u32, sequence: u32) -> Report {
+    Report::Crash(CrashReport {
+        origin: ReportOrigin {
+            serial_number: SERIAL_NUMBER,
+            boot_id,
+            sequence,
+        },
+        received_at: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        message: "test panic".to_string(),
+        file: "src/main.rs".to_string(),
+        line: LINE,
+    })
+}
+
+fn sequence_is(origin: &ReportOrigin, sequence: u32) -> bool {
+    origin.sequence == sequence
+}
+
+/// spool kept in memory, optionally failing to keep anything
+#[derive(Default)]
+struct MemorySpool {
+    reports: VecDeque<Report>,
+    failing: bool,
+    /// the reports put aside, with the reason
+    rejected: Vec<(Report, String)>,
+}
+
+impl ReportSpool for MemorySpool {
+    type Error = TestError;
+
+    fn push(&mut self, report: Report) -> Result<(), TestError> {
+        if self.failing {
+            return ErrorForTestSnafu.fail();
+        }
+        self.reports.push_back(report);
+        Ok(())
+    }
+
+    fn oldest(&self) -> Option<Report> {
+        self.reports.front().cloned()
+    }
+
+    fn oldest_n(&self, count: usize) -> Vec<Report> {
+        self.reports.iter().take(count).cloned().collect()
+    }
+
+    fn remove_oldest(&mut self) -> Result<(), TestError> {
+        self.reports.pop_front();
+        Ok(())
+    }
+
+    fn reject_oldest(&mut self, reason: &str) -> Result<(), TestError> {
+        if let Some(report) = self.reports.pop_front() {
+            self.rejected.push((report, reason.to_string()));
+        }
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.reports.len()
+    }
+}
 
 // create mocks of the ports
 
 mock! {
     MyTrackResultInput {}
 
+    #[async_trait]
     impl crate::app_service::ports::TrackResultInput for MyTrackResultInput {
         type Error = TestError;
-        fn read_next_report(&mut self) -> Result<Option<TrackResult>, TestError>;
+        async fn read_next_report(&mut self) -> Result<Option<Report>, TestError>;
+        fn confirm(&mut self, origin: &ReportOrigin);
     }
 }
 
@@ -30,7 +120,14 @@ mock! {
     #[async_trait]
     impl crate::app_service::ports::ReportMessaging for MyReportMessaging {
         type Error = TestError;
-        async fn send(&self, report: TrackResult) -> Result<(), TestError>;
+        async fn send(&self, origin: ReportOrigin, report: TrackResult) -> Result<(), TestError>;
+        async fn send_crash_report(&self, report: CrashReport) -> Result<(), TestError>;
+        async fn send_device_identity(&self, identity: DeviceIdentity) -> Result<(), TestError>;
+        async fn send_device_status(&self, status: DeviceStatus) -> Result<(), TestError>;
+        async fn send_batch(
+            &self,
+            reports: Vec<(ReportOrigin, TrackResult)>,
+        ) -> Result<usize, TestError>;
     }
 }
 
@@ -40,15 +137,16 @@ mock! {
 async fn read_and_forward_having_report_is_send() {
     // arrange
     let mut input = MockMyTrackResultInput::default();
+    input.expect_confirm().return_const(());
     input
         .expect_read_next_report()
         .once()
-        .returning(|| Ok(Some(TrackResult::from(DURATION))));
+        .returning(|| Ok(Some(activity(BOOT_ID, 0))));
 
     let mut output = MockMyReportMessaging::default();
-    output.expect_send().once().returning(|_| Ok(()));
+    output.expect_send().once().returning(|_, _| Ok(()));
 
-    let mut app_service = ApplicationService::new(input, output);
+    let mut app_service = ApplicationService::new(input, MemorySpool::default(), output);
 
     // act
     let _ = app_service.read_and_forward().await;
@@ -68,7 +166,7 @@ async fn read_and_forward_failing_read_does_not_send() {
     let mut output = MockMyReportMessaging::default();
     output.expect_send().never();
 
-    let mut app_service = ApplicationService::new(input, output);
+    let mut app_service = ApplicationService::new(input, MemorySpool::default(), output);
 
     // act
     let _ = app_service.read_and_forward().await;
@@ -88,7 +186,7 @@ async fn read_and_forward_failing_read_returns_error() {
     let mut output = MockMyReportMessaging::default();
     output.expect_send().never();
 
-    let mut app_service = ApplicationService::new(input, output);
+    let mut app_service = ApplicationService::new(input, MemorySpool::default(), output);
 
     // act
     let actual = app_service.read_and_forward().await;
@@ -104,18 +202,19 @@ async fn read_and_forward_failing_read_returns_error() {
 async fn read_and_forward_failing_send_returns_error() {
     // arrange
     let mut input = MockMyTrackResultInput::default();
+    input.expect_confirm().return_const(());
     input
         .expect_read_next_report()
         .once()
-        .returning(|| Ok(Some(TrackResult::from(DURATION))));
+        .returning(|| Ok(Some(activity(BOOT_ID, 0))));
 
     let mut output = MockMyReportMessaging::default();
     output
         .expect_send()
         .once()
-        .returning(|_| ErrorForTestSnafu.fail());
+        .returning(|_, _| ErrorForTestSnafu.fail());
 
-    let mut app_service = ApplicationService::new(input, output);
+    let mut app_service = ApplicationService::new(input, MemorySpool::default(), output);
 
     // act
     let actual = app_service.read_and_forward().await;
@@ -123,3 +222,724 @@ async fn read_and_forward_failing_send_returns_error() {
     // assert -> mockall
     assert!(matches!(actual, Err(Error::FailedSendingToTarget { .. })));
 }
+
+#[tokio::test]
+async fn read_and_forward_having_crash_report_is_send_as_crash_report() {
+    // arrange
+    let mut input = MockMyTrackResultInput::default();
+    input
+        .expect_read_next_report()
+        .once()
+        .returning(|| Ok(Some(crash(BOOT_ID, 0))));
+    input
+        .expect_confirm()
+        .once()
+        .withf(|origin| sequence_is(origin, 0))
+        .return_const(());
+
+    let mut output = MockMyReportMessaging::default();
+    output.expect_send().never();
+    output
+        .expect_send_crash_report()
+        .once()
+        .withf(|report| report.line == LINE)
+        .returning(|_| Ok(()));
+
+    let mut app_service = ApplicationService::new(input, MemorySpool::default(), output);
+
+    // act
+    let _ = app_service.read_and_forward().await;
+
+    // assert -> mockall
+}
+
+#[tokio::test]
+async fn read_and_forward_having_device_identity_is_send_as_identity() {
+    // arrange
+    let mut input = MockMyTrackResultInput::default();
+    input.expect_read_next_report().once().returning(|| {
+        Ok(Some(Report::Identity(DeviceIdentity {
+            serial_number: SERIAL_NUMBER,
+            firmware_version: "0.1.0".to_string(),
+            protocol_version: 1,
+        })))
+    });
+
+    let mut output = MockMyReportMessaging::default();
+    output.expect_send().never();
+    output
+        .expect_send_device_identity()
+        .once()
+        .withf(|identity| identity.serial_number == SERIAL_NUMBER)
+        .returning(|_| Ok(()));
+
+    let mut app_service = ApplicationService::new(input, MemorySpool::default(), output);
+
+    // act
+    let _ = app_service.read_and_forward().await;
+
+    // assert -> mockall
+}
+
+#[tokio::test]
+async fn read_and_forward_repeated_report_is_dropped() {
+    // arrange
+    let mut input = MockMyTrackResultInput::default();
+    // the repeated report is confirmed again, the first confirmation may have been lost
+    input.expect_confirm().times(3).return_const(());
+    let mut reports = vec![
+        activity(BOOT_ID, 0),
+        activity(BOOT_ID, 1),
+        activity(BOOT_ID, 1),
+    ]
+    .into_iter();
+    input
+        .expect_read_next_report()
+        .times(3)
+        .returning(move || Ok(reports.next()));
+
+    let mut output = MockMyReportMessaging::default();
+    output.expect_send().times(2).returning(|_, _| Ok(()));
+
+    let mut app_service = ApplicationService::new(input, MemorySpool::default(), output);
+
+    // act
+    for _ in 0..3 {
+        let _ = app_service.read_and_forward().await;
+    }
+
+    // assert -> mockall
+}
+
+#[tokio::test]
+async fn read_and_forward_repeated_crash_report_is_dropped() {
+    // arrange
+    let mut input = MockMyTrackResultInput::default();
+    // the controller keeps the panic until the crash report is confirmed
+    input
+        .expect_confirm()
+        .times(2)
+        .withf(|origin| sequence_is(origin, 0))
+        .return_const(());
+    let mut reports = vec![crash(BOOT_ID, 0), crash(BOOT_ID, 0)].into_iter();
+    input
+        .expect_read_next_report()
+        .times(2)
+        .returning(move || Ok(reports.next()));
+
+    let mut output = MockMyReportMessaging::default();
+    output
+        .expect_send_crash_report()
+        .once()
+        .returning(|_| Ok(()));
+
+    let mut app_service = ApplicationService::new(input, MemorySpool::default(), output);
+
+    // act
+    for _ in 0..2 {
+        let _ = app_service.read_and_forward().await;
+    }
+
+    // assert -> mockall
+}
+
+#[tokio::test]
+async fn read_and_forward_report_resent_after_later_one_is_send() {
+    // arrange
+    let mut input = MockMyTrackResultInput::default();
+    input.expect_confirm().return_const(());
+    let mut reports = vec![
+        activity(BOOT_ID, 0),
+        activity(BOOT_ID, 2),
+        activity(BOOT_ID, 1),
+        activity(BOOT_ID, 2),
+    ]
+    .into_iter();
+    input
+        .expect_read_next_report()
+        .times(4)
+        .returning(move || Ok(reports.next()));
+
+    let mut output = MockMyReportMessaging::default();
+    let mut sequence = mockall::Sequence::new();
+    for expected in [0, 2, 1] {
+        output
+            .expect_send()
+            .withf(move |origin, _| sequence_is(origin, expected))
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|_, _| Ok(()));
+    }
+
+    let mut app_service = ApplicationService::new(input, MemorySpool::default(), output);
+
+    // act
+    for _ in 0..4 {
+        let _ = app_service.read_and_forward().await;
+    }
+
+    // assert -> mockall
+}
+
+#[tokio::test]
+async fn read_and_forward_report_of_new_boot_is_send() {
+    // arrange
+    let mut input = MockMyTrackResultInput::default();
+    input.expect_confirm().return_const(());
+    let mut reports = vec![activity(BOOT_ID, 5), activity(BOOT_ID + 1, 0)].into_iter();
+    input
+        .expect_read_next_report()
+        .times(2)
+        .returning(move || Ok(reports.next()));
+
+    let mut output = MockMyReportMessaging::default();
+    output.expect_send().times(2).returning(|_, _| Ok(()));
+
+    let mut app_service = ApplicationService::new(input, MemorySpool::default(), output);
+
+    // act
+    for _ in 0..2 {
+        let _ = app_service.read_and_forward().await;
+    }
+
+    // assert -> mockall
+}
+
+#[tokio::test(start_paused = true)]
+async fn read_and_forward_report_failed_to_send_is_send_again_after_backoff() {
+    // arrange
+    let mut input = MockMyTrackResultInput::default();
+    input.expect_confirm().return_const(());
+    let mut reports = vec![activity(BOOT_ID, 0)].into_iter();
+    input
+        .expect_read_next_report()
+        .times(3)
+        .returning(move || Ok(reports.next()));
+
+    let mut output = MockMyReportMessaging::default();
+    let mut sequence = mockall::Sequence::new();
+    output
+        .expect_send()
+        .once()
+        .in_sequence(&mut sequence)
+        .returning(|_, _| ErrorForTestSnafu.fail());
+    output
+        .expect_send()
+        .once()
+        .in_sequence(&mut sequence)
+        .returning(|_, _| Ok(()));
+
+    let mut app_service = ApplicationService::new(input, MemorySpool::default(), output);
+
+    // act -> the second attempt is within the backoff, the third one after it
+    let first = app_service.read_and_forward().await;
+    let second = app_service.read_and_forward().await;
+    tokio::time::advance(Duration::from_secs(1)).await;
+    let third = app_service.read_and_forward().await;
+
+    // assert
+    assert!(first.is_err());
+    assert!(second.is_ok());
+    assert!(third.is_ok());
+    assert_eq!(app_service.spool.len(), 0);
+}
+
+#[tokio::test(start_paused = true)]
+async fn read_and_forward_repeatedly_failing_send_doubles_backoff() {
+    // arrange
+    let mut input = MockMyTrackResultInput::default();
+    input.expect_confirm().return_const(());
+    let mut reports = vec![activity(BOOT_ID, 0)].into_iter();
+    input
+        .expect_read_next_report()
+        .returning(move || Ok(reports.next()));
+
+    let mut output = MockMyReportMessaging::default();
+    output
+        .expect_send()
+        .times(2)
+        .returning(|_, _| ErrorForTestSnafu.fail());
+
+    let mut app_service = ApplicationService::new(input, MemorySpool::default(), output);
+
+    // act -> fails after 0s and 1s, the next attempt is due after another 2s
+    let _ = app_service.read_and_forward().await;
+    tokio::time::advance(Duration::from_secs(1)).await;
+    let _ = app_service.read_and_forward().await;
+    tokio::time::advance(Duration::from_secs(1)).await;
+    let _ = app_service.read_and_forward().await;
+
+    // assert -> + mockall
+    assert_eq!(app_service.spool.len(), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn read_and_forward_failing_send_waits_configured_backoff() {
+    // arrange
+    let mut input = MockMyTrackResultInput::default();
+    input.expect_confirm().return_const(());
+    let mut reports = vec![activity(BOOT_ID, 0)].into_iter();
+    input
+        .expect_read_next_report()
+        .returning(move || Ok(reports.next()));
+
+    let mut output = MockMyReportMessaging::default();
+    output
+        .expect_send()
+        .once()
+        .returning(|_, _| ErrorForTestSnafu.fail());
+
+    let mut app_service = ApplicationService::new(input, MemorySpool::default(), output)
+        .with_backoff(Duration::from_secs(10), Duration::from_secs(60));
+
+    // act -> no attempt is made before the configured backoff passed
+    let _ = app_service.read_and_forward().await;
+    tokio::time::advance(Duration::from_secs(9)).await;
+    let actual = app_service.read_and_forward().await;
+
+    // assert -> + mockall
+    assert!(actual.is_ok());
+    assert_eq!(app_service.spool.len(), 1);
+}
+
+#[tokio::test]
+async fn read_and_forward_failing_send_keeps_report_in_spool() {
+    // arrange
+    let mut input = MockMyTrackResultInput::default();
+    input.expect_confirm().return_const(());
+    input
+        .expect_read_next_report()
+        .once()
+        .returning(|| Ok(Some(activity(BOOT_ID, 0))));
+
+    let mut output = MockMyReportMessaging::default();
+    output
+        .expect_send()
+        .once()
+        .returning(|_, _| ErrorForTestSnafu.fail());
+
+    let mut app_service = ApplicationService::new(input, MemorySpool::default(), output);
+
+    // act
+    let _ = app_service.read_and_forward().await;
+
+    // assert
+    assert_eq!(app_service.spool.len(), 1);
+}
+
+#[tokio::test]
+async fn read_and_forward_spooled_reports_are_send_in_order() {
+    // arrange
+    let mut input = MockMyTrackResultInput::default();
+    input.expect_confirm().return_const(());
+    input
+        .expect_read_next_report()
+        .once()
+        .returning(|| Ok(Some(activity(BOOT_ID, 2))));
+
+    let mut output = MockMyReportMessaging::default();
+    let mut sequence = mockall::Sequence::new();
+    for expected in 0..3 {
+        output
+            .expect_send()
+            .once()
+            .in_sequence(&mut sequence)
+            .withf(move |origin, _| sequence_is(origin, expected))
+            .returning(|_, _| Ok(()));
+    }
+
+    // reports kept from before a restart
+    let spool = MemorySpool {
+        reports: vec![activity(BOOT_ID, 0), activity(BOOT_ID, 1)].into(),
+        failing: false,
+        rejected: Vec::new(),
+    };
+    let mut app_service = ApplicationService::new(input, spool, output).with_batch_size(1);
+
+    // act
+    let actual = app_service.read_and_forward().await;
+
+    // assert -> + mockall
+    assert!(actual.is_ok());
+    assert_eq!(app_service.spool.len(), 0);
+}
+
+#[tokio::test]
+async fn read_and_forward_spooled_activity_reports_are_send_as_batch() {
+    // arrange
+    let mut input = MockMyTrackResultInput::default();
+    input.expect_confirm().return_const(());
+    input
+        .expect_read_next_report()
+        .once()
+        .returning(|| Ok(Some(activity(BOOT_ID, 2))));
+
+    let mut output = MockMyReportMessaging::default();
+    output.expect_send().never();
+    output
+        .expect_send_batch()
+        .once()
+        .withf(|reports| reports.iter().map(|(origin, _)| origin.sequence).eq(0..3))
+        .returning(|reports| Ok(reports.len()));
+
+    // reports kept during an outage
+    let spool = MemorySpool {
+        reports: vec![activity(BOOT_ID, 0), activity(BOOT_ID, 1)].into(),
+        failing: false,
+        rejected: Vec::new(),
+    };
+    let mut app_service = ApplicationService::new(input, spool, output);
+
+    // act
+    let actual = app_service.read_and_forward().await;
+
+    // assert -> + mockall
+    assert!(actual.is_ok());
+    assert_eq!(app_service.spool.len(), 0);
+}
+
+#[tokio::test]
+async fn read_and_forward_batch_is_limited_to_batch_size() {
+    // arrange
+    let mut input = MockMyTrackResultInput::default();
+    input
+        .expect_read_next_report()
+        .once()
+        .returning(|| Ok(None));
+
+    let mut output = MockMyReportMessaging::default();
+    output
+        .expect_send_batch()
+        .times(2)
+        .withf(|reports| reports.len() == 2)
+        .returning(|reports| Ok(reports.len()));
+    output
+        .expect_send()
+        .once()
+        .withf(|origin, _| sequence_is(origin, 4))
+        .returning(|_, _| Ok(()));
+
+    let spool = MemorySpool {
+        reports: (0..5).map(|sequence| activity(BOOT_ID, sequence)).collect(),
+        failing: false,
+        rejected: Vec::new(),
+    };
+    let mut app_service = ApplicationService::new(input, spool, output).with_batch_size(2);
+
+    // act
+    let actual = app_service.read_and_forward().await;
+
+    // assert -> + mockall
+    assert!(actual.is_ok());
+    assert_eq!(app_service.spool.len(), 0);
+}
+
+#[tokio::test]
+async fn read_and_forward_batch_ends_before_other_report() {
+    // arrange
+    let mut input = MockMyTrackResultInput::default();
+    input
+        .expect_read_next_report()
+        .once()
+        .returning(|| Ok(None));
+
+    let mut output = MockMyReportMessaging::default();
+    let mut sequence = mockall::Sequence::new();
+    output
+        .expect_send_batch()
+        .once()
+        .in_sequence(&mut sequence)
+        .withf(|reports| reports.len() == 2)
+        .returning(|reports| Ok(reports.len()));
+    output
+        .expect_send_crash_report()
+        .once()
+        .in_sequence(&mut sequence)
+        .returning(|_| Ok(()));
+    output
+        .expect_send()
+        .once()
+        .in_sequence(&mut sequence)
+        .withf(|origin, _| sequence_is(origin, 3))
+        .returning(|_, _| Ok(()));
+
+    let spool = MemorySpool {
+        reports: vec![
+            activity(BOOT_ID, 0),
+            activity(BOOT_ID, 1),
+            crash(BOOT_ID, 2),
+            activity(BOOT_ID, 3),
+        ]
+        .into(),
+        failing: false,
+        rejected: Vec::new(),
+    };
+    let mut app_service = ApplicationService::new(input, spool, output);
+
+    // act
+    let actual = app_service.read_and_forward().await;
+
+    // assert -> + mockall
+    assert!(actual.is_ok());
+    assert_eq!(app_service.spool.len(), 0);
+}
+
+#[tokio::test]
+async fn read_and_forward_refused_report_is_put_aside() {
+    // arrange
+    let mut input = MockMyTrackResultInput::default();
+    input
+        .expect_read_next_report()
+        .once()
+        .returning(|| Ok(None));
+
+    let mut output = MockMyReportMessaging::default();
+    let mut sequence = mockall::Sequence::new();
+    output
+        .expect_send()
+        .once()
+        .in_sequence(&mut sequence)
+        .withf(|origin, _| sequence_is(origin, 0))
+        .returning(|_, _| RefusedForTestSnafu.fail());
+    output
+        .expect_send()
+        .once()
+        .in_sequence(&mut sequence)
+        .withf(|origin, _| sequence_is(origin, 1))
+        .returning(|_, _| Ok(()));
+
+    let spool = MemorySpool {
+        reports: vec![activity(BOOT_ID, 0), activity(BOOT_ID, 1)].into(),
+        ..MemorySpool::default()
+    };
+    let mut app_service = ApplicationService::new(input, spool, output).with_batch_size(1);
+
+    // act
+    let actual = app_service.read_and_forward().await;
+
+    // assert -> the refused report does not hold back the next one + mockall
+    assert!(actual.is_ok());
+    assert_eq!(app_service.spool.len(), 0);
+    assert_eq!(
+        app_service.spool.rejected,
+        vec![(activity(BOOT_ID, 0), "Refused for good".to_string())]
+    );
+}
+
+#[tokio::test]
+async fn read_and_forward_batch_with_refused_first_report_puts_it_aside() {
+    // arrange
+    let mut input = MockMyTrackResultInput::default();
+    input
+        .expect_read_next_report()
+        .once()
+        .returning(|| Ok(None));
+
+    let mut output = MockMyReportMessaging::default();
+    let mut sequence = mockall::Sequence::new();
+    output
+        .expect_send_batch()
+        .once()
+        .in_sequence(&mut sequence)
+        .withf(|reports| reports.len() == 3)
+        .returning(|_| RefusedForTestSnafu.fail());
+    output
+        .expect_send_batch()
+        .once()
+        .in_sequence(&mut sequence)
+        .withf(|reports| reports.len() == 2 && sequence_is(&reports[0].0, 1))
+        .returning(|_| Ok(2));
+
+    let spool = MemorySpool {
+        reports: (0..3).map(|sequence| activity(BOOT_ID, sequence)).collect(),
+        ..MemorySpool::default()
+    };
+    let mut app_service = ApplicationService::new(input, spool, output);
+
+    // act
+    let actual = app_service.read_and_forward().await;
+
+    // assert -> + mockall
+    assert!(actual.is_ok());
+    assert_eq!(app_service.spool.len(), 0);
+    assert_eq!(app_service.spool.rejected.len(), 1);
+}
+
+#[tokio::test]
+async fn read_and_forward_partly_taken_batch_keeps_the_rest_in_spool() {
+    // arrange
+    let mut input = MockMyTrackResultInput::default();
+    input
+        .expect_read_next_report()
+        .once()
+        .returning(|| Ok(None));
+
+    let mut output = MockMyReportMessaging::default();
+    let mut sequence = mockall::Sequence::new();
+    output
+        .expect_send_batch()
+        .once()
+        .in_sequence(&mut sequence)
+        .returning(|_| Ok(1));
+    output
+        .expect_send_batch()
+        .once()
+        .in_sequence(&mut sequence)
+        .withf(|reports| reports[0].0.sequence == 1)
+        .returning(|_| ErrorForTestSnafu.fail());
+
+    let spool = MemorySpool {
+        reports: (0..3).map(|sequence| activity(BOOT_ID, sequence)).collect(),
+        failing: false,
+        rejected: Vec::new(),
+    };
+    let mut app_service = ApplicationService::new(input, spool, output);
+
+    // act
+    let actual = app_service.read_and_forward().await;
+
+    // assert -> + mockall
+    assert!(matches!(actual, Err(Error::FailedSendingToTarget { .. })));
+    assert_eq!(app_service.spool.len(), 2);
+}
+
+#[tokio::test]
+async fn read_and_forward_failing_read_still_sends_spooled_reports() {
+    // arrange
+    let mut input = MockMyTrackResultInput::default();
+    input
+        .expect_read_next_report()
+        .once()
+        .returning(|| ErrorForTestSnafu.fail());
+
+    let mut output = MockMyReportMessaging::default();
+    output.expect_send().once().returning(|_, _| Ok(()));
+
+    let spool = MemorySpool {
+        reports: vec![activity(BOOT_ID, 0)].into(),
+        failing: false,
+        rejected: Vec::new(),
+    };
+    let mut app_service = ApplicationService::new(input, spool, output);
+
+    // act
+    let actual = app_service.read_and_forward().await;
+
+    // assert -> + mockall
+    assert!(matches!(
+        actual,
+        Err(Error::FailedListeningForReport { .. })
+    ));
+}
+
+#[tokio::test]
+async fn read_and_forward_spooled_report_is_confirmed_even_if_send_fails() {
+    // arrange
+    let mut input = MockMyTrackResultInput::default();
+    input
+        .expect_read_next_report()
+        .once()
+        .returning(|| Ok(Some(activity(BOOT_ID, 4))));
+    input
+        .expect_confirm()
+        .withf(|origin| sequence_is(origin, 4))
+        .once()
+        .return_const(());
+
+    let mut output = MockMyReportMessaging::default();
+    output
+        .expect_send()
+        .once()
+        .returning(|_, _| Err(TestError::ErrorForTest));
+
+    let mut app_service = ApplicationService::new(input, MemorySpool::default(), output);
+
+    // act
+    let _ = app_service.read_and_forward().await;
+
+    // assert -> mockall
+}
+
+#[tokio::test]
+async fn read_and_forward_failing_spool_returns_error() {
+    // arrange
+    let mut input = MockMyTrackResultInput::default();
+    // the controller has to send the report again, it was not kept
+    input.expect_confirm().never();
+    input
+        .expect_read_next_report()
+        .once()
+        .returning(|| Ok(Some(activity(BOOT_ID, 0))));
+
+    let mut output = MockMyReportMessaging::default();
+    output.expect_send().never();
+
+    let spool = MemorySpool {
+        reports: VecDeque::new(),
+        failing: true,
+        rejected: Vec::new(),
+    };
+    let mut app_service = ApplicationService::new(input, spool, output);
+
+    // act
+    let actual = app_service.read_and_forward().await;
+
+    // assert -> + mockall
+    assert!(matches!(actual, Err(Error::FailedSpoolingReport { .. })));
+}
+
+#[tokio::test]
+async fn read_and_forward_report_without_finish_gets_time_of_arrival() {
+    // arrange
+    let mut input = MockMyTrackResultInput::default();
+    input.expect_confirm().return_const(());
+    input
+        .expect_read_next_report()
+        .once()
+        .returning(|| Ok(Some(activity(BOOT_ID, 0))));
+
+    let mut output = MockMyReportMessaging::default();
+    output
+        .expect_send()
+        .once()
+        .withf(|_, result| result.finished_at().is_some())
+        .returning(|_, _| Ok(()));
+
+    let mut app_service = ApplicationService::new(input, MemorySpool::default(), output);
+
+    // act
+    let _ = app_service.read_and_forward().await;
+
+    // assert -> mockall
+}
+
+#[tokio::test]
+async fn read_and_forward_having_device_status_is_send_as_status() {
+    // arrange
+    let mut input = MockMyTrackResultInput::default();
+    input.expect_read_next_report().once().returning(|| {
+        Ok(Some(Report::Status(DeviceStatus {
+            serial_number: SERIAL_NUMBER,
+            last_seen: SystemTime::now(),
+            online: false,
+            mode: DeviceMode::Running,
+            uptime: 120,
+            unacknowledged: 1,
+        })))
+    });
+
+    let mut output = MockMyReportMessaging::default();
+    output.expect_send().never();
+    output
+        .expect_send_device_status()
+        .once()
+        .withf(|status| status.serial_number == SERIAL_NUMBER && !status.online)
+        .returning(|_| Ok(()));
+
+    let mut app_service = ApplicationService::new(input, MemorySpool::default(), output);
+
+    // act
+    let _ = app_service.read_and_forward().await;
+
+    // assert -> mockall
+}
@@ -0,0 +1,839 @@
+use crate::app_service::{BATCH_SIZE, INITIAL_BACKOFF, MAX_BACKOFF};
+use crate::infrastructure::devices::{DeviceSelection, MICROBIT_PID, MICROBIT_VID};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use snafu::{ResultExt, Snafu};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// most reports keret-service takes in a single batch
+const MAX_BATCH_SIZE: usize = 1000;
+
+#[derive(Debug, Snafu)]
+pub(crate) enum ConfigError {
+    #[snafu(display("Could not read the configuration file {path}"))]
+    CouldNotReadConfig {
+        path: String,
+        source: std::io::Error,
+    },
+    #[snafu(display("Could not parse the configuration file {path}"))]
+    CouldNotParseConfig {
+        path: String,
+        source: toml::de::Error,
+    },
+    #[snafu(display("Invalid configuration of `{field}`: {reason}"))]
+    InvalidConfig { field: String, reason: String },
+    /// the key itself is not shown, it is a secret
+    #[snafu(display("The API key sent in the {header} header is not a valid header value"))]
+    InvalidApiKey {
+        header: String,
+        source: reqwest::header::InvalidHeaderValue,
+    },
+}
+
+/// settings of the adapter, read from a TOML file
+/// every section and value is optional, missing ones fall back to the defaults.
+/// unknown keys are rejected, so a typo does not silently leave the default in place
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Config {
+    pub(crate) devices: DevicesConfig,
+    pub(crate) service: ServiceConfig,
+    pub(crate) retry: RetryConfig,
+    pub(crate) spool: SpoolConfig,
+    pub(crate) metrics: MetricsConfig,
+    /// zone the times are shown in, as IANA name (e.g. `Europe/Berlin`)
+    pub(crate) timezone: Tz,
+    /// destinations every report is forwarded to
+    pub(crate) sinks: Vec<SinkConfig>,
+}
+
+/// which controllers are listened to
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct DevicesConfig {
+    /// serial ports of the controllers, found by USB id if empty
+    pub(crate) ports: Vec<String>,
+    /// USB vendor id the controllers are found by
+    pub(crate) usb_vendor_id: u16,
+    /// USB product id the controllers are found by
+    pub(crate) usb_product_id: u16,
+}
+
+/// the keret-service the reports are posted to
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ServiceConfig {
+    /// full URL of the activity report endpoint, the other endpoints are next to it
+    pub(crate) url: Option<String>,
+    pub(crate) auth: Option<Auth>,
+    /// PEM file of a certificate authority trusted in addition to the well-known ones
+    pub(crate) ca_certificate: Option<PathBuf>,
+    /// PEM file of the certificate the adapter authenticates with (mTLS)
+    pub(crate) client_certificate: Option<PathBuf>,
+    /// PEM file of the private key of the client certificate
+    pub(crate) client_key: Option<PathBuf>,
+    /// longest wait for the connection to be established, e.g. `10s`
+    #[serde(with = "humantime_serde")]
+    pub(crate) connect_timeout: Duration,
+    /// longest wait for a request to be answered, including connecting, e.g. `30s`
+    #[serde(with = "humantime_serde")]
+    pub(crate) timeout: Duration,
+    /// most spooled activity reports posted at once, one at a time if 1
+    pub(crate) batch_size: usize,
+}
+
+/// credentials sent along with every request to keret-service
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) enum Auth {
+    /// `Authorization: Bearer <token>`
+    Bearer { token: String },
+    /// the key in a header of its own
+    ApiKey {
+        #[serde(default = "default_api_key_header")]
+        header: String,
+        key: String,
+    },
+}
+
+/// how long forwarding is paused after a failure
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct RetryConfig {
+    /// wait after the first failure, e.g. `1s`
+    #[serde(with = "humantime_serde")]
+    pub(crate) initial_backoff: Duration,
+    /// longest wait, the backoff doubles with every failure up to this, e.g. `5m`
+    #[serde(with = "humantime_serde")]
+    pub(crate) max_backoff: Duration,
+}
+
+/// where the reports are kept until they were forwarded
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct SpoolConfig {
+    pub(crate) path: PathBuf,
+}
+
+/// where the metrics are written to in prometheus text format, not written if missing
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct MetricsConfig {
+    pub(crate) file: Option<PathBuf>,
+}
+
+/// a destination the reports are forwarded to
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub(crate) struct SinkConfig {
+    #[serde(flatten)]
+    pub(crate) kind: SinkKind,
+    #[serde(default)]
+    pub(crate) on_failure: OnFailure,
+}
+
+/// the kind of destination, with its settings
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) enum SinkKind {
+    /// post to keret-service, as configured in the `service` section
+    Service,
+    /// append to a local file
+    File {
+        path: PathBuf,
+        #[serde(default)]
+        format: RecordFormat,
+    },
+    /// print to the standard output
+    Stdout {
+        #[serde(default)]
+        format: RecordFormat,
+    },
+    /// publish to an MQTT broker
+    Mqtt(Box<MqttConfig>),
+    /// run a program, passing the report as environment variables and as JSON on stdin
+    Command {
+        program: String,
+        #[serde(default)]
+        args: Vec<String>,
+        /// the program is killed if it takes longer, e.g. `30s`
+        #[serde(default = "default_command_timeout", with = "humantime_serde")]
+        timeout: Duration,
+    },
+}
+
+/// how the reports are written to files and the standard output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RecordFormat {
+    /// one JSON object per line
+    #[default]
+    Jsonl,
+    /// comma separated values, with a header line
+    Csv,
+}
+
+/// what happens to a report the sink failed to take
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum OnFailure {
+    /// keep the report in the spool, retrying this sink until it succeeds
+    #[default]
+    Retry,
+    /// log the failure and drop the report for this sink
+    Skip,
+}
+
+/// the MQTT broker the reports are published to
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct MqttConfig {
+    /// host name or address of the broker
+    pub(crate) host: String,
+    #[serde(default = "default_mqtt_port")]
+    pub(crate) port: u16,
+    #[serde(default = "default_mqtt_client_id")]
+    pub(crate) client_id: String,
+    pub(crate) username: Option<String>,
+    pub(crate) password: Option<String>,
+    /// replaces `{prefix}` in the topics
+    #[serde(default = "default_mqtt_topic_prefix")]
+    pub(crate) topic_prefix: String,
+    #[serde(default)]
+    pub(crate) topics: MqttTopics,
+    /// whether the controllers are announced to Home Assistant
+    #[serde(default = "default_mqtt_discovery")]
+    pub(crate) discovery: bool,
+    /// prefix of the discovery topics, as configured in Home Assistant
+    #[serde(default = "default_mqtt_discovery_prefix")]
+    pub(crate) discovery_prefix: String,
+}
+
+/// topics published to, `{prefix}` is replaced by the topic prefix and `{device}` by the
+/// serial number of the controller
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct MqttTopics {
+    /// every activity report
+    pub(crate) activity: String,
+    /// every crash report
+    pub(crate) crash: String,
+    /// identity of the controller, retained
+    pub(crate) identity: String,
+    /// mode of the controller and whether it is online, retained
+    pub(crate) state: String,
+    /// every heartbeat, with the uptime of the controller
+    pub(crate) heartbeat: String,
+    /// `online` or `offline` for the controller, retained
+    pub(crate) availability: String,
+    /// `online` or `offline` for the adapter itself, retained and set by the broker once the
+    /// adapter is gone
+    pub(crate) adapter_availability: String,
+}
+
+impl Default for MqttTopics {
+    fn default() -> Self {
+        Self {
+            activity: "{prefix}/{device}/activity".to_string(),
+            crash: "{prefix}/{device}/crash".to_string(),
+            identity: "{prefix}/{device}/identity".to_string(),
+            state: "{prefix}/{device}/state".to_string(),
+            heartbeat: "{prefix}/{device}/heartbeat".to_string(),
+            availability: "{prefix}/{device}/availability".to_string(),
+            adapter_availability: "{prefix}/adapter/availability".to_string(),
+        }
+    }
+}
+
+impl MqttTopics {
+    /// the topics naming the device, each one with its key
+    pub(crate) fn of_devices(&self) -> [(&'static str, &str); 6] {
+        [
+            ("activity", &self.activity),
+            ("crash", &self.crash),
+            ("identity", &self.identity),
+            ("state", &self.state),
+            ("heartbeat", &self.heartbeat),
+            ("availability", &self.availability),
+        ]
+    }
+}
+
+fn default_api_key_header() -> String {
+    "X-API-Key".to_string()
+}
+
+fn default_command_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_mqtt_client_id() -> String {
+    "keret-adapter".to_string()
+}
+
+fn default_mqtt_topic_prefix() -> String {
+    "keret".to_string()
+}
+
+fn default_mqtt_discovery() -> bool {
+    true
+}
+
+fn default_mqtt_discovery_prefix() -> String {
+    "homeassistant".to_string()
+}
+
+impl SinkConfig {
+    /// the sink forwarding to keret-service, retrying on failure
+    pub(crate) fn service() -> Self {
+        Self {
+            kind: SinkKind::Service,
+            on_failure: OnFailure::Retry,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            devices: DevicesConfig::default(),
+            service: ServiceConfig::default(),
+            retry: RetryConfig::default(),
+            spool: SpoolConfig::default(),
+            metrics: MetricsConfig::default(),
+            timezone: Tz::UTC,
+            sinks: vec![SinkConfig::service()],
+        }
+    }
+}
+
+impl Default for DevicesConfig {
+    fn default() -> Self {
+        Self {
+            ports: Vec::new(),
+            usb_vendor_id: MICROBIT_VID,
+            usb_product_id: MICROBIT_PID,
+        }
+    }
+}
+
+impl Default for ServiceConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            auth: None,
+            ca_certificate: None,
+            client_certificate: None,
+            client_key: None,
+            connect_timeout: Duration::from_secs(10),
+            timeout: Duration::from_secs(30),
+            batch_size: BATCH_SIZE,
+        }
+    }
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            initial_backoff: INITIAL_BACKOFF,
+            max_backoff: MAX_BACKOFF,
+        }
+    }
+}
+
+impl Default for SpoolConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("keret-adapter.spool"),
+        }
+    }
+}
+
+impl Config {
+    /// read the configuration file, not validated yet as it may still be overridden
+    pub(crate) fn load(path: &Path) -> Result<Self, ConfigError> {
+        let content = std::fs::read_to_string(path).context(CouldNotReadConfigSnafu {
+            path: path.display().to_string(),
+        })?;
+
+        Self::parse(&content).context(CouldNotParseConfigSnafu {
+            path: path.display().to_string(),
+        })
+    }
+
+    fn parse(content: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(content)
+    }
+
+    /// check the settings fit together, naming the first one that does not
+    pub(crate) fn validate(&self) -> Result<(), ConfigError> {
+        if self.sinks.is_empty() {
+            return invalid(
+                "sinks",
+                "at least one sink is needed to forward the reports to",
+            );
+        }
+
+        if self.uses_service() {
+            let Some(url) = &self.service.url else {
+                return invalid(
+                    "service.url",
+                    "missing, give it in the configuration file, as argument or in KERET_ADAPTER_URL",
+                );
+            };
+            match url::Url::parse(url) {
+                Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => {}
+                Ok(parsed) => {
+                    return invalid(
+                        "service.url",
+                        format!(
+                            "scheme {} is not supported, use http or https",
+                            parsed.scheme()
+                        ),
+                    )
+                }
+                Err(e) => return invalid("service.url", format!("{} is not a URL: {}", url, e)),
+            }
+        }
+
+        match &self.service.auth {
+            Some(Auth::Bearer { token }) if token.is_empty() => {
+                return invalid("service.auth.token", "must not be empty");
+            }
+            Some(Auth::ApiKey { header, .. })
+                if reqwest::header::HeaderName::from_bytes(header.as_bytes()).is_err() =>
+            {
+                return invalid(
+                    "service.auth.header",
+                    format!("{} is not a valid header name", header),
+                );
+            }
+            Some(Auth::ApiKey { key, .. }) if key.is_empty() => {
+                return invalid("service.auth.key", "must not be empty");
+            }
+            Some(Auth::ApiKey { header, key }) => {
+                reqwest::header::HeaderValue::from_str(key).context(InvalidApiKeySnafu {
+                    header: header.clone(),
+                })?;
+            }
+            _ => {}
+        }
+
+        match (&self.service.client_certificate, &self.service.client_key) {
+            (Some(_), None) => {
+                return invalid(
+                    "service.client_key",
+                    "missing, the client certificate needs its private key",
+                );
+            }
+            (None, Some(_)) => {
+                return invalid(
+                    "service.client_certificate",
+                    "missing, the private key needs its client certificate",
+                );
+            }
+            _ => {}
+        }
+        if self.service.connect_timeout.is_zero() {
+            return invalid("service.connect_timeout", "must be longer than 0s");
+        }
+        if self.service.timeout.is_zero() {
+            return invalid("service.timeout", "must be longer than 0s");
+        }
+        if !(1..=MAX_BATCH_SIZE).contains(&self.service.batch_size) {
+            return invalid(
+                "service.batch_size",
+                format!("must be between 1 and {}", MAX_BATCH_SIZE),
+            );
+        }
+
+        if self.retry.initial_backoff.is_zero() {
+            return invalid("retry.initial_backoff", "must be longer than 0s");
+        }
+        if self.retry.max_backoff < self.retry.initial_backoff {
+            return invalid(
+                "retry.max_backoff",
+                "must not be shorter than retry.initial_backoff",
+            );
+        }
+
+        if self.devices.ports.iter().any(String::is_empty) {
+            return invalid("devices.ports", "must not contain an empty path");
+        }
+
+        for sink in &self.sinks {
+            match &sink.kind {
+                SinkKind::File { path, .. } if path.as_os_str().is_empty() => {
+                    return invalid("sinks.path", "must not be empty");
+                }
+                SinkKind::Command { program, .. } if program.is_empty() => {
+                    return invalid("sinks.program", "must not be empty");
+                }
+                SinkKind::Command { timeout, .. } if timeout.is_zero() => {
+                    return invalid("sinks.timeout", "must be longer than 0s");
+                }
+                SinkKind::Mqtt(mqtt) if mqtt.host.is_empty() => {
+                    return invalid("sinks.host", "must not be empty");
+                }
+                SinkKind::Mqtt(mqtt) if mqtt.topic_prefix.is_empty() => {
+                    return invalid("sinks.topic_prefix", "must not be empty");
+                }
+                SinkKind::Mqtt(mqtt) if mqtt.password.is_some() && mqtt.username.is_none() => {
+                    return invalid("sinks.password", "needs a username as well");
+                }
+                SinkKind::Mqtt(mqtt) => {
+                    for (key, topic) in mqtt.topics.of_devices() {
+                        if !topic.contains("{device}") {
+                            return invalid(
+                                format!("sinks.topics.{}", key),
+                                format!("{} must contain {{device}}", topic),
+                            );
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// whether the reports are posted to keret-service
+    pub(crate) fn uses_service(&self) -> bool {
+        self.sinks
+            .iter()
+            .any(|sink| matches!(sink.kind, SinkKind::Service))
+    }
+
+    /// the controllers to listen to
+    pub(crate) fn device_selection(&self) -> DeviceSelection {
+        if self.devices.ports.is_empty() {
+            DeviceSelection::Usb {
+                vid: self.devices.usb_vendor_id,
+                pid: self.devices.usb_product_id,
+            }
+        } else {
+            DeviceSelection::Ports(self.devices.ports.clone())
+        }
+    }
+}
+
+/// parse the name of a timezone, as given on the command line
+pub(crate) fn parse_timezone(name: &str) -> Result<Tz, ConfigError> {
+    name.parse().map_err(|_| ConfigError::InvalidConfig {
+        field: "timezone".to_string(),
+        reason: format!("{} is not a known IANA timezone (e.g. Europe/Berlin)", name),
+    })
+}
+
+fn invalid<T>(field: impl Into<String>, reason: impl Into<String>) -> Result<T, ConfigError> {
+    InvalidConfigSnafu {
+        field: field.into(),
+        reason: reason.into(),
+    }
+    .fail()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const EXAMPLE: &str = include_str!("../keret-adapter.example.toml");
+
+    fn valid() -> Config {
+        Config {
+            service: ServiceConfig {
+                url: Some("http://localhost:3000/api/v1.0/report".to_string()),
+                ..ServiceConfig::default()
+            },
+            ..Config::default()
+        }
+    }
+
+    fn invalid_field(config: &Config) -> Option<String> {
+        match config.validate() {
+            Err(ConfigError::InvalidConfig { field, .. }) => Some(field),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn parse_example_is_valid() {
+        // act
+        let actual = Config::parse(EXAMPLE).unwrap();
+
+        // assert
+        assert!(actual.validate().is_ok());
+        assert_eq!(actual.timezone, Tz::Europe__Berlin);
+        assert_eq!(actual.retry.max_backoff, Duration::from_secs(600));
+        assert!(matches!(actual.service.auth, Some(Auth::Bearer { .. })));
+    }
+
+    #[test]
+    fn parse_empty_file_keeps_defaults() {
+        // act
+        let actual = Config::parse("").unwrap();
+
+        // assert
+        assert_eq!(actual.spool.path, PathBuf::from("keret-adapter.spool"));
+        assert_eq!(actual.retry.initial_backoff, INITIAL_BACKOFF);
+        assert_eq!(actual.sinks, vec![SinkConfig::service()]);
+        assert!(matches!(
+            actual.device_selection(),
+            DeviceSelection::Usb {
+                vid: MICROBIT_VID,
+                pid: MICROBIT_PID
+            }
+        ));
+    }
+
+    #[test]
+    fn parse_api_key_without_header_uses_default_header() {
+        // act
+        let actual =
+            Config::parse("[service.auth]\ntype = \"api_key\"\nkey = \"secret\"\n").unwrap();
+
+        // assert
+        match actual.service.auth {
+            Some(Auth::ApiKey { header, key }) => {
+                assert_eq!(header, "X-API-Key");
+                assert_eq!(key, "secret");
+            }
+            other => panic!("unexpected auth {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_unknown_key_fails() {
+        // act
+        let actual = Config::parse("[spool]\nfile = \"reports.spool\"\n");
+
+        // assert
+        assert!(actual
+            .unwrap_err()
+            .to_string()
+            .contains("unknown field `file`"));
+    }
+
+    #[test]
+    fn parse_sinks_keeps_order_and_failure_handling() {
+        // arrange
+        let content = r#"
+            [[sinks]]
+            type = "service"
+
+            [[sinks]]
+            type = "file"
+            path = "reports.csv"
+            format = "csv"
+            on_failure = "skip"
+
+            [[sinks]]
+            type = "command"
+            program = "notify-send"
+            args = ["keret"]
+        "#;
+
+        // act
+        let actual = Config::parse(content).unwrap();
+
+        // assert
+        assert_eq!(actual.sinks.len(), 3);
+        assert_eq!(actual.sinks[0], SinkConfig::service());
+        assert_eq!(
+            actual.sinks[1],
+            SinkConfig {
+                kind: SinkKind::File {
+                    path: PathBuf::from("reports.csv"),
+                    format: RecordFormat::Csv
+                },
+                on_failure: OnFailure::Skip
+            }
+        );
+        assert!(matches!(
+            &actual.sinks[2].kind,
+            SinkKind::Command { timeout, .. } if *timeout == Duration::from_secs(30)
+        ));
+    }
+
+    #[test]
+    fn parse_sink_with_unknown_key_fails() {
+        // act
+        let actual = Config::parse("[[sinks]]\ntype = \"stdout\"\npath = \"x\"\n");
+
+        // assert
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn validate_without_service_sink_needs_no_url() {
+        // arrange
+        let config = Config {
+            sinks: vec![SinkConfig {
+                kind: SinkKind::Stdout {
+                    format: RecordFormat::Jsonl,
+                },
+                on_failure: OnFailure::Retry,
+            }],
+            ..Config::default()
+        };
+
+        // act
+        let actual = config.validate();
+
+        // assert
+        assert!(actual.is_ok());
+    }
+
+    #[test]
+    fn validate_mqtt_topic_without_device_names_topic() {
+        // arrange
+        let mut config = valid();
+        let mut mqtt = Config::parse("[[sinks]]\ntype = \"mqtt\"\nhost = \"localhost\"\n")
+            .unwrap()
+            .sinks
+            .remove(0);
+        if let SinkKind::Mqtt(settings) = &mut mqtt.kind {
+            settings.topics.state = "{prefix}/state".to_string();
+        }
+        config.sinks.push(mqtt);
+
+        // act
+        let actual = invalid_field(&config);
+
+        // assert
+        assert_eq!(actual.as_deref(), Some("sinks.topics.state"));
+    }
+
+    #[test]
+    fn parse_unknown_timezone_fails() {
+        // act
+        let actual = Config::parse("timezone = \"Europe/Atlantis\"\n");
+
+        // assert
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn validate_without_url_names_url() {
+        // arrange
+        let config = Config::default();
+
+        // act
+        let actual = invalid_field(&config);
+
+        // assert
+        assert_eq!(actual.as_deref(), Some("service.url"));
+    }
+
+    #[test]
+    fn validate_unsupported_scheme_names_url() {
+        // arrange
+        let mut config = valid();
+        config.service.url = Some("ftp://localhost/report".to_string());
+
+        // act
+        let actual = invalid_field(&config);
+
+        // assert
+        assert_eq!(actual.as_deref(), Some("service.url"));
+    }
+
+    #[test]
+    fn validate_max_backoff_below_initial_names_max_backoff() {
+        // arrange
+        let mut config = valid();
+        config.retry.initial_backoff = Duration::from_secs(10);
+        config.retry.max_backoff = Duration::from_secs(5);
+
+        // act
+        let actual = invalid_field(&config);
+
+        // assert
+        assert_eq!(actual.as_deref(), Some("retry.max_backoff"));
+    }
+
+    #[test]
+    fn validate_invalid_header_names_header() {
+        // arrange
+        let mut config = valid();
+        config.service.auth = Some(Auth::ApiKey {
+            header: "X API Key".to_string(),
+            key: "secret".to_string(),
+        });
+
+        // act
+        let actual = invalid_field(&config);
+
+        // assert
+        assert_eq!(actual.as_deref(), Some("service.auth.header"));
+    }
+
+    #[test]
+    fn validate_api_key_with_line_break_is_rejected() {
+        // arrange
+        let mut config = valid();
+        config.service.auth = Some(Auth::ApiKey {
+            header: "X-Api-Key".to_string(),
+            key: "secret\n".to_string(),
+        });
+
+        // act
+        let actual = config.validate();
+
+        // assert
+        assert!(matches!(actual, Err(ConfigError::InvalidApiKey { .. })));
+    }
+
+    #[test]
+    fn validate_client_certificate_without_key_names_key() {
+        // arrange
+        let mut config = valid();
+        config.service.client_certificate = Some(PathBuf::from("adapter.pem"));
+
+        // act
+        let actual = invalid_field(&config);
+
+        // assert
+        assert_eq!(actual.as_deref(), Some("service.client_key"));
+    }
+
+    #[test]
+    fn validate_batch_size_above_maximum_names_batch_size() {
+        // arrange
+        let mut config = valid();
+        config.service.batch_size = MAX_BATCH_SIZE + 1;
+
+        // act
+        let actual = invalid_field(&config);
+
+        // assert
+        assert_eq!(actual.as_deref(), Some("service.batch_size"));
+    }
+
+    #[test]
+    fn validate_without_sinks_names_sinks() {
+        // arrange
+        let mut config = valid();
+        config.sinks.clear();
+
+        // act
+        let actual = invalid_field(&config);
+
+        // assert
+        assert_eq!(actual.as_deref(), Some("sinks"));
+    }
+
+    #[test]
+    fn parse_timezone_unknown_name_fails() {
+        // act
+        let actual = parse_timezone("Mars/Olympus_Mons");
+
+        // assert
+        assert!(matches!(actual, Err(ConfigError::InvalidConfig { .. })));
+    }
+}
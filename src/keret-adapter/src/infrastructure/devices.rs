@@ -0,0 +1,203 @@
+use crate::app_service::ports::TrackResultInput;
+use crate::infrastructure::listening::PortListener;
+use crate::model::{Report, ReportOrigin};
+use async_trait::async_trait;
+use serialport::SerialPortType;
+use snafu::Snafu;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, Receiver, Sender, UnboundedSender};
+use tokio::sync::watch;
+
+#[derive(Debug, Snafu)]
+pub(crate) enum DevicesError {
+    #[snafu(display("All controllers stopped being listened to"))]
+    AllListenersStopped,
+}
+
+/// USB vendor id of the micro:bit, as announced by its interface chip (DAPLink)
+pub(crate) const MICROBIT_VID: u16 = 0x0D28;
+
+/// USB product id of the micro:bit, as announced by its interface chip (DAPLink)
+pub(crate) const MICROBIT_PID: u16 = 0x0204;
+
+/// how often the USB devices are scanned for newly plugged in controllers
+const SCAN_INTERVAL: Duration = Duration::from_secs(2);
+
+/// reports of all controllers waiting to be forwarded
+const REPORT_BUFFER: usize = 64;
+
+/// which controllers are listened to
+#[derive(Debug, Clone)]
+pub(crate) enum DeviceSelection {
+    /// the controllers connected to the given ports
+    Ports(Vec<String>),
+    /// all USB devices with the given vendor and product id, as they get plugged in
+    Usb { vid: u16, pid: u16 },
+}
+
+impl std::fmt::Display for DeviceSelection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeviceSelection::Ports(paths) => write!(f, "{}", paths.join(", ")),
+            DeviceSelection::Usb { vid, pid } => write!(f, "USB devices {:04x}:{:04x}", vid, pid),
+        }
+    }
+}
+
+/// a report, and where to confirm it to the listener which received it
+struct Received {
+    report: Report,
+    confirmations: UnboundedSender<ReportOrigin>,
+}
+
+/// reports of all selected controllers, each one listened to in a task of its own
+/// a listener keeps reconnecting to its port, so controllers may be unplugged and plugged in
+/// again at any time. New controllers are picked up while running, if selected by USB id
+pub(crate) struct DeviceReports {
+    receiver: Receiver<Received>,
+    /// where to confirm the reports of each controller, by its serial number
+    confirmations: HashMap<u64, UnboundedSender<ReportOrigin>>,
+}
+
+impl DeviceReports {
+    /// start listening to the selected controllers
+    pub(crate) fn start(selection: DeviceSelection) -> Self {
+        let (sender, receiver) = mpsc::channel(REPORT_BUFFER);
+        match selection {
+            DeviceSelection::Ports(paths) => {
+                // the selected ports are listened to until the adapter stops
+                let (_, never_stopped) = watch::channel(false);
+                for path in paths {
+                    spawn_listener(path, sender.clone(), never_stopped.clone());
+                }
+            }
+            DeviceSelection::Usb { vid, pid } => {
+                tokio::spawn(discover(vid, pid, sender));
+            }
+        }
+
+        Self {
+            receiver,
+            confirmations: HashMap::new(),
+        }
+    }
+}
+
+/// listen to the controller at the given port until the adapter stops, or it is told to stop
+/// a stopped listener reports its controller offline, if it was online
+fn spawn_listener(path: String, sender: Sender<Received>, stop: watch::Receiver<bool>) {
+    tokio::spawn(async move {
+        let mut listener: PortListener = PortListener::new(path);
+        let (confirmations, mut confirmed) = mpsc::unbounded_channel();
+        loop {
+            if *stop.borrow() {
+                if let Some(report) = listener.stop() {
+                    let _ = sender
+                        .send(Received {
+                            report,
+                            confirmations,
+                        })
+                        .await;
+                }
+                return;
+            }
+            while let Ok(origin) = confirmed.try_recv() {
+                listener.confirm(&origin);
+            }
+            match listener.read_next_report().await {
+                Ok(Some(report)) => {
+                    let received = Received {
+                        report,
+                        confirmations: confirmations.clone(),
+                    };
+                    if sender.send(received).await.is_err() {
+                        // nobody forwards the reports anymore
+                        return;
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => crate::report(&e),
+            }
+        }
+    });
+}
+
+/// start a listener for every controller plugged in, as long as the adapter runs
+/// the listener of a controller unplugged is stopped, a new one starts once it is plugged in again
+async fn discover(vid: u16, pid: u16, sender: Sender<Received>) {
+    // how to stop the listener of each port found
+    let mut known: HashMap<String, watch::Sender<bool>> = HashMap::new();
+    let mut waiting = false;
+    while !sender.is_closed() {
+        // a failed scan is repeated, it is no reason to stop listening
+        if let Ok(ports) = usb_ports(vid, pid) {
+            known.retain(|path, stop| {
+                let present = ports.contains(path);
+                if !present {
+                    eprintln!("[PORT] {}: controller unplugged, no longer listening", path);
+                    let _ = stop.send(true);
+                }
+                present
+            });
+            for path in ports {
+                if let Entry::Vacant(entry) = known.entry(path) {
+                    eprintln!("[PORT] {}: found controller", entry.key());
+                    let (stop, stopped) = watch::channel(false);
+                    spawn_listener(entry.key().clone(), sender.clone(), stopped);
+                    entry.insert(stop);
+                    waiting = false;
+                }
+            }
+        }
+        if known.is_empty() && !waiting {
+            eprintln!("[PORT] waiting for a controller to be plugged in");
+            waiting = true;
+        }
+
+        tokio::time::sleep(SCAN_INTERVAL).await;
+    }
+}
+
+/// paths of the ports of all USB devices with the given vendor and product id
+fn usb_ports(vid: u16, pid: u16) -> Result<Vec<String>, serialport::Error> {
+    let ports = serialport::available_ports()?
+        .into_iter()
+        .filter(|port| match &port.port_type {
+            SerialPortType::UsbPort(usb) => usb.vid == vid && usb.pid == pid,
+            _ => false,
+        })
+        .map(|port| port.port_name)
+        .collect();
+
+    Ok(ports)
+}
+
+#[async_trait]
+impl TrackResultInput for DeviceReports {
+    type Error = DevicesError;
+
+    async fn read_next_report(&mut self) -> Result<Option<Report>, Self::Error> {
+        match self.receiver.recv().await {
+            Some(Received {
+                report,
+                confirmations,
+            }) => {
+                if let Some(origin) = report.origin() {
+                    self.confirmations
+                        .insert(origin.serial_number, confirmations);
+                }
+                Ok(Some(report))
+            }
+            None => AllListenersStoppedSnafu.fail(),
+        }
+    }
+
+    fn confirm(&mut self, origin: &ReportOrigin) {
+        // a listener which stopped does not need to be told, the controller sends it again
+        if let Some(confirmations) = self.confirmations.get(&origin.serial_number) {
+            let _ = confirmations.send(origin.clone());
+        }
+    }
+}
@@ -1,80 +1,507 @@
-use crate::model::TrackResult;
-use keret_controller_transmit::ActionReport;
-use serialport::SerialPort;
+use crate::infrastructure::metrics::record_frame_statistics;
+use crate::model::{
+    CrashReport, DeviceIdentity, DeviceMode, DeviceStatus, Report, ReportOrigin, TrackResult,
+};
+use async_trait::async_trait;
+use chrono::Utc;
+use keret_controller_transmit::{
+    Ack, Command, ControllerMode, FrameDecoder, FrameStatistics, Heartbeat, Message, Transport,
+    PROTOCOL_VERSION,
+};
 use snafu::{ResultExt, Snafu};
+use std::collections::VecDeque;
 use std::io::ErrorKind;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
 
 #[derive(Debug, Snafu)]
 pub(crate) enum ListeningError {
     #[snafu(display("Could not open serial port using {device}"))]
     CouldNotOpenPort {
         device: String,
-        source: serialport::Error,
+        source: tokio_serial::Error,
     },
     #[snafu(display("Could not read data from serial port"))]
     CouldNotReadFromPort { source: std::io::Error },
+    #[snafu(display("Could not write data to serial port"))]
+    CouldNotWriteToPort { source: std::io::Error },
+    #[snafu(display("Could not serialize the message to the controller"))]
+    CouldNotSerializeMessage {
+        source: keret_controller_transmit::Error,
+    },
+    #[snafu(display(
+        "Controller speaks protocol version {version}, but only version {PROTOCOL_VERSION} is supported. Its messages are ignored"
+    ))]
+    IncompatibleProtocol { version: u8 },
     #[snafu(display("Could not deserialize the serial message"))]
     CouldNotDeserializeMessage {
         source: keret_controller_transmit::Error,
     },
 }
 
-pub(crate) struct PortListener {
-    port: Box<dyn SerialPort>,
-    buffer: Vec<u8>,
+/// speed of the serial port of the controller
+const BAUD_RATE: u32 = 115_200;
+
+/// how often it is tried to open the port again, while not connected
+const CONNECT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// how many bytes are read from the serial port at once
+const READ_CHUNK_SIZE: usize = 64;
+
+/// longest wait for received bytes, before the heartbeats and the clock are checked again
+const RECEIVE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// how often the current time is pushed to the controller
+const CLOCK_SYNC_INTERVAL: Duration = Duration::from_secs(60);
+
+/// without a heartbeat for this long the controller is considered offline (3 missed heartbeats)
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// state of the connection to the controller, logged whenever it changes
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PortState {
+    /// the port could not be opened
+    Unavailable,
+    Connected,
+    /// the port was lost, e.g. as the controller was unplugged
+    Disconnected,
 }
 
-impl PortListener {
-    pub(crate) fn new(path: &str) -> Result<Self, ListeningError> {
-        let port = serialport::new(path, 115_200)
-            .timeout(Duration::from_millis(10))
-            .open()
-            .context(CouldNotOpenPortSnafu {
-                device: path.to_string(),
-            })?;
+impl std::fmt::Display for PortState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PortState::Unavailable => write!(f, "could not open the port, retrying"),
+            PortState::Connected => write!(f, "connected"),
+            PortState::Disconnected => write!(f, "lost connection"),
+        }
+    }
+}
 
-        let buffer = Vec::<u8>::new();
+/// connection to a controller, opened again whenever it was lost
+pub(crate) trait Port: AsyncRead + AsyncWrite + Unpin + Send + Sized {
+    fn open(path: &str) -> Result<Self, tokio_serial::Error>;
+}
 
-        Ok(Self { port, buffer })
+impl Port for SerialStream {
+    fn open(path: &str) -> Result<Self, tokio_serial::Error> {
+        tokio_serial::new(path, BAUD_RATE).open_native_async()
     }
 }
 
-impl crate::app_service::ports::TrackResultInput for PortListener {
-    type Error = ListeningError;
+/// listens to the controller connected to a single serial port
+pub(crate) struct PortListener<P: Port = SerialStream> {
+    path: String,
+    /// the open port, while connected
+    port: Option<P>,
+    state: Option<PortState>,
+    /// when it was last tried to connect
+    last_connect: Option<Instant>,
+    decoder: FrameDecoder,
+    /// frames already decoded, but not yet handed out
+    pending: VecDeque<Result<Message, keret_controller_transmit::Error>>,
+    /// statistics of the decoder when they were last recorded
+    statistics: FrameStatistics,
+    /// serial number of the connected controller, once it identified itself
+    device: Option<u64>,
+    /// serial number taken until the controller identified itself
+    /// none while listening, the reports are held back until then
+    assumed_device: Option<u64>,
+    /// boot of the connected controller, as of its last report or boot announcement
+    boot_id: Option<u32>,
+    /// protocol version of the connected controller, once refused as incompatible
+    incompatible: Option<u8>,
+    /// reports kept by the adapter, still to be acknowledged
+    confirmed: VecDeque<ReportOrigin>,
+    /// when the current time was last pushed to the controller
+    last_clock_sync: Option<Instant>,
+    /// status of the controller, as of its last heartbeat
+    status: Option<DeviceStatus>,
+    /// when the last heartbeat was received
+    last_heartbeat: Option<Instant>,
+}
 
-    fn read_next_report(&mut self) -> Result<Option<TrackResult>, Self::Error> {
-        let mut read_buffer: Vec<u8> = vec![0; 10];
+impl<P: Port> PortListener<P> {
+    /// create the listener, which connects once the port can be opened
+    pub(crate) fn new(path: String) -> Self {
+        Self {
+            path,
+            port: None,
+            state: None,
+            last_connect: None,
+            decoder: FrameDecoder::new(),
+            pending: VecDeque::new(),
+            statistics: FrameStatistics::default(),
+            device: None,
+            assumed_device: None,
+            boot_id: None,
+            incompatible: None,
+            confirmed: VecDeque::new(),
+            last_clock_sync: None,
+            status: None,
+            last_heartbeat: None,
+        }
+    }
 
-        match self.port.read(&mut read_buffer) {
-            Ok(length) => {
-                self.buffer.extend_from_slice(&read_buffer[..length]);
-            }
-            Err(e) => match e.kind() {
-                ErrorKind::TimedOut => {
-                    return Ok(None);
-                }
-                _ => {
-                    return Err(ListeningError::CouldNotReadFromPort { source: e });
+    /// take the reports received before the controller identified itself as the ones of the
+    /// given serial number, e.g. in a capture the controller never identified itself in
+    pub(crate) fn assuming_device(mut self, serial_number: u64) -> Self {
+        self.assumed_device = Some(serial_number);
+        self
+    }
+
+    /// identifies a report of the connected controller, which repeats it until acknowledged
+    /// none before the controller identified itself: the report is not confirmed then,
+    /// so it arrives again once the controller answered the request to identify itself.
+    /// keyed by the port, the report could not be told apart from its resend
+    async fn report_origin(
+        &mut self,
+        boot_id: u32,
+        sequence: u32,
+    ) -> Result<Option<ReportOrigin>, ListeningError> {
+        let Some(serial_number) = self.device.or(self.assumed_device) else {
+            eprintln!(
+                "[DEVICE] {}: report #{} received before the controller identified itself, waiting for it again",
+                self.path, sequence
+            );
+            self.send(Message::Command(Command::Identify)).await?;
+            return Ok(None);
+        };
+        self.boot_id = Some(boot_id);
+
+        Ok(Some(ReportOrigin {
+            serial_number,
+            boot_id,
+            sequence,
+        }))
+    }
+
+    /// log the state of the connection, if it changed
+    fn set_state(&mut self, state: PortState) {
+        if self.state != Some(state) {
+            eprintln!("[PORT] {}: {}", self.path, state);
+            self.state = Some(state);
+        }
+    }
+
+    /// open the port, unless connected already
+    /// waits until it was not tried for a while, so a missing port is not tried over and over
+    async fn connect(&mut self) -> Result<(), ListeningError> {
+        if self.port.is_some() {
+            return Ok(());
+        }
+        if let Some(last) = self.last_connect {
+            tokio::time::sleep(CONNECT_INTERVAL.saturating_sub(last.elapsed())).await;
+        }
+        self.last_connect = Some(Instant::now());
+
+        let port = match P::open(&self.path) {
+            Ok(port) => port,
+            Err(e) => {
+                let first_failure = self.state != Some(PortState::Unavailable);
+                self.set_state(PortState::Unavailable);
+                // report the reason once, not on every retry
+                if !first_failure {
+                    return Ok(());
                 }
+                return Err(e).context(CouldNotOpenPortSnafu {
+                    device: self.path.clone(),
+                });
+            }
+        };
+
+        self.port = Some(port);
+        // the new decoder counts from zero, so the progress is measured against zero as well
+        self.decoder = FrameDecoder::new();
+        self.statistics = FrameStatistics::default();
+        self.pending.clear();
+        self.set_state(PortState::Connected);
+
+        // a controller booted before the adapter connected has already sent its hello
+        self.send(Message::Command(Command::Identify)).await
+    }
+
+    /// close the port after it failed, it is reopened once available again
+    fn disconnect(&mut self) {
+        if self.port.take().is_none() {
+            return;
+        }
+        self.set_state(PortState::Disconnected);
+
+        // another controller may show up on the port, which has to identify itself first
+        self.device = None;
+        self.boot_id = None;
+        self.incompatible = None;
+        self.confirmed.clear();
+        self.last_clock_sync = None;
+        // report the controller offline right away, instead of waiting for the heartbeat
+        self.last_heartbeat = None;
+    }
+
+    /// send a message to the controller, using the transport the controller uses
+    async fn send(&mut self, message: Message) -> Result<(), ListeningError> {
+        let transport = self.decoder.transport().unwrap_or(Transport::Binary);
+        let frame = message
+            .as_frame(transport)
+            .context(CouldNotSerializeMessageSnafu)?;
+        let Some(port) = self.port.as_mut() else {
+            // nobody to tell, the controller is asked again once connected
+            return Ok(());
+        };
+
+        let written = port.write_all(&frame).await;
+        if written.is_err() {
+            self.disconnect();
+        }
+        written.context(CouldNotWriteToPortSnafu)
+    }
+
+    /// acknowledge the reports kept by the adapter, the controller stops sending them again
+    async fn acknowledge(&mut self) -> Result<(), ListeningError> {
+        while let Some(origin) = self.confirmed.pop_front() {
+            // the acknowledgement only names the sequence, which starts over on every boot
+            if self.device == Some(origin.serial_number) && self.boot_id == Some(origin.boot_id) {
+                self.send(Message::Ack(Ack::new(origin.sequence))).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// push the current time to the controller, unless it was done recently
+    async fn synchronize_clock(&mut self) -> Result<(), ListeningError> {
+        if self.port.is_none()
+            || self
+                .last_clock_sync
+                .is_some_and(|last| last.elapsed() < CLOCK_SYNC_INTERVAL)
+        {
+            return Ok(());
+        }
+
+        let utc = Utc::now().timestamp().max(0) as u64;
+        self.send(Message::Command(Command::SyncTime { utc }))
+            .await?;
+        self.last_clock_sync = Some(Instant::now());
+        Ok(())
+    }
+
+    /// mark the controller offline once its heartbeats stay away, reporting the changed status
+    fn detect_missing_heartbeat(&mut self) -> Option<Report> {
+        let status = self.status.as_mut().filter(|status| status.online)?;
+        if self
+            .last_heartbeat
+            .is_some_and(|last| last.elapsed() < HEARTBEAT_TIMEOUT)
+        {
+            return None;
+        }
+
+        eprintln!(
+            "[DEVICE] {}: No heartbeat for {}s, controller is offline",
+            self.path,
+            HEARTBEAT_TIMEOUT.as_secs()
+        );
+        status.online = false;
+        Some(Report::Status(status.clone()))
+    }
+
+    /// let go of the port for good, as the controller was unplugged
+    /// returns the status reporting the controller offline, if it was online
+    pub(crate) fn stop(&mut self) -> Option<Report> {
+        self.disconnect();
+        let status = self.status.as_mut().filter(|status| status.online)?;
+        status.online = false;
+        Some(Report::Status(status.clone()))
+    }
+
+    /// update the status of the controller with the received heartbeat
+    fn receive_heartbeat(&mut self, heartbeat: Heartbeat) -> Option<Report> {
+        let Some(serial_number) = self.device else {
+            eprintln!(
+                "[HEARTBEAT] {}: {} (not identified yet)",
+                self.path, heartbeat
+            );
+            return None;
+        };
+        if !self.status.as_ref().is_some_and(|status| status.online) {
+            eprintln!("[DEVICE] {}: {}", self.path, heartbeat);
+        }
+
+        let status = DeviceStatus {
+            serial_number,
+            last_seen: SystemTime::now(),
+            online: true,
+            mode: match heartbeat.mode {
+                ControllerMode::Idle => DeviceMode::Idle,
+                ControllerMode::Running { .. } => DeviceMode::Running,
+                ControllerMode::Error => DeviceMode::Error,
             },
+            uptime: heartbeat.uptime,
+            unacknowledged: heartbeat.unacknowledged,
+        };
+        self.last_heartbeat = Some(Instant::now());
+        self.status = Some(status.clone());
+        Some(Report::Status(status))
+    }
+
+    /// wait for bytes from the port and decode all frames completed by them
+    async fn receive(&mut self) -> Result<(), ListeningError> {
+        let mut read_buffer = [0; READ_CHUNK_SIZE];
+        let Some(port) = self.port.as_mut() else {
+            return Ok(());
         };
 
-        if let Some(index) = self.buffer.iter().position(|&x| x == b'\n') {
-            if index == 0 {
-                self.buffer.pop();
-                return Ok(None);
+        let read = match tokio::time::timeout(RECEIVE_TIMEOUT, port.read(&mut read_buffer)).await {
+            Ok(read) => read,
+            // nothing received, but the caller has to check the heartbeats
+            Err(_) => return Ok(()),
+        };
+        match read {
+            Ok(0) => {
+                self.disconnect();
+                Err(ErrorKind::UnexpectedEof.into()).context(CouldNotReadFromPortSnafu)
+            }
+            Ok(length) => {
+                self.pending
+                    .extend(self.decoder.decode(&read_buffer[..length]));
+                self.record_statistics();
+                Ok(())
+            }
+            Err(e) => {
+                self.disconnect();
+                Err(e).context(CouldNotReadFromPortSnafu)
             }
+        }
+    }
+
+    /// publish the frame statistics as metrics, logging them if frames had to be rejected
+    fn record_statistics(&mut self) {
+        let current = self.decoder.statistics();
+        if current == self.statistics {
+            return;
+        }
+
+        if current.corrupted != self.statistics.corrupted
+            || current.resynchronized != self.statistics.resynchronized
+        {
+            eprintln!(
+                "[FRAMES] {}: good: {}, corrupted: {}, resynchronized: {}",
+                self.path, current.good, current.corrupted, current.resynchronized
+            );
+        }
 
-            let incoming_report = ActionReport::from_message(&self.buffer[..index])
-                .context(CouldNotDeserializeMessageSnafu)?;
-            for _ in 0..=index {
-                self.buffer.pop();
+        record_frame_statistics(&self.statistics, &current);
+        self.statistics = current;
+    }
+}
+
+#[async_trait]
+impl<P: Port> crate::app_service::ports::TrackResultInput for PortListener<P> {
+    type Error = ListeningError;
+
+    async fn read_next_report(&mut self) -> Result<Option<Report>, Self::Error> {
+        // a lost controller is reported offline, before it is tried to reconnect
+        if let Some(report) = self.detect_missing_heartbeat() {
+            return Ok(Some(report));
+        }
+        self.connect().await?;
+        self.acknowledge().await?;
+        self.synchronize_clock().await?;
+        if self.pending.is_empty() {
+            self.receive().await?;
+        }
+
+        let Some(incoming_message) = self.pending.pop_front() else {
+            return Ok(None);
+        };
+        let incoming_message = match incoming_message {
+            // refused once per connection, instead of for every message of the controller
+            Err(keret_controller_transmit::Error::UnsupportedVersion { version })
+                if self.incompatible == Some(version) =>
+            {
+                return Ok(None);
             }
+            Err(keret_controller_transmit::Error::UnsupportedVersion { version }) => {
+                self.incompatible = Some(version);
+                return IncompatibleProtocolSnafu { version }.fail();
+            }
+            other => other.context(CouldNotDeserializeMessageSnafu)?,
+        };
 
-            Ok(Some(incoming_report.duration().into()))
-        } else {
-            Ok(None)
+        match incoming_message {
+            Message::ActionReport(report) => {
+                // acknowledged once confirmed, the controller keeps repeating the report until then.
+                // repeated reports are dropped by the app service
+                let Some(origin) = self
+                    .report_origin(report.boot_id(), report.sequence())
+                    .await?
+                else {
+                    return Ok(None);
+                };
+                let result = TrackResult::from(report.duration())
+                    .with_category(report.category())
+                    .with_finished_at(report.finished_at());
+                Ok(Some(Report::Activity(origin, result)))
+            }
+            Message::Diagnostic(diagnostic) => {
+                eprintln!("[DIAGNOSTIC] {}: {}", self.path, diagnostic);
+                Ok(None)
+            }
+            Message::BootAnnouncement(announcement) => {
+                eprintln!("[DIAGNOSTIC] {}: {}", self.path, announcement);
+                self.boot_id = Some(announcement.boot_id);
+                Ok(None)
+            }
+            Message::Hello(hello) => {
+                eprintln!("[DEVICE] {}: {}", self.path, hello);
+                self.device = Some(hello.serial_number);
+                // the controller may have rebooted, losing the time it was told
+                self.last_clock_sync = None;
+                Ok(Some(Report::Identity(DeviceIdentity {
+                    serial_number: hello.serial_number,
+                    firmware_version: hello.firmware_version.to_string(),
+                    // only messages of the supported version are decoded at all
+                    protocol_version: PROTOCOL_VERSION,
+                })))
+            }
+            Message::State(state) => {
+                eprintln!("[DEVICE] {}: {}", self.path, state);
+                Ok(None)
+            }
+            Message::Pong => {
+                eprintln!("[DEVICE] {}: Controller answered ping", self.path);
+                Ok(None)
+            }
+            Message::Heartbeat(heartbeat) => Ok(self.receive_heartbeat(heartbeat)),
+            Message::Ack(_) | Message::Command(_) => {
+                eprintln!(
+                    "[WARN] ignoring message not meant for the adapter: {:?}",
+                    incoming_message
+                );
+                Ok(None)
+            }
+            Message::Log(record) => {
+                eprintln!("[DEVICE] {}: {}", self.path, record);
+                Ok(None)
+            }
+            Message::CrashReport(crash) => {
+                eprintln!("[DIAGNOSTIC] {}: {}", self.path, crash);
+                let Some(origin) = self
+                    .report_origin(crash.boot_id(), crash.sequence())
+                    .await?
+                else {
+                    return Ok(None);
+                };
+                Ok(Some(Report::Crash(CrashReport {
+                    origin,
+                    received_at: SystemTime::now(),
+                    message: crash.message().to_string(),
+                    file: crash.file().to_string(),
+                    line: crash.line(),
+                })))
+            }
         }
     }
+
+    fn confirm(&mut self, origin: &ReportOrigin) {
+        self.confirmed.push_back(origin.clone());
+    }
 }
@@ -0,0 +1,65 @@
+use keret_controller_transmit::FrameStatistics;
+use lazy_static::lazy_static;
+use prometheus::{IntCounter, Opts, Registry};
+use snafu::{ResultExt, Snafu};
+use std::path::Path;
+
+#[derive(Debug, Snafu)]
+pub(crate) enum MetricsError {
+    #[snafu(display("Could not encode the metrics"))]
+    CouldNotEncodeMetrics { source: prometheus::Error },
+    #[snafu(display("Could not write the metrics to {path}"))]
+    CouldNotWriteMetrics {
+        path: String,
+        source: std::io::Error,
+    },
+}
+
+lazy_static! {
+    pub(crate) static ref REGISTRY: Registry = Registry::new();
+    static ref FRAMES_GOOD: IntCounter = frame_counter("good", "frames decoded into a message");
+    static ref FRAMES_CORRUPTED: IntCounter = frame_counter(
+        "corrupted",
+        "frames rejected as malformed, oversized or with wrong checksum"
+    );
+    static ref FRAMES_RESYNCHRONIZED: IntCounter = frame_counter(
+        "resynchronized",
+        "times decoding recovered after rejected frames"
+    );
+}
+
+/// create a counter of received frames and register it
+fn frame_counter(name: &str, help: &str) -> IntCounter {
+    let opts = Opts::new(format!("keret_adapter_frames_{name}_total"), help);
+    let counter = IntCounter::with_opts(opts).expect("metric options are valid");
+    REGISTRY
+        .register(Box::new(counter.clone()))
+        .expect("metric is registered only once");
+    counter
+}
+
+/// update the frame counters with the progress made since the `previous` statistics
+pub(crate) fn record_frame_statistics(previous: &FrameStatistics, current: &FrameStatistics) {
+    FRAMES_GOOD.inc_by(current.good - previous.good);
+    FRAMES_CORRUPTED.inc_by(current.corrupted - previous.corrupted);
+    FRAMES_RESYNCHRONIZED.inc_by(current.resynchronized - previous.resynchronized);
+}
+
+/// write all metrics in the prometheus text format, e.g. for the textfile collector of the
+/// node exporter. The file is replaced atomically, so readers never see partial content
+pub(crate) fn write_metrics(path: &Path) -> Result<(), MetricsError> {
+    use prometheus::Encoder;
+    let encoder = prometheus::TextEncoder::new();
+
+    let mut buffer = Vec::new();
+    encoder
+        .encode(&REGISTRY.gather(), &mut buffer)
+        .context(CouldNotEncodeMetricsSnafu)?;
+
+    let temporary = path.with_extension("tmp");
+    std::fs::write(&temporary, buffer)
+        .and_then(|()| std::fs::rename(&temporary, path))
+        .context(CouldNotWriteMetricsSnafu {
+            path: path.display().to_string(),
+        })
+}
@@ -1,2 +1,7 @@
+pub(crate) mod devices;
 pub(crate) mod listening;
+pub(crate) mod metrics;
+pub(crate) mod monitoring;
 pub(crate) mod sending;
+pub(crate) mod sinks;
+pub(crate) mod spool;
@@ -0,0 +1,549 @@
+use crate::app_service::ports::TrackResultInput;
+use crate::infrastructure::listening::{ListeningError, Port, PortListener};
+use keret_controller_transmit::{FrameDecoder, Message, Transport, MAX_FRAME_SIZE};
+use snafu::{ResultExt, Snafu};
+use std::io::{Cursor, ErrorKind};
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio_serial::SerialStream;
+
+#[derive(Debug, Snafu)]
+#[allow(clippy::enum_variant_names)]
+pub(crate) enum MonitoringError {
+    #[snafu(display("Could not open serial port using {device}"))]
+    CouldNotOpenMonitoredPort {
+        device: String,
+        source: tokio_serial::Error,
+    },
+    #[snafu(display("Could not read data from serial port {device}"))]
+    CouldNotReadMonitoredPort {
+        device: String,
+        source: std::io::Error,
+    },
+    #[snafu(display("Could not write the capture {path}"))]
+    CouldNotWriteCapture {
+        path: String,
+        source: std::io::Error,
+    },
+}
+
+/// how many bytes are read from the serial port at once
+const READ_CHUNK_SIZE: usize = 64;
+
+/// bytes shown per line of a hex dump
+const DUMP_LINE_LENGTH: usize = 16;
+
+/// most bytes of a single frame kept for its hex dump, e.g. when no delimiter arrives
+const MAX_DUMP_SIZE: usize = 2 * MAX_FRAME_SIZE;
+
+/// serial number the reports of a replayed capture are attributed to, until the controller
+/// identified itself in it
+const UNKNOWN_DEVICE: u64 = 0;
+
+/// decodes frames like the listener does, keeping the bytes each frame was received as
+#[derive(Default)]
+struct FrameRecorder {
+    decoder: FrameDecoder,
+    /// bytes of the frame received so far
+    raw: Vec<u8>,
+    /// bytes of the frame not kept, as it grew too long
+    dropped: usize,
+}
+
+/// a frame as received, with its decoded message or the reason it could not be decoded
+struct RecordedFrame {
+    raw: Vec<u8>,
+    dropped: usize,
+    /// transport of the frame, if it was decoded
+    transport: Option<Transport>,
+    message: Result<Message, keret_controller_transmit::Error>,
+}
+
+impl FrameRecorder {
+    /// feed a single byte into the decoder, returning the frame once it is complete
+    fn push(&mut self, byte: u8) -> Option<RecordedFrame> {
+        if self.raw.len() < MAX_DUMP_SIZE {
+            self.raw.push(byte);
+        } else {
+            self.dropped += 1;
+        }
+
+        let message = self.decoder.push(byte)?;
+        Some(RecordedFrame {
+            raw: std::mem::take(&mut self.raw),
+            dropped: std::mem::take(&mut self.dropped),
+            transport: self.decoder.transport(),
+            message,
+        })
+    }
+}
+
+impl std::fmt::Display for RecordedFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} bytes", self.raw.len() + self.dropped)?;
+        if let Some(transport) = self.transport {
+            write!(f, ", {:?}", transport)?;
+        }
+        if let Err(e) = &self.message {
+            write!(f, ", could not be decoded: {}", crate::error_chain(e))?;
+        }
+        writeln!(f)?;
+
+        write!(f, "{}", hex_dump(&self.raw))?;
+        if self.dropped > 0 {
+            writeln!(f, "... {} more bytes", self.dropped)?;
+        }
+        match &self.message {
+            Ok(message) => writeln!(f, "{:#?}", message),
+            Err(_) => Ok(()),
+        }
+    }
+}
+
+/// the bytes as lines of offset, hex values and printable characters
+fn hex_dump(bytes: &[u8]) -> String {
+    let mut dump = String::new();
+    for (line, chunk) in bytes.chunks(DUMP_LINE_LENGTH).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let text: String = chunk
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        dump.push_str(&format!(
+            "{:04x}  {:<width$}  |{}|\n",
+            line * DUMP_LINE_LENGTH,
+            hex.join(" "),
+            text,
+            width = DUMP_LINE_LENGTH * 3 - 1
+        ));
+    }
+    dump
+}
+
+/// print every frame received from the controller at the port, until interrupted
+/// nothing is sent to the controller, so it keeps its reports until the adapter confirms them.
+/// the received bytes are written to the capture file, if given, so they can be replayed
+pub(crate) async fn monitor(device: &str, capture: Option<&Path>) -> Result<(), MonitoringError> {
+    let mut port =
+        <SerialStream as Port>::open(device).context(CouldNotOpenMonitoredPortSnafu { device })?;
+    let mut capture = match capture {
+        Some(path) => Some((
+            File::create(path)
+                .await
+                .context(CouldNotWriteCaptureSnafu {
+                    path: path.display().to_string(),
+                })?,
+            path,
+        )),
+        None => None,
+    };
+    eprintln!(
+        "[MONITOR] {}: printing every frame, stop with Ctrl-C",
+        device
+    );
+
+    let mut recorder = FrameRecorder::default();
+    let mut frames = 0;
+    let mut read_buffer = [0; READ_CHUNK_SIZE];
+    // the capture is flushed however the monitoring ends, its last bytes matter most
+    let ended = loop {
+        let length = tokio::select! {
+            read = port.read(&mut read_buffer) => {
+                match read.context(CouldNotReadMonitoredPortSnafu { device }) {
+                    Ok(length) => length,
+                    Err(e) => break Err(e),
+                }
+            }
+            _ = tokio::signal::ctrl_c() => break Ok(()),
+        };
+        if length == 0 {
+            break Err(ErrorKind::UnexpectedEof.into())
+                .context(CouldNotReadMonitoredPortSnafu { device });
+        }
+
+        if let Some((file, path)) = capture.as_mut() {
+            if let Err(e) = file.write_all(&read_buffer[..length]).await {
+                break Err(e).context(CouldNotWriteCaptureSnafu {
+                    path: path.display().to_string(),
+                });
+            }
+        }
+        for byte in &read_buffer[..length] {
+            if let Some(frame) = recorder.push(*byte) {
+                frames += 1;
+                println!("[FRAME] #{}: {}", frames, frame);
+            }
+        }
+    };
+
+    let flushed = match capture.as_mut() {
+        Some((file, path)) => file.flush().await.context(CouldNotWriteCaptureSnafu {
+            path: path.display().to_string(),
+        }),
+        None => Ok(()),
+    };
+    let statistics = recorder.decoder.statistics();
+    eprintln!(
+        "[FRAMES] {}: good: {}, corrupted: {}, resynchronized: {}",
+        device, statistics.good, statistics.corrupted, statistics.resynchronized
+    );
+    ended.and(flushed)
+}
+
+/// a recorded capture of the bytes received from a controller, read as if it was its port
+/// anything sent to the controller is dropped
+pub(crate) struct CaptureFile {
+    data: Cursor<Vec<u8>>,
+}
+
+impl Port for CaptureFile {
+    fn open(path: &str) -> Result<Self, tokio_serial::Error> {
+        Ok(Self {
+            data: Cursor::new(std::fs::read(path)?),
+        })
+    }
+}
+
+impl AsyncRead for CaptureFile {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.data).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for CaptureFile {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// the listener the adapter uses, reading the capture
+/// the capture may start after the controller identified itself, so its reports are taken
+/// as the ones of an unknown controller until then
+fn replay_listener(capture: &Path) -> PortListener<CaptureFile> {
+    PortListener::new(capture.display().to_string()).assuming_device(UNKNOWN_DEVICE)
+}
+
+/// feed the capture through the listener the adapter uses, printing the reports it yields
+/// nothing is forwarded. Frames which could not be decoded are logged like while listening
+pub(crate) async fn replay(capture: &Path) -> Result<(), ListeningError> {
+    let mut listener = replay_listener(capture);
+    let mut reports = 0;
+    loop {
+        match listener.read_next_report().await {
+            Ok(Some(report)) => {
+                reports += 1;
+                println!("[REPORT] #{}: {:#?}", reports, report);
+            }
+            Ok(None) => {}
+            // the end of the capture was reached
+            Err(ListeningError::CouldNotReadFromPort { source })
+                if source.kind() == ErrorKind::UnexpectedEof =>
+            {
+                break;
+            }
+            Err(e @ ListeningError::CouldNotOpenPort { .. }) => return Err(e),
+            Err(e) => crate::report(&e),
+        }
+    }
+
+    eprintln!("[REPLAY] {}: {} report(s)", capture.display(), reports);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use keret_controller_transmit::{
+        encode_frame, ActionReport, ControllerMode, FirmwareVersion, Heartbeat, Hello,
+        PROTOCOL_VERSION,
+    };
+    use tempfile::tempdir;
+
+    fn report_frame(transport: Transport) -> Vec<u8> {
+        Message::ActionReport(ActionReport::new(0xB007, 3, 2, 60))
+            .as_frame(transport)
+            .unwrap()
+            .to_vec()
+    }
+
+    fn recorded(recorder: &mut FrameRecorder, bytes: &[u8]) -> Vec<RecordedFrame> {
+        bytes
+            .iter()
+            .filter_map(|byte| recorder.push(*byte))
+            .collect()
+    }
+
+    #[test]
+    fn hex_dump_shows_offset_hex_and_text() {
+        // act
+        let actual = hex_dump(b"keret\x00 frames\x01 in hex");
+
+        // assert
+        assert_eq!(
+            actual,
+            "0000  6b 65 72 65 74 00 20 66 72 61 6d 65 73 01 20 69  |keret. frames. i|\n\
+             0010  6e 20 68 65 78                                   |n hex|\n"
+        );
+    }
+
+    #[test]
+    fn push_returns_bytes_of_good_frame() {
+        // arrange
+        let mut recorder = FrameRecorder::default();
+        let frame = report_frame(Transport::Text);
+
+        // act
+        let actual = recorded(&mut recorder, &frame);
+
+        // assert
+        assert_eq!(actual.len(), 1);
+        assert_eq!(actual[0].raw, frame);
+        assert_eq!(actual[0].transport, Some(Transport::Text));
+        assert!(actual[0].message.is_ok());
+    }
+
+    #[test]
+    fn push_returns_bytes_and_error_of_corrupted_frame() {
+        // arrange
+        let mut recorder = FrameRecorder::default();
+        let mut corrupted = report_frame(Transport::Binary);
+        corrupted[3] ^= 0xFF;
+        let good = report_frame(Transport::Binary);
+
+        // act
+        let actual = recorded(&mut recorder, &[corrupted.clone(), good].concat());
+
+        // assert
+        assert_eq!(actual.len(), 2);
+        assert_eq!(actual[0].raw, corrupted);
+        assert!(actual[0].message.is_err());
+        assert!(actual[1].message.is_ok());
+        assert!(actual[0].to_string().contains("could not be decoded"));
+    }
+
+    #[test]
+    fn push_keeps_only_start_of_oversized_frame() {
+        // arrange
+        let mut recorder = FrameRecorder::default();
+        let garbage = vec![0x55; MAX_DUMP_SIZE + 10];
+
+        // act
+        let actual = recorded(&mut recorder, &[garbage, vec![0]].concat());
+
+        // assert
+        assert_eq!(actual.len(), 1);
+        assert_eq!(actual[0].raw.len(), MAX_DUMP_SIZE);
+        assert_eq!(actual[0].dropped, 11);
+    }
+
+    #[tokio::test]
+    async fn replay_listener_yields_reports_of_capture() {
+        // arrange
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("capture");
+        let hello = Message::Hello(Hello::new(
+            FirmwareVersion::new(0, 1, 0),
+            0x0123_4567_89AB_CDEF,
+        ))
+        .as_frame(Transport::Binary)
+        .unwrap();
+        std::fs::write(
+            &path,
+            [&hello[..], &report_frame(Transport::Binary)].concat(),
+        )
+        .unwrap();
+        let mut listener = replay_listener(&path);
+
+        // act
+        let identity = listener.read_next_report().await;
+        let activity = listener.read_next_report().await;
+        let end = listener.read_next_report().await;
+
+        // assert
+        assert!(matches!(
+            identity,
+            Ok(Some(crate::model::Report::Identity(_)))
+        ));
+        match activity {
+            Ok(Some(crate::model::Report::Activity(origin, result))) => {
+                assert_eq!(origin.serial_number, 0x0123_4567_89AB_CDEF);
+                assert_eq!(result.duration(), 60);
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(matches!(
+            end,
+            Err(ListeningError::CouldNotReadFromPort { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn replay_listener_yields_report_before_hello_as_of_unknown_device() {
+        // arrange -> the capture started while the controller was running already
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("capture");
+        let hello = Message::Hello(Hello::new(
+            FirmwareVersion::new(0, 1, 0),
+            0x0123_4567_89AB_CDEF,
+        ))
+        .as_frame(Transport::Binary)
+        .unwrap();
+        std::fs::write(
+            &path,
+            [
+                &report_frame(Transport::Binary),
+                &hello[..],
+                &report_frame(Transport::Binary),
+            ]
+            .concat(),
+        )
+        .unwrap();
+        let mut listener = replay_listener(&path);
+
+        // act
+        let early = listener.read_next_report().await;
+        let identity = listener.read_next_report().await;
+        let identified = listener.read_next_report().await;
+
+        // assert
+        match early {
+            Ok(Some(crate::model::Report::Activity(origin, _))) => {
+                assert_eq!(origin.serial_number, UNKNOWN_DEVICE);
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(matches!(
+            identity,
+            Ok(Some(crate::model::Report::Identity(_)))
+        ));
+        match identified {
+            Ok(Some(crate::model::Report::Activity(origin, _))) => {
+                assert_eq!(origin.serial_number, 0x0123_4567_89AB_CDEF);
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn replay_listener_refuses_incompatible_protocol_once() {
+        // arrange
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("capture");
+        let newer = [PROTOCOL_VERSION + 1, 0, 0];
+        let frame = encode_frame(&newer, Transport::Binary).unwrap();
+        std::fs::write(&path, [&frame[..], &frame[..], &frame[..]].concat()).unwrap();
+        let mut listener = replay_listener(&path);
+
+        // act
+        let first = listener.read_next_report().await;
+        let second = listener.read_next_report().await;
+        let third = listener.read_next_report().await;
+
+        // assert
+        assert!(matches!(
+            first,
+            Err(ListeningError::IncompatibleProtocol { version }) if version == PROTOCOL_VERSION + 1
+        ));
+        assert!(matches!(second, Ok(None)));
+        assert!(matches!(third, Ok(None)));
+    }
+
+    #[tokio::test]
+    async fn stopped_listener_reports_controller_offline() {
+        // arrange
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("capture");
+        let hello = Message::Hello(Hello::new(
+            FirmwareVersion::new(0, 1, 0),
+            0x0123_4567_89AB_CDEF,
+        ))
+        .as_frame(Transport::Binary)
+        .unwrap();
+        let heartbeat = Message::Heartbeat(Heartbeat::new(120, ControllerMode::Idle, 0))
+            .as_frame(Transport::Binary)
+            .unwrap();
+        std::fs::write(&path, [&hello[..], &heartbeat[..]].concat()).unwrap();
+        let mut listener = replay_listener(&path);
+        let _identity = listener.read_next_report().await;
+        let _online = listener.read_next_report().await;
+
+        // act
+        let actual = listener.stop();
+        let again = listener.stop();
+
+        // assert
+        match actual {
+            Some(crate::model::Report::Status(status)) => assert!(!status.online),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(again, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reconnected_listener_counts_frames_of_new_connection() {
+        // arrange
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("capture");
+        let hello = Message::Hello(Hello::new(
+            FirmwareVersion::new(0, 1, 0),
+            0x0123_4567_89AB_CDEF,
+        ))
+        .as_frame(Transport::Binary)
+        .unwrap();
+        std::fs::write(&path, [&hello[..], &hello[..]].concat()).unwrap();
+        let mut listener = replay_listener(&path);
+        let _first = listener.read_next_report().await;
+        let _second = listener.read_next_report().await;
+        let _lost = listener.read_next_report().await;
+        std::fs::write(&path, &hello[..]).unwrap();
+
+        // act -> fewer frames than before the reconnect must not be taken as negative progress
+        let actual = listener.read_next_report().await;
+
+        // assert
+        assert!(matches!(
+            actual,
+            Ok(Some(crate::model::Report::Identity(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn replay_missing_capture_fails() {
+        // arrange
+        let dir = tempdir().unwrap();
+
+        // act
+        let actual = replay(&dir.path().join("missing")).await;
+
+        // assert
+        assert!(matches!(
+            actual,
+            Err(ListeningError::CouldNotOpenPort { .. })
+        ));
+    }
+}
@@ -1,7 +1,17 @@
-use crate::model::TrackResult;
+use crate::app_service::ports::ForwardingError;
+use crate::config::{Auth, ServiceConfig};
+use crate::model::{
+    CrashReport, DeviceIdentity, DeviceMode, DeviceStatus, ReportOrigin, TrackResult,
+};
 use async_trait::async_trait;
-use keret_service_transmit::ActionReport;
+use chrono::{DateTime, Utc};
+use keret_service_transmit::{ActionReport, BatchItemResult};
+use reqwest::{Certificate, Client, Identity, Response, StatusCode, Url};
+use serde::Serialize;
 use snafu::{ResultExt, Snafu};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
 #[derive(Debug, Snafu)]
 pub(crate) enum SendingError {
@@ -10,38 +20,667 @@ pub(crate) enum SendingError {
         target: String,
         source: reqwest::Error,
     },
+    #[snafu(display("{target} rejected the report with {status}: {body}"))]
+    ReportRejected {
+        target: String,
+        status: StatusCode,
+        body: String,
+    },
+    #[snafu(display("{target} rejected the report: {reason}"))]
+    BatchItemRejected { target: String, reason: String },
+    #[snafu(display("Could not read the answer of {target} to the batch"))]
+    InvalidBatchAnswer {
+        target: String,
+        source: reqwest::Error,
+    },
+    #[snafu(display("{target} answered for {answered} of {sent} reports of the batch"))]
+    IncompleteBatchAnswer {
+        target: String,
+        sent: usize,
+        answered: usize,
+    },
+    #[snafu(display("Could not derive the URL of {path} from {target}"))]
+    InvalidTargetUrl {
+        target: String,
+        path: String,
+        source: url::ParseError,
+    },
+    #[snafu(display("Could not read the certificate {path}"))]
+    CouldNotReadCertificate {
+        path: String,
+        source: std::io::Error,
+    },
+    #[snafu(display("Invalid certificate {path}"))]
+    InvalidCertificate {
+        path: String,
+        source: reqwest::Error,
+    },
+    #[snafu(display("Could not set up the HTTP client"))]
+    CouldNotBuildClient { source: reqwest::Error },
+}
+
+// only a report the service refused itself is refused for good, anything else may pass later on
+impl ForwardingError for SendingError {
+    fn is_permanent(&self) -> bool {
+        match self {
+            // overloaded services ask to slow down, they take the report later on
+            SendingError::ReportRejected { status, .. } => {
+                status.is_client_error() && *status != StatusCode::TOO_MANY_REQUESTS
+            }
+            SendingError::BatchItemRejected { .. } => true,
+            _ => false,
+        }
+    }
 }
 
+/// longest part of the answer of a rejected report kept for the log
+const MAX_REJECTION_BODY: usize = 500;
+
+/// path of the batch endpoint, relative to the activity report endpoint
+const BATCH_PATH: &str = "batch";
+
+/// path of the crash report endpoint, relative to the activity report endpoint
+const CRASH_REPORT_PATH: &str = "crash";
+
+/// path of the device endpoint, relative to the activity report endpoint
+const DEVICE_PATH: &str = "device";
+
+/// path of the device status endpoint, relative to the activity report endpoint
+const STATUS_PATH: &str = "status";
+
+/// posts the reports to keret-service
+/// a single client is kept for all requests, so connections are reused
 pub(crate) struct ReportSender {
-    target: String,
+    client: Client,
+    target: Url,
+    auth: Option<Auth>,
+    /// cleared once the service turned out to predate the batch endpoint
+    batches_supported: AtomicBool,
 }
 
 impl ReportSender {
-    pub(crate) fn new(target: String) -> Self {
-        Self { target }
+    pub(crate) fn new(config: &ServiceConfig) -> Result<Self, SendingError> {
+        let url = config.url.clone().unwrap_or_default();
+        let target = Url::parse(&url).context(InvalidTargetUrlSnafu {
+            target: url,
+            path: "",
+        })?;
+
+        let mut client = Client::builder()
+            .connect_timeout(config.connect_timeout)
+            .timeout(config.timeout);
+        if let Some(path) = &config.ca_certificate {
+            let certificate =
+                Certificate::from_pem(&read_pem(path)?).context(InvalidCertificateSnafu {
+                    path: path.display().to_string(),
+                })?;
+            client = client.add_root_certificate(certificate);
+        }
+        if let (Some(certificate), Some(key)) = (&config.client_certificate, &config.client_key) {
+            // the identity is read from a single PEM holding the certificate and its key
+            let mut pem = read_pem(certificate)?;
+            pem.push(b'\n');
+            pem.extend(read_pem(key)?);
+            let identity = Identity::from_pem(&pem).context(InvalidCertificateSnafu {
+                path: certificate.display().to_string(),
+            })?;
+            client = client.identity(identity);
+        }
+
+        Ok(Self {
+            client: client.build().context(CouldNotBuildClientSnafu)?,
+            target,
+            auth: config.auth.clone(),
+            batches_supported: AtomicBool::new(true),
+        })
+    }
+
+    /// post the payload as JSON, carrying the credentials if configured
+    /// anything but a success status counts as failure, the report was not taken then
+    async fn post<T: Serialize>(&self, url: Url, payload: &T) -> Result<(), SendingError> {
+        let response = self.request(url.clone(), payload).await?;
+        accepted(&url, response).await.map(|_| ())
+    }
+
+    /// post the payload as JSON, carrying the credentials if configured, whatever the answer
+    async fn request<T: Serialize>(&self, url: Url, payload: &T) -> Result<Response, SendingError> {
+        let request = self.client.post(url.clone()).json(payload);
+        let request = match &self.auth {
+            None => request,
+            Some(Auth::Bearer { token }) => request.bearer_auth(token),
+            Some(Auth::ApiKey { header, key }) => request.header(header.as_str(), key.as_str()),
+        };
+
+        request.send().await.context(CouldNotSendReportSnafu {
+            target: url.to_string(),
+        })
+    }
+
+    /// post the activity reports to the batch endpoint, returning how many were stored,
+    /// counted from the first. None if the service has no batch endpoint
+    async fn post_batch(&self, reports: &[ActionReport]) -> Result<Option<usize>, SendingError> {
+        let target = self.batch_url()?;
+        let response = self.request(target.clone(), &reports).await?;
+        // older services do not know the path, or only allow to list it
+        if matches!(
+            response.status(),
+            StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED
+        ) {
+            return Ok(None);
+        }
+
+        let results: Vec<BatchItemResult> = accepted(&target, response)
+            .await?
+            .json()
+            .await
+            .context(InvalidBatchAnswerSnafu {
+                target: target.to_string(),
+            })?;
+        if results.len() != reports.len() {
+            return IncompleteBatchAnswerSnafu {
+                target: target.to_string(),
+                sent: reports.len(),
+                answered: results.len(),
+            }
+            .fail();
+        }
+
+        let stored = results
+            .iter()
+            .take_while(|result| matches!(result, BatchItemResult::Stored { .. }))
+            .count();
+        match results.into_iter().nth(stored) {
+            // the reports behind a rejected one are sent again with it
+            Some(BatchItemResult::Rejected { reason }) if stored == 0 => BatchItemRejectedSnafu {
+                target: target.to_string(),
+                reason,
+            }
+            .fail(),
+            _ => Ok(Some(stored)),
+        }
+    }
+
+    /// URL of the batch endpoint below the activity report endpoint
+    fn batch_url(&self) -> Result<Url, SendingError> {
+        let mut url = self.target.clone();
+        url.path_segments_mut()
+            .map_err(|()| url::ParseError::RelativeUrlWithCannotBeABaseBase)
+            .context(InvalidTargetUrlSnafu {
+                target: self.target.to_string(),
+                path: BATCH_PATH,
+            })?
+            .pop_if_empty()
+            .push(BATCH_PATH);
+        Ok(url)
+    }
+
+    /// URL of an endpoint next to the activity report endpoint
+    fn sibling_url(&self, path: &str) -> Result<Url, SendingError> {
+        self.target.join(path).context(InvalidTargetUrlSnafu {
+            target: self.target.to_string(),
+            path,
+        })
     }
 }
 
+/// the response, if its status tells the request succeeded
+/// otherwise the answer is kept for the log, as it usually tells why the report was rejected
+async fn accepted(url: &Url, response: Response) -> Result<Response, SendingError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let body = response.text().await.unwrap_or_default();
+    ReportRejectedSnafu {
+        target: url.to_string(),
+        status,
+        body: body.chars().take(MAX_REJECTION_BODY).collect::<String>(),
+    }
+    .fail()
+}
+
+/// turn the value into a sendable ActionReport for the service
+fn action_report(origin: ReportOrigin, report: TrackResult) -> ActionReport {
+    // extract the actual values from the adapter value object
+    let category = report.category();
+    let finished_at = report
+        .finished_at()
+        .and_then(|seconds| DateTime::from_timestamp(seconds as i64, 0));
+    let report: u64 = report.into();
+    let origin =
+        keret_service_transmit::ReportOrigin::new(origin.device(), origin.boot_id, origin.sequence);
+    // prefer the time the controller finished, the report may have been delayed
+    let report = match finished_at {
+        Some(timestamp) => ActionReport::new(timestamp, Duration::from_secs(report)),
+        None => ActionReport::from(report),
+    };
+    report.with_origin(origin).with_category(category)
+}
+
+fn read_pem(path: &Path) -> Result<Vec<u8>, SendingError> {
+    std::fs::read(path).context(CouldNotReadCertificateSnafu {
+        path: path.display().to_string(),
+    })
+}
+
 #[async_trait]
 impl crate::app_service::ports::ReportMessaging for ReportSender {
     type Error = SendingError;
 
-    async fn send(&self, report: TrackResult) -> Result<(), Self::Error> {
-        // extract the actual value from the adapter value object
-        let report: u64 = report.into();
-        // turn the value into a sendable ActionReport for the service
-        let report: ActionReport = report.into();
-        let client = reqwest::Client::new();
-
-        let _res = client
-            .post(&self.target)
-            .json(&report)
-            .send()
+    async fn send(&self, origin: ReportOrigin, report: TrackResult) -> Result<(), Self::Error> {
+        self.post(self.target.clone(), &action_report(origin, report))
             .await
-            .context(CouldNotSendReportSnafu {
-                target: self.target.clone(),
-            })?;
+    }
+
+    async fn send_batch(
+        &self,
+        reports: Vec<(ReportOrigin, TrackResult)>,
+    ) -> Result<usize, Self::Error> {
+        let reports: Vec<ActionReport> = reports
+            .into_iter()
+            .map(|(origin, report)| action_report(origin, report))
+            .collect();
+
+        if self.batches_supported.load(Ordering::Relaxed) {
+            match self.post_batch(&reports).await {
+                Ok(Some(stored)) => return Ok(stored),
+                Ok(None) => {
+                    eprintln!(
+                        "[SINK] {} has no batch endpoint, sending the reports one at a time",
+                        self.target
+                    );
+                    self.batches_supported.store(false, Ordering::Relaxed);
+                }
+                // refusing the batch as a whole says nothing about a single report,
+                // which is found out by sending them one at a time
+                Err(e @ SendingError::ReportRejected { .. }) if e.is_permanent() => {
+                    eprintln!(
+                        "[SINK] {}, sending the reports one at a time",
+                        crate::error_chain(&e)
+                    );
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let mut sent = 0;
+        for report in &reports {
+            match self.post(self.target.clone(), report).await {
+                Ok(()) => sent += 1,
+                Err(e) if sent == 0 => return Err(e),
+                // the failed report is the first one of the next attempt, failing there
+                Err(_) => break,
+            }
+        }
+        Ok(sent)
+    }
+
+    async fn send_crash_report(&self, report: CrashReport) -> Result<(), Self::Error> {
+        // crash reports are posted next to the activity reports
+        let target = self.sibling_url(CRASH_REPORT_PATH)?;
+        let origin = keret_service_transmit::ReportOrigin::new(
+            report.origin.device(),
+            report.origin.boot_id,
+            report.origin.sequence,
+        );
+        let report = keret_service_transmit::CrashReport::new(
+            report.received_at.into(),
+            report.message,
+            report.file,
+            report.line,
+        )
+        .with_origin(origin);
+        self.post(target, &report).await
+    }
+
+    async fn send_device_identity(&self, identity: DeviceIdentity) -> Result<(), Self::Error> {
+        // device identities are posted next to the activity reports
+        let target = self.sibling_url(DEVICE_PATH)?;
+        let identity = keret_service_transmit::DeviceIdentity::new(
+            Utc::now(),
+            format!("{:016x}", identity.serial_number),
+            identity.firmware_version,
+            identity.protocol_version,
+        );
+        self.post(target, &identity).await
+    }
+
+    async fn send_device_status(&self, status: DeviceStatus) -> Result<(), Self::Error> {
+        // device statuses are posted next to the activity reports
+        let target = self.sibling_url(STATUS_PATH)?;
+        let mode = match status.mode {
+            DeviceMode::Idle => keret_service_transmit::DeviceMode::Idle,
+            DeviceMode::Running => keret_service_transmit::DeviceMode::Running,
+            DeviceMode::Error => keret_service_transmit::DeviceMode::Error,
+        };
+        let status = keret_service_transmit::DeviceStatus::new(
+            format!("{:016x}", status.serial_number),
+            status.last_seen.into(),
+            status.online,
+            mode,
+            status.uptime,
+            status.unacknowledged,
+        );
+        self.post(target, &status).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::app_service::ports::ReportMessaging;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// answer a single request with the status and body, returning the request received
+    async fn serve_once(
+        status: &'static str,
+        body: &'static str,
+    ) -> (String, tokio::task::JoinHandle<String>) {
+        let (url, server) = serve(vec![(status, body)]).await;
+        let server = tokio::spawn(async move { server.await.unwrap().remove(0) });
+        (url, server)
+    }
+
+    /// answer one request after the other with the statuses and bodies, returning the
+    /// requests received
+    async fn serve(
+        answers: Vec<(&'static str, &'static str)>,
+    ) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/api/v1.0/report", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for (status, body) in answers {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0; 4096];
+                // read the headers and as much of the body as announced
+                loop {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                    let text = String::from_utf8_lossy(&request).to_lowercase();
+                    if let Some(end) = text.find("\r\n\r\n") {
+                        let length = text
+                            .lines()
+                            .find_map(|line| line.strip_prefix("content-length: "))
+                            .map_or(0, |length| length.trim().parse().unwrap());
+                        if request.len() >= end + 4 + length {
+                            break;
+                        }
+                    }
+                    if read == 0 {
+                        break;
+                    }
+                }
+                let response = format!(
+                    "HTTP/1.1 {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+                requests.push(String::from_utf8_lossy(&request).to_string());
+            }
+            requests
+        });
+        (url, server)
+    }
+
+    fn service(url: &str) -> ServiceConfig {
+        ServiceConfig {
+            url: Some(url.to_string()),
+            ..ServiceConfig::default()
+        }
+    }
+
+    fn origin() -> ReportOrigin {
+        ReportOrigin {
+            serial_number: 0x0123_4567_89AB_CDEF,
+            boot_id: 0xB007,
+            sequence: 3,
+        }
+    }
+
+    fn batch(count: u32) -> Vec<(ReportOrigin, TrackResult)> {
+        (0..count)
+            .map(|sequence| {
+                let origin = ReportOrigin {
+                    sequence,
+                    ..origin()
+                };
+                (origin, TrackResult::from(60))
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn send_accepted_report_succeeds() {
+        // arrange
+        let (url, server) = serve_once("201 Created", "").await;
+        let sender = ReportSender::new(&service(&url)).unwrap();
+
+        // act
+        let actual = sender.send(origin(), TrackResult::from(60)).await;
+
+        // assert
+        assert!(actual.is_ok(), "{:?}", actual);
+        assert!(server
+            .await
+            .unwrap()
+            .starts_with("POST /api/v1.0/report HTTP/1.1"));
+    }
+
+    #[tokio::test]
+    async fn send_rejected_report_fails_with_answer() {
+        // arrange
+        let (url, _server) = serve_once("500 Internal Server Error", "database is gone").await;
+        let sender = ReportSender::new(&service(&url)).unwrap();
+
+        // act
+        let actual = sender.send(origin(), TrackResult::from(60)).await;
+
+        // assert
+        match actual {
+            Err(SendingError::ReportRejected { status, body, .. }) => {
+                assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+                assert_eq!(body, "database is gone");
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn send_report_refused_by_service_fails_for_good() {
+        // arrange
+        let (url, _server) = serve_once("422 Unprocessable Entity", "invalid duration").await;
+        let sender = ReportSender::new(&service(&url)).unwrap();
+
+        // act
+        let actual = sender.send(origin(), TrackResult::from(60)).await;
+
+        // assert
+        assert!(actual.unwrap_err().is_permanent());
+    }
+
+    #[tokio::test]
+    async fn send_to_busy_service_fails_for_now() {
+        // arrange
+        let (url, _server) = serve_once("429 Too Many Requests", "slow down").await;
+        let sender = ReportSender::new(&service(&url)).unwrap();
+
+        // act
+        let actual = sender.send(origin(), TrackResult::from(60)).await;
+
+        // assert
+        assert!(!actual.unwrap_err().is_permanent());
+    }
+
+    #[tokio::test]
+    async fn send_with_bearer_token_carries_token() {
+        // arrange
+        let (url, server) = serve_once("200 OK", "").await;
+        let mut config = service(&url);
+        config.auth = Some(Auth::Bearer {
+            token: "secret".to_string(),
+        });
+        let sender = ReportSender::new(&config).unwrap();
+
+        // act
+        sender.send(origin(), TrackResult::from(60)).await.unwrap();
+
+        // assert
+        let request = server.await.unwrap().to_lowercase();
+        assert!(request.contains("authorization: bearer secret\r\n"));
+    }
+
+    #[tokio::test]
+    async fn send_with_api_key_carries_key_in_header() {
+        // arrange
+        let (url, server) = serve_once("200 OK", "").await;
+        let mut config = service(&url);
+        config.auth = Some(Auth::ApiKey {
+            header: "X-API-Key".to_string(),
+            key: "secret".to_string(),
+        });
+        let sender = ReportSender::new(&config).unwrap();
+
+        // act
+        sender.send(origin(), TrackResult::from(60)).await.unwrap();
+
+        // assert
+        let request = server.await.unwrap().to_lowercase();
+        assert!(request.contains("x-api-key: secret\r\n"));
+    }
+
+    #[tokio::test]
+    async fn send_to_silent_service_times_out() {
+        // arrange -> accepts the connection, but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/api/v1.0/report", listener.local_addr().unwrap());
+        let _server = tokio::spawn(async move {
+            let (_stream, _) = listener.accept().await.unwrap();
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        });
+        let mut config = service(&url);
+        config.timeout = Duration::from_millis(200);
+        let sender = ReportSender::new(&config).unwrap();
+
+        // act
+        let actual = sender.send(origin(), TrackResult::from(60)).await;
+
+        // assert
+        match actual {
+            Err(SendingError::CouldNotSendReport { source, .. }) => assert!(source.is_timeout()),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn new_with_missing_ca_certificate_fails() {
+        // arrange
+        let mut config = service("https://localhost/api/v1.0/report");
+        config.ca_certificate = Some("/nonexistent/keret-ca.pem".into());
+
+        // act
+        let actual = ReportSender::new(&config);
+
+        // assert
+        assert!(matches!(
+            actual,
+            Err(SendingError::CouldNotReadCertificate { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn send_batch_counts_reports_stored_before_rejected_one() {
+        // arrange
+        let (url, server) = serve_once(
+            "200 OK",
+            "[{\"result\":\"stored\",\"index\":0},\
+             {\"result\":\"rejected\",\"reason\":\"malformed\"},\
+             {\"result\":\"stored\",\"index\":1}]",
+        )
+        .await;
+        let sender = ReportSender::new(&service(&url)).unwrap();
+
+        // act
+        let actual = sender.send_batch(batch(3)).await;
+
+        // assert
+        assert_eq!(actual.unwrap(), 1);
+        assert!(server
+            .await
+            .unwrap()
+            .starts_with("POST /api/v1.0/report/batch HTTP/1.1"));
+    }
+
+    #[tokio::test]
+    async fn send_batch_with_first_report_rejected_fails_with_reason() {
+        // arrange
+        let (url, _server) = serve_once(
+            "200 OK",
+            "[{\"result\":\"rejected\",\"reason\":\"malformed\"},\
+             {\"result\":\"stored\",\"index\":0}]",
+        )
+        .await;
+        let sender = ReportSender::new(&service(&url)).unwrap();
+
+        // act
+        let actual = sender.send_batch(batch(2)).await;
+
+        // assert
+        match actual {
+            Err(e @ SendingError::BatchItemRejected { .. }) => {
+                assert!(e.is_permanent());
+                assert!(e.to_string().ends_with("malformed"));
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn send_batch_to_service_without_batch_endpoint_sends_one_at_a_time() {
+        // arrange
+        let (url, server) = serve(vec![
+            ("404 Not Found", ""),
+            ("200 OK", "0"),
+            ("200 OK", "1"),
+        ])
+        .await;
+        let sender = ReportSender::new(&service(&url)).unwrap();
+
+        // act
+        let actual = sender.send_batch(batch(2)).await;
+
+        // assert
+        assert_eq!(actual.unwrap(), 2);
+        let requests = server.await.unwrap();
+        assert!(requests[0].starts_with("POST /api/v1.0/report/batch HTTP/1.1"));
+        assert!(requests[1].starts_with("POST /api/v1.0/report HTTP/1.1"));
+        assert!(requests[2].starts_with("POST /api/v1.0/report HTTP/1.1"));
+        assert!(!sender.batches_supported.load(Ordering::Relaxed));
+    }
+
+    #[tokio::test]
+    async fn send_batch_refused_as_a_whole_sends_one_at_a_time() {
+        // arrange
+        let (url, server) = serve(vec![
+            ("400 Bad Request", "invalid batch"),
+            ("400 Bad Request", "invalid report"),
+        ])
+        .await;
+        let sender = ReportSender::new(&service(&url)).unwrap();
+
+        // act
+        let actual = sender.send_batch(batch(2)).await;
 
-        Ok(())
+        // assert -> only the first report is refused for good, the batch endpoint is kept
+        assert!(actual.unwrap_err().is_permanent());
+        let requests = server.await.unwrap();
+        assert!(requests[0].starts_with("POST /api/v1.0/report/batch HTTP/1.1"));
+        assert!(requests[1].starts_with("POST /api/v1.0/report HTTP/1.1"));
+        assert!(sender.batches_supported.load(Ordering::Relaxed));
     }
 }
@@ -0,0 +1,182 @@
+use crate::infrastructure::sinks::record::Record;
+use snafu::{ResultExt, Snafu};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+#[derive(Debug, Snafu)]
+pub(crate) enum CommandError {
+    #[snafu(display("Could not start {program}"))]
+    CouldNotStartCommand {
+        program: String,
+        source: std::io::Error,
+    },
+    #[snafu(display("Could not wait for {program} to finish"))]
+    CouldNotRunCommand {
+        program: String,
+        source: std::io::Error,
+    },
+    #[snafu(display("{program} did not finish within {}s and was killed", timeout.as_secs()))]
+    CommandTimedOut { program: String, timeout: Duration },
+    #[snafu(display("{program} failed with {status}: {stderr}"))]
+    CommandFailed {
+        program: String,
+        status: String,
+        stderr: String,
+    },
+    #[snafu(display("Could not serialize the report"))]
+    CouldNotSerializeReport { source: serde_json::Error },
+}
+
+/// runs a program for every report
+/// the values of the report are passed as environment variables (`KERET_KIND`,
+/// `KERET_DEVICE`, `KERET_DURATION`, ...) and the whole report as a JSON line on stdin.
+/// the report counts as taken if the program exits successfully
+pub(crate) struct CommandSink {
+    program: String,
+    args: Vec<String>,
+    timeout: Duration,
+}
+
+impl CommandSink {
+    pub(crate) fn new(program: String, args: Vec<String>, timeout: Duration) -> Self {
+        Self {
+            program,
+            args,
+            timeout,
+        }
+    }
+
+    pub(crate) async fn run(&self, record: &Record) -> Result<(), CommandError> {
+        let mut input = record.to_json().context(CouldNotSerializeReportSnafu)?;
+        input.push('\n');
+
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .envs(record.environment())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .context(CouldNotStartCommandSnafu {
+                program: self.program.clone(),
+            })?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            // the program does not have to read the report, only its exit status counts
+            let _ = stdin.write_all(input.as_bytes()).await;
+        }
+
+        let output = match tokio::time::timeout(self.timeout, child.wait_with_output()).await {
+            Ok(output) => output.context(CouldNotRunCommandSnafu {
+                program: self.program.clone(),
+            })?,
+            // dropping the child killed it
+            Err(_) => {
+                return CommandTimedOutSnafu {
+                    program: self.program.clone(),
+                    timeout: self.timeout,
+                }
+                .fail()
+            }
+        };
+
+        if !output.status.success() {
+            return CommandFailedSnafu {
+                program: self.program.clone(),
+                status: output.status.to_string(),
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            }
+            .fail();
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::{Report, ReportOrigin, TrackResult};
+    use chrono_tz::Tz;
+
+    fn record() -> Record {
+        let origin = ReportOrigin {
+            serial_number: 0x0123_4567_89AB_CDEF,
+            boot_id: 0xB007,
+            sequence: 3,
+        };
+        Record::from_report(&Report::Activity(origin, TrackResult::from(10)), Tz::UTC)
+    }
+
+    fn shell(script: &str, timeout: Duration) -> CommandSink {
+        CommandSink::new(
+            "sh".to_string(),
+            vec!["-c".to_string(), script.to_string()],
+            timeout,
+        )
+    }
+
+    #[tokio::test]
+    async fn run_passes_report_as_environment_and_stdin() {
+        // arrange -> fails unless both carry the report
+        let sink = shell(
+            "test \"$KERET_DURATION\" = 10 && grep -q '\"sequence\":3'",
+            Duration::from_secs(5),
+        );
+
+        // act
+        let actual = sink.run(&record()).await;
+
+        // assert
+        assert!(actual.is_ok(), "{:?}", actual);
+    }
+
+    #[tokio::test]
+    async fn run_failing_program_fails_with_its_output() {
+        // arrange
+        let sink = shell("echo broken >&2; exit 3", Duration::from_secs(5));
+
+        // act
+        let actual = sink.run(&record()).await;
+
+        // assert
+        match actual {
+            Err(CommandError::CommandFailed { stderr, .. }) => assert_eq!(stderr, "broken"),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn run_slow_program_times_out() {
+        // arrange
+        let sink = shell("sleep 5", Duration::from_millis(100));
+
+        // act
+        let actual = sink.run(&record()).await;
+
+        // assert
+        assert!(matches!(actual, Err(CommandError::CommandTimedOut { .. })));
+    }
+
+    #[tokio::test]
+    async fn run_missing_program_fails_to_start() {
+        // arrange
+        let sink = CommandSink::new(
+            "/nonexistent/keret-notify".to_string(),
+            Vec::new(),
+            Duration::from_secs(5),
+        );
+
+        // act
+        let actual = sink.run(&record()).await;
+
+        // assert
+        assert!(matches!(
+            actual,
+            Err(CommandError::CouldNotStartCommand { .. })
+        ));
+    }
+}
@@ -0,0 +1,158 @@
+use crate::config::RecordFormat;
+use crate::infrastructure::sinks::record::{csv_header, Record};
+use snafu::{ResultExt, Snafu};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Debug, Snafu)]
+#[allow(clippy::enum_variant_names)]
+pub(crate) enum FileSinkError {
+    #[snafu(display("Could not write the report to {path}"))]
+    CouldNotWriteFile {
+        path: String,
+        source: std::io::Error,
+    },
+    #[snafu(display("Could not write the report to the standard output"))]
+    CouldNotWriteStdout { source: std::io::Error },
+    #[snafu(display("Could not serialize the report"))]
+    CouldNotSerializeRecord { source: serde_json::Error },
+}
+
+/// appends the reports to a local file, one per line
+/// the file is opened for every report, so it may be rotated while the adapter runs.
+/// a CSV file gets its header whenever it is empty
+pub(crate) struct FileSink {
+    path: PathBuf,
+    format: RecordFormat,
+}
+
+impl FileSink {
+    pub(crate) fn new(path: PathBuf, format: RecordFormat) -> Self {
+        Self { path, format }
+    }
+
+    pub(crate) fn write(&self, record: &Record) -> Result<(), FileSinkError> {
+        let line = record
+            .to_line(self.format)
+            .context(CouldNotSerializeRecordSnafu)?;
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| {
+                let mut content = String::new();
+                if self.format == RecordFormat::Csv && file.metadata()?.len() == 0 {
+                    content.push_str(&csv_header());
+                    content.push('\n');
+                }
+                content.push_str(&line);
+                content.push('\n');
+                file.write_all(content.as_bytes())
+            })
+            .context(CouldNotWriteFileSnafu {
+                path: self.path.display().to_string(),
+            })
+    }
+}
+
+/// prints the reports to the standard output, one per line
+/// the log goes to the standard error, so the output can be piped into other tools
+pub(crate) struct StdoutSink {
+    format: RecordFormat,
+    header_written: AtomicBool,
+}
+
+impl StdoutSink {
+    pub(crate) fn new(format: RecordFormat) -> Self {
+        Self {
+            format,
+            header_written: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn write(&self, record: &Record) -> Result<(), FileSinkError> {
+        let line = record
+            .to_line(self.format)
+            .context(CouldNotSerializeRecordSnafu)?;
+
+        let mut stdout = std::io::stdout().lock();
+        if self.format == RecordFormat::Csv && !self.header_written.swap(true, Ordering::Relaxed) {
+            writeln!(stdout, "{}", csv_header()).context(CouldNotWriteStdoutSnafu)?;
+        }
+        writeln!(stdout, "{}", line)
+            .and_then(|()| stdout.flush())
+            .context(CouldNotWriteStdoutSnafu)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::{Report, ReportOrigin, TrackResult};
+    use chrono_tz::Tz;
+    use tempfile::tempdir;
+
+    fn record(sequence: u32) -> Record {
+        let origin = ReportOrigin {
+            serial_number: 0x0123_4567_89AB_CDEF,
+            boot_id: 0xB007,
+            sequence,
+        };
+        Record::from_report(&Report::Activity(origin, TrackResult::from(10)), Tz::UTC)
+    }
+
+    #[test]
+    fn write_csv_writes_header_once() {
+        // arrange
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("header.csv");
+        let sink = FileSink::new(path.clone(), RecordFormat::Csv);
+
+        // act
+        sink.write(&record(0)).unwrap();
+        sink.write(&record(1)).unwrap();
+
+        // assert
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<_> = content.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], csv_header());
+    }
+
+    #[test]
+    fn write_jsonl_appends_to_existing_file() {
+        // arrange
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("append.jsonl");
+        std::fs::write(&path, "{}\n").unwrap();
+        let sink = FileSink::new(path.clone(), RecordFormat::Jsonl);
+
+        // act
+        sink.write(&record(0)).unwrap();
+
+        // assert
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content.lines().count(), 2);
+        assert!(content.ends_with("\"sequence\":0,\"category\":0,\"duration\":10}\n"));
+    }
+
+    #[test]
+    fn write_to_missing_directory_fails() {
+        // arrange
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("missing").join("reports.jsonl");
+        let sink = FileSink::new(path, RecordFormat::Jsonl);
+
+        // act
+        let actual = sink.write(&record(0));
+
+        // assert
+        assert!(matches!(
+            actual,
+            Err(FileSinkError::CouldNotWriteFile { .. })
+        ));
+    }
+}
@@ -0,0 +1,544 @@
+pub(crate) mod command;
+pub(crate) mod file;
+pub(crate) mod mqtt;
+pub(crate) mod record;
+
+use crate::app_service::ports::{ForwardingError, ReportMessaging};
+use crate::config::{Config, OnFailure, SinkConfig, SinkKind};
+use crate::infrastructure::sending::{ReportSender, SendingError};
+use crate::model::{CrashReport, DeviceIdentity, DeviceStatus, Report, ReportOrigin, TrackResult};
+use async_trait::async_trait;
+use chrono_tz::Tz;
+use command::CommandSink;
+use file::{FileSink, StdoutSink};
+use mqtt::MqttSink;
+use record::Record;
+use snafu::Snafu;
+use std::sync::Mutex;
+
+#[derive(Debug, Snafu)]
+pub(crate) enum SinkError {
+    #[snafu(display("Could not forward the report to {sink}"))]
+    SinkFailed {
+        sink: String,
+        /// all sinks failing to take the report refused it for good
+        permanent: bool,
+        source: Box<dyn std::error::Error + Send + Sync + 'static>,
+    },
+    #[snafu(display("Could not set up {sink}"))]
+    CouldNotOpenSink {
+        sink: String,
+        source: Box<dyn std::error::Error + Send + Sync + 'static>,
+    },
+}
+
+/// a destination the reports are forwarded to
+pub(crate) enum Sink {
+    Service(ReportSender),
+    File(FileSink),
+    Stdout(StdoutSink),
+    Mqtt(Box<MqttSink>),
+    Command(CommandSink),
+}
+
+impl Sink {
+    async fn deliver(
+        &self,
+        report: &Report,
+        timezone: Tz,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+        match self {
+            Sink::Service(sender) => Ok(forward(sender, report.clone()).await?),
+            Sink::Mqtt(mqtt) => Ok(forward(mqtt.as_ref(), report.clone()).await?),
+            Sink::File(file) => Ok(file.write(&Record::from_report(report, timezone))?),
+            Sink::Stdout(stdout) => Ok(stdout.write(&Record::from_report(report, timezone))?),
+            Sink::Command(command) => {
+                Ok(command.run(&Record::from_report(report, timezone)).await?)
+            }
+        }
+    }
+
+    /// deliver the reports in their order, stopping at the first failure
+    /// returns how many were taken, counted from the first, and the failure if any.
+    /// keret-service gets consecutive activity reports in a single batch
+    async fn deliver_all(
+        &self,
+        reports: &[Report],
+        timezone: Tz,
+    ) -> (
+        usize,
+        Option<Box<dyn std::error::Error + Send + Sync + 'static>>,
+    ) {
+        if let (Sink::Service(sender), true) = (self, reports.len() > 1) {
+            let batch: Option<Vec<_>> = reports
+                .iter()
+                .map(|report| match report {
+                    Report::Activity(origin, result) => Some((origin.clone(), result.clone())),
+                    _ => None,
+                })
+                .collect();
+            if let Some(batch) = batch {
+                return match sender.send_batch(batch).await {
+                    Ok(sent) => (sent, None),
+                    Err(e) => (0, Some(e.into())),
+                };
+            }
+        }
+
+        for (sent, report) in reports.iter().enumerate() {
+            if let Err(e) = self.deliver(report, timezone).await {
+                return (sent, Some(e));
+            }
+        }
+        (reports.len(), None)
+    }
+}
+
+impl ForwardingError for SinkError {
+    fn is_permanent(&self) -> bool {
+        matches!(
+            self,
+            SinkError::SinkFailed {
+                permanent: true,
+                ..
+            }
+        )
+    }
+}
+
+/// whether the failure of a sink refuses the report for good, only keret-service does that
+fn refused(error: &(dyn std::error::Error + Send + Sync + 'static)) -> bool {
+    error
+        .downcast_ref::<SendingError>()
+        .is_some_and(ForwardingError::is_permanent)
+}
+
+/// send the report to the messaging, depending on its kind
+async fn forward<T: ReportMessaging>(messaging: &T, report: Report) -> Result<(), T::Error> {
+    match report {
+        Report::Activity(origin, result) => messaging.send(origin, result).await,
+        Report::Crash(crash) => messaging.send_crash_report(crash).await,
+        Report::Identity(identity) => messaging.send_device_identity(identity).await,
+        Report::Status(status) => messaging.send_device_status(status).await,
+    }
+}
+
+/// a sink with its name for the log and its handling of failures
+struct NamedSink {
+    name: String,
+    sink: Sink,
+    on_failure: OnFailure,
+}
+
+/// forwards every report to all configured sinks, in the order they were configured
+/// a failing sink does not stop the others. If a sink retrying on failure failed, forwarding
+/// fails as a whole, so the report stays in the spool. When it is forwarded again, only the
+/// sinks which did not take it yet get it, so the others do not see it twice
+pub(crate) struct FanOut {
+    sinks: Vec<NamedSink>,
+    timezone: Tz,
+    /// the reports which failed to be forwarded, and which sinks took them already
+    pending: Mutex<Vec<(Report, Vec<bool>)>>,
+}
+
+impl FanOut {
+    /// set up all sinks of the configuration
+    pub(crate) fn open(config: &Config) -> Result<Self, SinkError> {
+        let mut sinks = Vec::new();
+        for sink in &config.sinks {
+            let name = sink_name(sink, config);
+            let opened = open_sink(sink, config).map_err(|source| SinkError::CouldNotOpenSink {
+                sink: name.clone(),
+                source,
+            })?;
+            sinks.push(NamedSink {
+                name,
+                sink: opened,
+                on_failure: sink.on_failure,
+            });
+        }
+
+        Ok(Self::new(sinks, config.timezone))
+    }
+
+    fn new(sinks: Vec<NamedSink>, timezone: Tz) -> Self {
+        Self {
+            sinks,
+            timezone,
+            pending: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// names of the sinks, for the log
+    pub(crate) fn names(&self) -> Vec<&str> {
+        self.sinks.iter().map(|sink| sink.name.as_str()).collect()
+    }
+
+    async fn deliver(&self, report: Report) -> Result<(), SinkError> {
+        self.deliver_all(vec![report]).await.map(|_| ())
+    }
+
+    /// forward the reports in their order, returning how many were taken by all sinks,
+    /// counted from the first. Fails if not even the first one was
+    async fn deliver_all(&self, reports: Vec<Report>) -> Result<usize, SinkError> {
+        let mut taken: Vec<Vec<bool>> = {
+            let pending = self.pending.lock().expect("not poisoned");
+            reports
+                .iter()
+                .map(|report| {
+                    pending
+                        .iter()
+                        .find(|(pending, _)| pending == report)
+                        .map_or_else(|| vec![false; self.sinks.len()], |(_, taken)| taken.clone())
+                })
+                .collect()
+        };
+
+        // the failure returned, and whether it was one of the first report
+        let mut failure: Option<(String, Box<dyn std::error::Error + Send + Sync>, bool)> = None;
+        // whether the sinks which did not take the first report refused it for good
+        let mut refused_first = true;
+        for (index, sink) in self.sinks.iter().enumerate() {
+            // the reports this sink did not take yet, in their order
+            let mut missing: Vec<usize> = (0..reports.len())
+                .filter(|report| !taken[*report][index])
+                .collect();
+            while !missing.is_empty() {
+                let batch: Vec<Report> = missing
+                    .iter()
+                    .map(|report| reports[*report].clone())
+                    .collect();
+                let (sent, error) = sink.sink.deliver_all(&batch, self.timezone).await;
+                for report in missing.drain(..sent) {
+                    taken[report][index] = true;
+                }
+
+                match error {
+                    None => break,
+                    // go on with the reports behind the dropped one
+                    Some(e) if sink.on_failure == OnFailure::Skip => {
+                        eprintln!("[SINK] {}: dropping the report: {}", sink.name, e);
+                        taken[missing.remove(0)][index] = true;
+                    }
+                    Some(e) => {
+                        let first = missing.first() == Some(&0);
+                        if first {
+                            refused_first &= refused(e.as_ref());
+                        }
+                        // the failure of the first report decides whether it is retried
+                        if failure
+                            .as_ref()
+                            .is_some_and(|(_, _, on_first)| *on_first || !first)
+                        {
+                            eprintln!("[SINK] {}: keeping the report: {}", sink.name, e);
+                        } else if let Some((name, e, _)) =
+                            failure.replace((sink.name.clone(), e, first))
+                        {
+                            eprintln!("[SINK] {}: keeping the report: {}", name, e);
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+
+        let forwarded = taken
+            .iter()
+            .take_while(|taken| taken.iter().all(|taken| *taken))
+            .count();
+        // a report refused for good is not tried again
+        let permanent = failure.is_some() && forwarded == 0 && refused_first;
+        let retried = if permanent { 1 } else { forwarded };
+
+        // the pending reports of other deliveries are still retried later on
+        let mut pending = self.pending.lock().expect("not poisoned");
+        pending.retain(|(pending, _)| !reports.contains(pending));
+        pending.extend(
+            reports
+                .into_iter()
+                .zip(taken)
+                .skip(retried)
+                // statuses are not retried, the next heartbeat outdates them
+                .filter(|(report, _)| !matches!(report, Report::Status(_))),
+        );
+        drop(pending);
+
+        match failure {
+            Some((sink, source, _)) if forwarded == 0 => Err(SinkError::SinkFailed {
+                sink,
+                permanent,
+                source,
+            }),
+            _ => Ok(forwarded),
+        }
+    }
+}
+
+fn open_sink(
+    sink: &SinkConfig,
+    config: &Config,
+) -> Result<Sink, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    Ok(match &sink.kind {
+        SinkKind::Service => Sink::Service(ReportSender::new(&config.service)?),
+        SinkKind::File { path, format } => Sink::File(FileSink::new(path.clone(), *format)),
+        SinkKind::Stdout { format } => Sink::Stdout(StdoutSink::new(*format)),
+        SinkKind::Mqtt(mqtt) => Sink::Mqtt(Box::new(MqttSink::connect(mqtt, config.timezone))),
+        SinkKind::Command {
+            program,
+            args,
+            timeout,
+        } => Sink::Command(CommandSink::new(program.clone(), args.clone(), *timeout)),
+    })
+}
+
+fn sink_name(sink: &SinkConfig, config: &Config) -> String {
+    match &sink.kind {
+        SinkKind::Service => format!(
+            "service {}",
+            config.service.url.as_deref().unwrap_or_default()
+        ),
+        SinkKind::File { path, .. } => format!("file {}", path.display()),
+        SinkKind::Stdout { .. } => "stdout".to_string(),
+        SinkKind::Mqtt(mqtt) => format!("mqtt {}:{}", mqtt.host, mqtt.port),
+        SinkKind::Command { program, .. } => format!("command {}", program),
+    }
+}
+
+#[async_trait]
+impl ReportMessaging for FanOut {
+    type Error = SinkError;
+
+    async fn send(&self, origin: ReportOrigin, report: TrackResult) -> Result<(), Self::Error> {
+        self.deliver(Report::Activity(origin, report)).await
+    }
+
+    async fn send_crash_report(&self, report: CrashReport) -> Result<(), Self::Error> {
+        self.deliver(Report::Crash(report)).await
+    }
+
+    async fn send_device_identity(&self, identity: DeviceIdentity) -> Result<(), Self::Error> {
+        self.deliver(Report::Identity(identity)).await
+    }
+
+    async fn send_device_status(&self, status: DeviceStatus) -> Result<(), Self::Error> {
+        self.deliver(Report::Status(status)).await
+    }
+
+    async fn send_batch(
+        &self,
+        reports: Vec<(ReportOrigin, TrackResult)>,
+    ) -> Result<usize, Self::Error> {
+        let reports = reports
+            .into_iter()
+            .map(|(origin, result)| Report::Activity(origin, result))
+            .collect();
+        self.deliver_all(reports).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::RecordFormat;
+    use crate::model::DeviceMode;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    fn file_sink(path: PathBuf, on_failure: OnFailure) -> NamedSink {
+        NamedSink {
+            name: format!("file {}", path.display()),
+            sink: Sink::File(FileSink::new(path, RecordFormat::Jsonl)),
+            on_failure,
+        }
+    }
+
+    fn origin(sequence: u32) -> ReportOrigin {
+        ReportOrigin {
+            serial_number: 0x0123_4567_89AB_CDEF,
+            boot_id: 0xB007,
+            sequence,
+        }
+    }
+
+    fn lines(path: &PathBuf) -> usize {
+        std::fs::read_to_string(path).map_or(0, |content| content.lines().count())
+    }
+
+    #[tokio::test]
+    async fn send_reaches_all_sinks() {
+        // arrange
+        let dir = tempdir().unwrap();
+        let first = dir.path().join("first.jsonl");
+        let second = dir.path().join("second.jsonl");
+        let fan_out = FanOut::new(
+            vec![
+                file_sink(first.clone(), OnFailure::Retry),
+                file_sink(second.clone(), OnFailure::Retry),
+            ],
+            Tz::UTC,
+        );
+
+        // act
+        let actual = fan_out.send(origin(0), TrackResult::from(10)).await;
+
+        // assert
+        assert!(actual.is_ok());
+        assert_eq!(lines(&first), 1);
+        assert_eq!(lines(&second), 1);
+    }
+
+    #[tokio::test]
+    async fn send_failing_retried_sink_fails_but_reaches_others() {
+        // arrange
+        let dir = tempdir().unwrap();
+        let working = dir.path().join("working.jsonl");
+        let failing = dir.path().join("missing").join("reports.jsonl");
+        let fan_out = FanOut::new(
+            vec![
+                file_sink(failing, OnFailure::Retry),
+                file_sink(working.clone(), OnFailure::Retry),
+            ],
+            Tz::UTC,
+        );
+
+        // act
+        let actual = fan_out.send(origin(0), TrackResult::from(10)).await;
+
+        // assert
+        assert!(matches!(actual, Err(SinkError::SinkFailed { .. })));
+        assert_eq!(lines(&working), 1);
+    }
+
+    #[tokio::test]
+    async fn send_again_after_failure_skips_sinks_which_took_report() {
+        // arrange
+        let dir = tempdir().unwrap();
+        let working = dir.path().join("working.jsonl");
+        let failing = dir.path().join("missing").join("reports.jsonl");
+        let fan_out = FanOut::new(
+            vec![
+                file_sink(working.clone(), OnFailure::Retry),
+                file_sink(failing, OnFailure::Retry),
+            ],
+            Tz::UTC,
+        );
+        let _ = fan_out.send(origin(0), TrackResult::from(10)).await;
+
+        // act
+        let again = fan_out.send(origin(0), TrackResult::from(10)).await;
+        let next = fan_out.send(origin(1), TrackResult::from(10)).await;
+
+        // assert -> the next report is a new one, reaching the working sink again
+        assert!(again.is_err());
+        assert!(next.is_err());
+        assert_eq!(lines(&working), 2);
+    }
+
+    #[tokio::test]
+    async fn send_again_after_unrelated_status_skips_sinks_which_took_report() {
+        // arrange
+        let dir = tempdir().unwrap();
+        let working = dir.path().join("working.jsonl");
+        let failing = dir.path().join("missing").join("reports.jsonl");
+        let fan_out = FanOut::new(
+            vec![
+                file_sink(working.clone(), OnFailure::Retry),
+                file_sink(failing, OnFailure::Retry),
+            ],
+            Tz::UTC,
+        );
+        let status = DeviceStatus {
+            serial_number: 0x0123_4567_89AB_CDEF,
+            last_seen: std::time::SystemTime::now(),
+            online: true,
+            mode: DeviceMode::Idle,
+            uptime: 10,
+            unacknowledged: 1,
+        };
+        let _ = fan_out.send(origin(0), TrackResult::from(10)).await;
+        let _ = fan_out.send_device_status(status).await;
+
+        // act
+        let again = fan_out.send(origin(0), TrackResult::from(10)).await;
+
+        // assert -> the report and the status, but not the report twice
+        assert!(again.is_err());
+        assert_eq!(lines(&working), 2);
+    }
+
+    #[tokio::test]
+    async fn send_failing_skipped_sink_succeeds() {
+        // arrange
+        let dir = tempdir().unwrap();
+        let working = dir.path().join("working.jsonl");
+        let failing = dir.path().join("missing").join("reports.jsonl");
+        let fan_out = FanOut::new(
+            vec![
+                file_sink(failing, OnFailure::Skip),
+                file_sink(working.clone(), OnFailure::Retry),
+            ],
+            Tz::UTC,
+        );
+
+        // act
+        let actual = fan_out.send(origin(0), TrackResult::from(10)).await;
+
+        // assert
+        assert!(actual.is_ok());
+        assert_eq!(lines(&working), 1);
+    }
+
+    #[tokio::test]
+    async fn send_batch_again_after_failure_skips_sinks_which_took_reports() {
+        // arrange
+        let dir = tempdir().unwrap();
+        let working = dir.path().join("working.jsonl");
+        let failing = dir.path().join("missing").join("reports.jsonl");
+        let fan_out = FanOut::new(
+            vec![
+                file_sink(working.clone(), OnFailure::Retry),
+                file_sink(failing, OnFailure::Retry),
+            ],
+            Tz::UTC,
+        );
+        let batch = || {
+            (0..3)
+                .map(|sequence| (origin(sequence), TrackResult::from(10)))
+                .collect::<Vec<_>>()
+        };
+        let first = fan_out.send_batch(batch()).await;
+
+        // act
+        let again = fan_out.send_batch(batch()).await;
+
+        // assert
+        assert!(first.is_err());
+        assert!(again.is_err());
+        assert_eq!(lines(&working), 3);
+    }
+
+    #[tokio::test]
+    async fn send_batch_failing_skipped_sink_reaches_all_sinks() {
+        // arrange
+        let dir = tempdir().unwrap();
+        let working = dir.path().join("skip-working.jsonl");
+        let failing = dir.path().join("skip-missing").join("reports.jsonl");
+        let fan_out = FanOut::new(
+            vec![
+                file_sink(failing, OnFailure::Skip),
+                file_sink(working.clone(), OnFailure::Retry),
+            ],
+            Tz::UTC,
+        );
+        let batch = (0..3)
+            .map(|sequence| (origin(sequence), TrackResult::from(10)))
+            .collect();
+
+        // act
+        let actual = fan_out.send_batch(batch).await;
+
+        // assert
+        assert_eq!(actual.unwrap(), 3);
+        assert_eq!(lines(&working), 3);
+    }
+}
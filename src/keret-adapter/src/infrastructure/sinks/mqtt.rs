@@ -0,0 +1,683 @@
+use crate::config::MqttConfig;
+use crate::infrastructure::sinks::record::Record;
+use crate::model::{CrashReport, DeviceIdentity, DeviceStatus, Report, ReportOrigin, TrackResult};
+use async_trait::async_trait;
+use chrono_tz::Tz;
+use rumqttc::{AsyncClient, ClientError, Event, LastWill, MqttOptions, Packet, QoS};
+use serde_json::json;
+use snafu::{ResultExt, Snafu};
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+#[derive(Debug, Snafu)]
+pub(crate) enum MqttError {
+    #[snafu(display("Could not publish to {topic}"))]
+    CouldNotPublish { topic: String, source: ClientError },
+    #[snafu(display("The broker did not acknowledge the message to {topic}"))]
+    NotAcknowledged { topic: String },
+    #[snafu(display("Could not serialize the report"))]
+    CouldNotSerializeReport { source: serde_json::Error },
+}
+
+// the broker takes anything, once it can be reached
+impl crate::app_service::ports::ForwardingError for MqttError {
+    fn is_permanent(&self) -> bool {
+        false
+    }
+}
+
+/// messages waiting to be sent to the broker, publishing fails once it is full
+const PUBLISH_QUEUE: usize = 64;
+
+/// how long the broker may take to acknowledge a message
+const ACKNOWLEDGE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// wait before connecting again after the connection to the broker failed
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// interval of the pings keeping the connection to the broker alive
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+/// payload of the availability topics while available
+const ONLINE: &str = "online";
+
+/// payload of the availability topics while not available
+const OFFLINE: &str = "offline";
+
+/// a message to be published
+#[derive(Debug, PartialEq)]
+struct Publication {
+    topic: String,
+    payload: String,
+    retain: bool,
+}
+
+/// publishes the reports to an MQTT broker, as JSON to the configured topics
+/// the state, identity and availability of the controllers are retained, so subscribers see
+/// them right away. Every controller is announced to Home Assistant once it is known by its
+/// serial number, if discovery is enabled.
+/// the connection is kept up in a task of its own, reconnecting whenever it was lost.
+/// a report counts as taken once the broker acknowledged all of its messages, otherwise it is
+/// kept in the spool and published again later on
+pub(crate) struct MqttSink {
+    client: AsyncClient,
+    messages: Messages,
+    unacknowledged: Arc<Unacknowledged>,
+}
+
+impl MqttSink {
+    /// start connecting to the broker
+    pub(crate) fn connect(config: &MqttConfig, timezone: Tz) -> Self {
+        let messages = Messages::new(config.clone(), timezone);
+        let adapter_availability = messages.adapter_availability();
+
+        let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+        options.set_keep_alive(KEEP_ALIVE);
+        // the broker marks the adapter offline if the connection is lost
+        options.set_last_will(LastWill::new(
+            &adapter_availability,
+            OFFLINE,
+            QoS::AtLeastOnce,
+            true,
+        ));
+        if let Some(username) = &config.username {
+            options.set_credentials(username, config.password.clone().unwrap_or_default());
+        }
+
+        let (client, mut event_loop) = AsyncClient::new(options, PUBLISH_QUEUE);
+        let unacknowledged = Arc::new(Unacknowledged::default());
+        let announcer = client.clone();
+        let acknowledged = unacknowledged.clone();
+        let broker = format!("{}:{}", config.host, config.port);
+        tokio::spawn(async move {
+            loop {
+                match event_loop.poll().await {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        eprintln!("[MQTT] connected to {}", broker);
+                        // nobody waits for the acknowledgement, but it is still counted
+                        if let Err(e) = acknowledged.queue(|| {
+                            announcer.try_publish(
+                                &adapter_availability,
+                                QoS::AtLeastOnce,
+                                true,
+                                ONLINE,
+                            )
+                        }) {
+                            eprintln!("[MQTT] could not announce the adapter: {}", e);
+                        }
+                    }
+                    Ok(Event::Incoming(Packet::PubAck(_))) => acknowledged.acknowledge(),
+                    Ok(_) => {}
+                    Err(e) => {
+                        eprintln!(
+                            "[MQTT] {}: {}, reconnecting in {}s",
+                            broker,
+                            e,
+                            RECONNECT_INTERVAL.as_secs()
+                        );
+                        tokio::time::sleep(RECONNECT_INTERVAL).await;
+                    }
+                }
+            }
+        });
+
+        Self {
+            client,
+            messages,
+            unacknowledged,
+        }
+    }
+
+    /// publish all messages of the report, waiting until the broker acknowledged them
+    async fn publish(&self, report: Report) -> Result<(), MqttError> {
+        let (publications, discovered) = self
+            .messages
+            .of_report(&report)
+            .context(CouldNotSerializeReportSnafu)?;
+
+        let mut acknowledgements = Vec::new();
+        for publication in publications {
+            let acknowledgement = self
+                .unacknowledged
+                .queue(|| {
+                    self.client.try_publish(
+                        publication.topic.clone(),
+                        QoS::AtLeastOnce,
+                        publication.retain,
+                        publication.payload,
+                    )
+                })
+                .context(CouldNotPublishSnafu {
+                    topic: publication.topic.clone(),
+                })?;
+            acknowledgements.push((publication.topic, acknowledgement));
+        }
+        for (topic, acknowledgement) in acknowledgements {
+            if !matches!(
+                tokio::time::timeout(ACKNOWLEDGE_TIMEOUT, acknowledgement).await,
+                Ok(Ok(()))
+            ) {
+                return NotAcknowledgedSnafu { topic }.fail();
+            }
+        }
+
+        if let Some(device) = discovered {
+            self.messages.mark_discovered(device);
+        }
+        Ok(())
+    }
+}
+
+/// the messages published but not yet acknowledged by the broker, oldest first
+/// the broker acknowledges the messages in the order it received them, and after reconnecting
+/// the client sends the unacknowledged ones again in their order, so every acknowledgement
+/// belongs to the oldest message
+#[derive(Default)]
+struct Unacknowledged(Mutex<VecDeque<oneshot::Sender<()>>>);
+
+impl Unacknowledged {
+    /// publish a message, returning what completes once the broker acknowledged it
+    fn queue(
+        &self,
+        publish: impl FnOnce() -> Result<(), ClientError>,
+    ) -> Result<oneshot::Receiver<()>, ClientError> {
+        // locked while publishing, so the messages are kept in the order they are sent
+        let mut unacknowledged = self.0.lock().expect("not poisoned");
+        publish()?;
+        let (sender, receiver) = oneshot::channel();
+        unacknowledged.push_back(sender);
+        Ok(receiver)
+    }
+
+    /// the broker acknowledged the oldest message
+    fn acknowledge(&self) {
+        if let Some(sender) = self.0.lock().expect("not poisoned").pop_front() {
+            // whoever published it may have stopped waiting
+            let _ = sender.send(());
+        }
+    }
+}
+
+/// builds the messages to publish for the reports
+struct Messages {
+    config: MqttConfig,
+    timezone: Tz,
+    /// controllers announced to Home Assistant already
+    discovered: Mutex<HashSet<String>>,
+}
+
+impl Messages {
+    fn new(config: MqttConfig, timezone: Tz) -> Self {
+        Self {
+            config,
+            timezone,
+            discovered: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// the messages of the report, and the controller announced by them
+    fn of_report(
+        &self,
+        report: &Report,
+    ) -> Result<(Vec<Publication>, Option<String>), serde_json::Error> {
+        let record = Record::from_report(report, self.timezone);
+        let topics = &self.config.topics;
+        let device = record.device.clone();
+        let mut publications = Vec::new();
+
+        let firmware_version = match report {
+            Report::Activity(..) => {
+                publications.push(self.message(&topics.activity, &device, &record, false)?);
+                None
+            }
+            Report::Crash(_) => {
+                publications.push(self.message(&topics.crash, &device, &record, false)?);
+                None
+            }
+            Report::Identity(identity) => {
+                publications.push(self.message(&topics.identity, &device, &record, true)?);
+                Some(identity.firmware_version.as_str())
+            }
+            Report::Status(status) => {
+                let state = Record {
+                    uptime: None,
+                    unacknowledged: None,
+                    ..Record::from_report(report, self.timezone)
+                };
+                publications.push(self.message(&topics.state, &device, &state, true)?);
+                // a controller found offline did not send a heartbeat
+                if status.online {
+                    let heartbeat = Record {
+                        kind: "heartbeat",
+                        mode: None,
+                        online: None,
+                        ..Record::from_report(report, self.timezone)
+                    };
+                    publications.push(self.message(
+                        &topics.heartbeat,
+                        &device,
+                        &heartbeat,
+                        false,
+                    )?);
+                }
+                publications.push(Publication {
+                    topic: self.topic(&topics.availability, &device),
+                    payload: if status.online { ONLINE } else { OFFLINE }.to_string(),
+                    retain: true,
+                });
+                None
+            }
+        };
+
+        // the identity is announced again, as it carries the firmware version
+        let announce = self.config.discovery
+            && (firmware_version.is_some()
+                || !self
+                    .discovered
+                    .lock()
+                    .expect("not poisoned")
+                    .contains(&device));
+        if !announce {
+            return Ok((publications, None));
+        }
+
+        publications.extend(self.discovery(&device, firmware_version));
+        Ok((publications, Some(device)))
+    }
+
+    fn mark_discovered(&self, device: String) {
+        self.discovered.lock().expect("not poisoned").insert(device);
+    }
+
+    fn message(
+        &self,
+        template: &str,
+        device: &str,
+        record: &Record,
+        retain: bool,
+    ) -> Result<Publication, serde_json::Error> {
+        Ok(Publication {
+            topic: self.topic(template, device),
+            payload: record.to_json()?,
+            retain,
+        })
+    }
+
+    /// the topic of the template for the device
+    fn topic(&self, template: &str, device: &str) -> String {
+        template
+            .replace("{prefix}", &self.config.topic_prefix)
+            .replace("{device}", &topic_level(device))
+    }
+
+    fn adapter_availability(&self) -> String {
+        self.topic(&self.config.topics.adapter_availability, "")
+    }
+
+    /// Home Assistant discovery messages of all entities of the controller
+    fn discovery(&self, device: &str, firmware_version: Option<&str>) -> Vec<Publication> {
+        let topics = &self.config.topics;
+        let id = format!("keret_{}", topic_level(device));
+        let mut device_info = json!({
+            "identifiers": [id],
+            "name": format!("keret {}", device),
+            "manufacturer": "keret",
+            "model": "micro:bit",
+        });
+        if let Some(firmware_version) = firmware_version {
+            device_info["sw_version"] = json!(firmware_version);
+        }
+        let adapter = json!({ "topic": self.adapter_availability() });
+        let controller = json!({ "topic": self.topic(&topics.availability, device) });
+
+        let entities = [
+            (
+                "sensor",
+                "activity",
+                json!({
+                    "name": "Last activity",
+                    "state_topic": self.topic(&topics.activity, device),
+                    "value_template": "{{ value_json.duration }}",
+                    "json_attributes_topic": self.topic(&topics.activity, device),
+                    "device_class": "duration",
+                    "unit_of_measurement": "s",
+                }),
+            ),
+            (
+                "sensor",
+                "category",
+                json!({
+                    "name": "Last category",
+                    "state_topic": self.topic(&topics.activity, device),
+                    "value_template": "{{ value_json.category }}",
+                }),
+            ),
+            (
+                "sensor",
+                "mode",
+                json!({
+                    "name": "Mode",
+                    "state_topic": self.topic(&topics.state, device),
+                    "value_template": "{{ value_json.mode }}",
+                    "device_class": "enum",
+                    "options": ["idle", "running", "error"],
+                }),
+            ),
+            (
+                "binary_sensor",
+                "online",
+                json!({
+                    "name": "Online",
+                    "state_topic": self.topic(&topics.state, device),
+                    "value_template": "{{ 'ON' if value_json.online else 'OFF' }}",
+                    "device_class": "connectivity",
+                    "entity_category": "diagnostic",
+                }),
+            ),
+            (
+                "sensor",
+                "uptime",
+                json!({
+                    "name": "Uptime",
+                    "state_topic": self.topic(&topics.heartbeat, device),
+                    "value_template": "{{ value_json.uptime }}",
+                    "device_class": "duration",
+                    "unit_of_measurement": "s",
+                    "entity_category": "diagnostic",
+                }),
+            ),
+            (
+                "sensor",
+                "unacknowledged",
+                json!({
+                    "name": "Unacknowledged reports",
+                    "state_topic": self.topic(&topics.heartbeat, device),
+                    "value_template": "{{ value_json.unacknowledged }}",
+                    "entity_category": "diagnostic",
+                }),
+            ),
+        ];
+
+        entities
+            .into_iter()
+            .map(|(component, object, mut payload)| {
+                payload["unique_id"] = json!(format!("{}_{}", id, object));
+                payload["device"] = device_info.clone();
+                // whether the controller is online is known while it is offline as well
+                payload["availability"] = if object == "online" {
+                    json!([adapter])
+                } else {
+                    json!([adapter, controller])
+                };
+                payload["availability_mode"] = json!("all");
+                Publication {
+                    topic: format!(
+                        "{}/{}/{}/{}/config",
+                        self.config.discovery_prefix, component, id, object
+                    ),
+                    payload: payload.to_string(),
+                    retain: true,
+                }
+            })
+            .collect()
+    }
+}
+
+/// the device as a single topic level, without separators and wildcards
+fn topic_level(device: &str) -> String {
+    device.trim_start_matches('/').replace(['/', '+', '#'], "_")
+}
+
+#[async_trait]
+impl crate::app_service::ports::ReportMessaging for MqttSink {
+    type Error = MqttError;
+
+    async fn send(&self, origin: ReportOrigin, report: TrackResult) -> Result<(), Self::Error> {
+        self.publish(Report::Activity(origin, report)).await
+    }
+
+    async fn send_crash_report(&self, report: CrashReport) -> Result<(), Self::Error> {
+        self.publish(Report::Crash(report)).await
+    }
+
+    async fn send_device_identity(&self, identity: DeviceIdentity) -> Result<(), Self::Error> {
+        self.publish(Report::Identity(identity)).await
+    }
+
+    async fn send_device_status(&self, status: DeviceStatus) -> Result<(), Self::Error> {
+        self.publish(Report::Status(status)).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::DeviceMode;
+    use std::time::SystemTime;
+
+    const SERIAL_NUMBER: u64 = 0x0123_4567_89AB_CDEF;
+    const DEVICE: &str = "0123456789abcdef";
+
+    fn messages(discovery: bool) -> Messages {
+        let config: crate::config::Config = toml::from_str(&format!(
+            "[[sinks]]\ntype = \"mqtt\"\nhost = \"localhost\"\ndiscovery = {}\n",
+            discovery
+        ))
+        .unwrap();
+        match &config.sinks[0].kind {
+            crate::config::SinkKind::Mqtt(mqtt) => Messages::new((**mqtt).clone(), Tz::UTC),
+            _ => unreachable!(),
+        }
+    }
+
+    fn status(online: bool) -> Report {
+        Report::Status(DeviceStatus {
+            serial_number: SERIAL_NUMBER,
+            last_seen: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            online,
+            mode: DeviceMode::Running,
+            uptime: 120,
+            unacknowledged: 1,
+        })
+    }
+
+    fn crash() -> Report {
+        Report::Crash(CrashReport {
+            origin: ReportOrigin {
+                serial_number: SERIAL_NUMBER,
+                boot_id: 0xB007,
+                sequence: 4,
+            },
+            received_at: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            message: "oops".to_string(),
+            file: "src/main.rs".to_string(),
+            line: 42,
+        })
+    }
+
+    fn topics(publications: &[Publication]) -> Vec<&str> {
+        publications
+            .iter()
+            .map(|publication| publication.topic.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn of_report_status_publishes_retained_state_heartbeat_and_availability() {
+        // arrange
+        let messages = messages(false);
+
+        // act
+        let (actual, _) = messages.of_report(&status(true)).unwrap();
+
+        // assert
+        assert_eq!(
+            topics(&actual),
+            vec![
+                "keret/0123456789abcdef/state",
+                "keret/0123456789abcdef/heartbeat",
+                "keret/0123456789abcdef/availability",
+            ]
+        );
+        assert_eq!(
+            actual[0].payload,
+            "{\"kind\":\"status\",\"time\":\"2023-11-14T22:13:20Z\",\
+             \"device\":\"0123456789abcdef\",\"mode\":\"running\",\"online\":true}"
+        );
+        assert!(actual[0].retain);
+        assert!(!actual[1].retain);
+        assert!(actual[1].payload.contains("\"kind\":\"heartbeat\""));
+        assert!(actual[1].payload.contains("\"uptime\":120"));
+        assert_eq!(actual[2].payload, ONLINE);
+    }
+
+    #[test]
+    fn of_report_offline_status_publishes_no_heartbeat() {
+        // arrange
+        let messages = messages(false);
+
+        // act
+        let (actual, _) = messages.of_report(&status(false)).unwrap();
+
+        // assert
+        assert_eq!(
+            topics(&actual),
+            vec![
+                "keret/0123456789abcdef/state",
+                "keret/0123456789abcdef/availability",
+            ]
+        );
+        assert_eq!(actual[1].payload, OFFLINE);
+    }
+
+    #[test]
+    fn of_report_first_status_announces_controller_once() {
+        // arrange
+        let messages = messages(true);
+
+        // act
+        let (first, discovered) = messages.of_report(&status(true)).unwrap();
+        messages.mark_discovered(discovered.unwrap());
+        let (second, _) = messages.of_report(&status(true)).unwrap();
+
+        // assert
+        assert_eq!(first.len(), 3 + 6);
+        assert!(
+            topics(&first).contains(&"homeassistant/sensor/keret_0123456789abcdef/activity/config")
+        );
+        assert_eq!(second.len(), 3);
+    }
+
+    #[test]
+    fn of_report_identity_announces_firmware_version() {
+        // arrange
+        let messages = messages(true);
+        messages.mark_discovered(DEVICE.to_string());
+        let identity = Report::Identity(DeviceIdentity {
+            serial_number: SERIAL_NUMBER,
+            firmware_version: "0.1.0".to_string(),
+            protocol_version: 5,
+        });
+
+        // act
+        let (actual, _) = messages.of_report(&identity).unwrap();
+
+        // assert
+        assert_eq!(actual[0].topic, "keret/0123456789abcdef/identity");
+        assert!(actual[0].retain);
+        let discovery: serde_json::Value = serde_json::from_str(&actual[1].payload).unwrap();
+        assert_eq!(discovery["device"]["sw_version"], "0.1.0");
+        assert_eq!(discovery["unique_id"], "keret_0123456789abcdef_activity");
+        assert_eq!(
+            discovery["availability"][0]["topic"],
+            "keret/adapter/availability"
+        );
+    }
+
+    #[test]
+    fn of_report_crash_announces_controller() {
+        // arrange
+        let messages = messages(true);
+
+        // act
+        let (actual, discovered) = messages.of_report(&crash()).unwrap();
+
+        // assert
+        assert_eq!(topics(&actual)[0], "keret/0123456789abcdef/crash");
+        assert_eq!(discovered, Some("0123456789abcdef".to_string()));
+    }
+
+    #[test]
+    fn acknowledge_completes_oldest_message() {
+        // arrange
+        let unacknowledged = Unacknowledged::default();
+        let mut first = unacknowledged.queue(|| Ok(())).unwrap();
+        let mut second = unacknowledged.queue(|| Ok(())).unwrap();
+
+        // act
+        unacknowledged.acknowledge();
+
+        // assert
+        assert_eq!(first.try_recv(), Ok(()));
+        assert_eq!(second.try_recv(), Err(oneshot::error::TryRecvError::Empty));
+    }
+
+    #[test]
+    fn queue_failing_to_publish_awaits_no_acknowledgement() {
+        // arrange
+        let unacknowledged = Unacknowledged::default();
+        let failed = unacknowledged.queue(|| {
+            Err(ClientError::TryRequest(rumqttc::Request::PingReq(
+                rumqttc::PingReq,
+            )))
+        });
+        let mut published = unacknowledged.queue(|| Ok(())).unwrap();
+
+        // act
+        unacknowledged.acknowledge();
+
+        // assert
+        assert!(failed.is_err());
+        assert_eq!(published.try_recv(), Ok(()));
+    }
+
+    /// run with `cargo test -- --ignored` while a broker listens on localhost:1883, e.g.
+    /// `docker run --rm -p 1883:1883 eclipse-mosquitto mosquitto -c /mosquitto-no-auth.conf`
+    #[tokio::test]
+    #[ignore = "needs an MQTT broker on localhost:1883"]
+    async fn publish_status_is_retained_by_broker() {
+        // arrange
+        let config: crate::config::Config = toml::from_str(
+            "[[sinks]]\ntype = \"mqtt\"\nhost = \"localhost\"\nclient_id = \"keret-test\"\n",
+        )
+        .unwrap();
+        let crate::config::SinkKind::Mqtt(mqtt) = &config.sinks[0].kind else {
+            unreachable!()
+        };
+        let sink = MqttSink::connect(mqtt, Tz::UTC);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        // act
+        sink.publish(status(true)).await.unwrap();
+
+        // assert -> a client subscribing afterwards gets the retained state
+        let options = MqttOptions::new("keret-test-subscriber", "localhost", 1883);
+        let (client, mut event_loop) = AsyncClient::new(options, 10);
+        client
+            .subscribe("keret/0123456789abcdef/state", QoS::AtLeastOnce)
+            .await
+            .unwrap();
+        let payload = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Ok(Event::Incoming(Packet::Publish(publish))) = event_loop.poll().await {
+                    return publish.payload;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert!(String::from_utf8_lossy(&payload).contains("\"mode\":\"running\""));
+    }
+}
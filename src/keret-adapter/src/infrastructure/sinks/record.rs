@@ -0,0 +1,295 @@
+use crate::config::RecordFormat;
+use crate::model::Report;
+use chrono::{DateTime, SecondsFormat, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+
+/// names of the values of a record, in the order of the CSV columns
+const COLUMNS: [&str; 16] = [
+    "kind",
+    "time",
+    "device",
+    "boot_id",
+    "sequence",
+    "category",
+    "duration",
+    "message",
+    "file",
+    "line",
+    "firmware_version",
+    "protocol_version",
+    "mode",
+    "online",
+    "uptime",
+    "unacknowledged",
+];
+
+/// prefix of the environment variables passed to commands
+const ENVIRONMENT_PREFIX: &str = "KERET_";
+
+/// a report flattened into named values, as written to files, the standard output, MQTT and
+/// commands. Only the values fitting the kind of report are set
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct Record {
+    pub(crate) kind: &'static str,
+    /// RFC 3339 in the configured timezone
+    pub(crate) time: String,
+    pub(crate) device: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) boot_id: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) sequence: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) category: Option<u8>,
+    /// tracked time in seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) duration: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) line: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) firmware_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) protocol_version: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) mode: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) online: Option<bool>,
+    /// seconds since the controller booted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) uptime: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) unacknowledged: Option<u8>,
+}
+
+impl Record {
+    /// flatten the report, showing its time in the timezone
+    pub(crate) fn from_report(report: &Report, timezone: Tz) -> Self {
+        match report {
+            Report::Activity(origin, result) => {
+                // prefer the time the controller finished, the report may have been delayed
+                let finished_at = result
+                    .finished_at()
+                    .and_then(|seconds| DateTime::from_timestamp(seconds as i64, 0))
+                    .unwrap_or_else(Utc::now);
+                Self {
+                    kind: "activity",
+                    time: local_time(finished_at, timezone),
+                    device: origin.device(),
+                    boot_id: Some(origin.boot_id),
+                    sequence: Some(origin.sequence),
+                    category: Some(result.category()),
+                    duration: Some(result.duration()),
+                    ..Self::default()
+                }
+            }
+            Report::Crash(crash) => Self {
+                kind: "crash",
+                time: local_time(crash.received_at.into(), timezone),
+                device: crash.origin.device(),
+                boot_id: Some(crash.origin.boot_id),
+                sequence: Some(crash.origin.sequence),
+                message: Some(crash.message.clone()),
+                file: Some(crash.file.clone()),
+                line: Some(crash.line),
+                ..Self::default()
+            },
+            Report::Identity(identity) => Self {
+                kind: "identity",
+                time: local_time(Utc::now(), timezone),
+                device: format!("{:016x}", identity.serial_number),
+                firmware_version: Some(identity.firmware_version.clone()),
+                protocol_version: Some(identity.protocol_version),
+                ..Self::default()
+            },
+            Report::Status(status) => Self {
+                kind: "status",
+                time: local_time(status.last_seen.into(), timezone),
+                device: format!("{:016x}", status.serial_number),
+                mode: Some(status.mode.name()),
+                online: Some(status.online),
+                uptime: Some(status.uptime),
+                unacknowledged: Some(status.unacknowledged),
+                ..Self::default()
+            },
+        }
+    }
+
+    pub(crate) fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+
+    /// the record as a single line in the format, without line break
+    pub(crate) fn to_line(&self, format: RecordFormat) -> Result<String, serde_json::Error> {
+        match format {
+            RecordFormat::Jsonl => self.to_json(),
+            RecordFormat::Csv => Ok(self
+                .values()
+                .iter()
+                .map(|value| escape_csv(value))
+                .collect::<Vec<_>>()
+                .join(",")),
+        }
+    }
+
+    /// the values set, as environment variables (e.g. `KERET_DURATION`)
+    pub(crate) fn environment(&self) -> Vec<(String, String)> {
+        COLUMNS
+            .iter()
+            .zip(self.values())
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(name, value)| {
+                (
+                    format!("{}{}", ENVIRONMENT_PREFIX, name.to_uppercase()),
+                    value,
+                )
+            })
+            .collect()
+    }
+
+    /// all values in the order of the columns, empty if not set
+    fn values(&self) -> [String; 16] {
+        [
+            self.kind.to_string(),
+            self.time.clone(),
+            self.device.clone(),
+            text(&self.boot_id),
+            text(&self.sequence),
+            text(&self.category),
+            text(&self.duration),
+            text(&self.message),
+            text(&self.file),
+            text(&self.line),
+            text(&self.firmware_version),
+            text(&self.protocol_version),
+            text(&self.mode),
+            text(&self.online),
+            text(&self.uptime),
+            text(&self.unacknowledged),
+        ]
+    }
+}
+
+/// the header line of the CSV format, without line break
+pub(crate) fn csv_header() -> String {
+    COLUMNS.join(",")
+}
+
+fn local_time(time: DateTime<Utc>, timezone: Tz) -> String {
+    time.with_timezone(&timezone)
+        .to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn text<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map(ToString::to_string).unwrap_or_default()
+}
+
+/// quote the value if it would break the CSV line
+fn escape_csv(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::{CrashReport, ReportOrigin, TrackResult};
+    use std::time::{Duration, SystemTime};
+
+    fn activity() -> Report {
+        let origin = ReportOrigin {
+            serial_number: 0x0123_4567_89AB_CDEF,
+            boot_id: 0xB007,
+            sequence: 3,
+        };
+        let result = TrackResult::from(60)
+            .with_category(2)
+            .with_finished_at(Some(1_700_000_000));
+        Report::Activity(origin, result)
+    }
+
+    #[test]
+    fn from_report_shows_time_in_timezone() {
+        // act
+        let actual = Record::from_report(&activity(), Tz::Europe__Berlin);
+
+        // assert
+        assert_eq!(actual.time, "2023-11-14T23:13:20+01:00");
+    }
+
+    #[test]
+    fn to_line_jsonl_leaves_out_values_not_set() {
+        // arrange
+        let record = Record::from_report(&activity(), Tz::UTC);
+
+        // act
+        let actual = record.to_line(RecordFormat::Jsonl).unwrap();
+
+        // assert
+        assert_eq!(
+            actual,
+            "{\"kind\":\"activity\",\"time\":\"2023-11-14T22:13:20Z\",\
+             \"device\":\"0123456789abcdef\",\"boot_id\":45063,\"sequence\":3,\"category\":2,\
+             \"duration\":60}"
+        );
+    }
+
+    #[test]
+    fn to_line_csv_matches_header() {
+        // arrange
+        let record = Record::from_report(&activity(), Tz::UTC);
+
+        // act
+        let actual = record.to_line(RecordFormat::Csv).unwrap();
+
+        // assert
+        assert_eq!(
+            actual,
+            "activity,2023-11-14T22:13:20Z,0123456789abcdef,45063,3,2,60,,,,,,,,,"
+        );
+        assert_eq!(actual.split(',').count(), csv_header().split(',').count());
+    }
+
+    #[test]
+    fn to_line_csv_quotes_message() {
+        // arrange
+        let crash = Report::Crash(CrashReport {
+            origin: ReportOrigin {
+                serial_number: 0x0123_4567_89AB_CDEF,
+                boot_id: 0xB007,
+                sequence: 4,
+            },
+            received_at: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            message: "index 3, \"len\" 2".to_string(),
+            file: "src/main.rs".to_string(),
+            line: 42,
+        });
+        let record = Record::from_report(&crash, Tz::UTC);
+
+        // act
+        let actual = record.to_line(RecordFormat::Csv).unwrap();
+
+        // assert
+        assert!(actual.contains(",\"index 3, \"\"len\"\" 2\",src/main.rs,42,"));
+    }
+
+    #[test]
+    fn environment_holds_values_set() {
+        // arrange
+        let record = Record::from_report(&activity(), Tz::UTC);
+
+        // act
+        let actual = record.environment();
+
+        // assert
+        assert_eq!(actual.len(), 7);
+        assert!(actual.contains(&("KERET_DURATION".to_string(), "60".to_string())));
+        assert!(actual.contains(&("KERET_KIND".to_string(), "activity".to_string())));
+    }
+}
@@ -0,0 +1,398 @@
+use crate::model::Report;
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Snafu)]
+#[allow(clippy::enum_variant_names)]
+pub(crate) enum SpoolError {
+    #[snafu(display("Could not open the spool {path}"))]
+    CouldNotOpenSpool {
+        path: String,
+        source: std::io::Error,
+    },
+    #[snafu(display("Could not write to the spool {path}"))]
+    CouldNotWriteSpool {
+        path: String,
+        source: std::io::Error,
+    },
+    #[snafu(display("Could not serialize the report for the spool"))]
+    CouldNotSerializeReport { source: serde_json::Error },
+    #[snafu(display("Could not put the refused report aside in {path}"))]
+    CouldNotWriteRejected {
+        path: String,
+        source: std::io::Error,
+    },
+}
+
+/// a line of the journal: a report was received, or a report was forwarded
+#[derive(Serialize, Deserialize)]
+enum JournalEntry {
+    Received { id: u64, report: Report },
+    Forwarded { id: u64 },
+}
+
+/// a line of the file of rejected reports: the report and why it was refused
+#[derive(Serialize)]
+struct RejectedReport<'a> {
+    /// seconds since the unix epoch
+    rejected_at: u64,
+    reason: &'a str,
+    report: &'a Report,
+}
+
+/// spool kept in an append-only journal file, one JSON entry per line
+/// every change is synced to disk before it is confirmed, so the reports survive a restart
+/// of the adapter (or the device it runs on). The journal is compacted when opened and
+/// truncated whenever all reports were forwarded, so it does not grow forever.
+/// reports refused for good are appended to a file next to it, ending in `.rejected`
+pub(crate) struct FileSpool {
+    path: PathBuf,
+    /// where the reports refused for good are put aside
+    rejected: PathBuf,
+    journal: File,
+    reports: VecDeque<(u64, Report)>,
+    next_id: u64,
+}
+
+impl FileSpool {
+    /// open the spool, restoring all reports not forwarded before
+    pub(crate) fn open(path: &Path) -> Result<Self, SpoolError> {
+        let reports = replay(path)?;
+        let next_id = reports.back().map_or(0, |(id, _)| id + 1);
+        if !reports.is_empty() {
+            eprintln!(
+                "[SPOOL] {} report(s) from before the restart still to be forwarded",
+                reports.len()
+            );
+        }
+
+        let journal = compact(path, &reports)?;
+
+        let mut rejected = path.as_os_str().to_owned();
+        rejected.push(".rejected");
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            rejected: PathBuf::from(rejected),
+            journal,
+            reports,
+            next_id,
+        })
+    }
+
+    /// append the entry to the journal and wait until it is on disk
+    fn append(&mut self, entry: &JournalEntry) -> Result<(), SpoolError> {
+        let mut line = serde_json::to_vec(entry).context(CouldNotSerializeReportSnafu)?;
+        line.push(b'\n');
+
+        self.journal
+            .write_all(&line)
+            .and_then(|()| self.journal.sync_data())
+            .context(CouldNotWriteSpoolSnafu {
+                path: self.path.display().to_string(),
+            })
+    }
+}
+
+/// read the journal, returning the reports received but not forwarded
+fn replay(path: &Path) -> Result<VecDeque<(u64, Report)>, SpoolError> {
+    let mut reports = VecDeque::new();
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(reports),
+        Err(e) => {
+            return Err(e).context(CouldNotOpenSpoolSnafu {
+                path: path.display().to_string(),
+            })
+        }
+    };
+
+    for line in BufReader::new(file).lines() {
+        let line = line.context(CouldNotOpenSpoolSnafu {
+            path: path.display().to_string(),
+        })?;
+        match serde_json::from_str(&line) {
+            Ok(JournalEntry::Received { id, report }) => reports.push_back((id, report)),
+            Ok(JournalEntry::Forwarded { id }) => reports.retain(|(kept, _)| *kept != id),
+            // the adapter stopped while writing, the entry never got confirmed
+            Err(e) => eprintln!("[SPOOL] skipping incomplete entry in the journal: {}", e),
+        }
+    }
+
+    Ok(reports)
+}
+
+/// replace the journal by one holding only the given reports, opened for appending
+/// the new journal is written aside first, so a crash leaves either the old or the new one
+fn compact(path: &Path, reports: &VecDeque<(u64, Report)>) -> Result<File, SpoolError> {
+    let mut content = Vec::new();
+    for (id, report) in reports {
+        let entry = JournalEntry::Received {
+            id: *id,
+            report: report.clone(),
+        };
+        serde_json::to_writer(&mut content, &entry).context(CouldNotSerializeReportSnafu)?;
+        content.push(b'\n');
+    }
+
+    // appended to the whole name, another file with the same stem may be written next to it
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    File::create(&temporary)
+        .and_then(|mut file| file.write_all(&content).and_then(|()| file.sync_all()))
+        .and_then(|()| std::fs::rename(&temporary, path))
+        .and_then(|()| OpenOptions::new().append(true).open(path))
+        .context(CouldNotOpenSpoolSnafu {
+            path: path.display().to_string(),
+        })
+}
+
+impl crate::app_service::ports::ReportSpool for FileSpool {
+    type Error = SpoolError;
+
+    fn push(&mut self, report: Report) -> Result<(), Self::Error> {
+        let id = self.next_id;
+        self.append(&JournalEntry::Received {
+            id,
+            report: report.clone(),
+        })?;
+
+        self.next_id += 1;
+        self.reports.push_back((id, report));
+        Ok(())
+    }
+
+    fn oldest(&self) -> Option<Report> {
+        self.reports.front().map(|(_, report)| report.clone())
+    }
+
+    fn oldest_n(&self, count: usize) -> Vec<Report> {
+        self.reports
+            .iter()
+            .take(count)
+            .map(|(_, report)| report.clone())
+            .collect()
+    }
+
+    fn remove_oldest(&mut self) -> Result<(), Self::Error> {
+        let Some((id, _)) = self.reports.front() else {
+            return Ok(());
+        };
+
+        if self.reports.len() == 1 {
+            // nothing left to keep, start the journal afresh
+            self.journal
+                .set_len(0)
+                .and_then(|()| self.journal.sync_data())
+                .context(CouldNotWriteSpoolSnafu {
+                    path: self.path.display().to_string(),
+                })?;
+        } else {
+            self.append(&JournalEntry::Forwarded { id: *id })?;
+        }
+
+        self.reports.pop_front();
+        Ok(())
+    }
+
+    fn reject_oldest(&mut self, reason: &str) -> Result<(), Self::Error> {
+        let Some((_, report)) = self.reports.front() else {
+            return Ok(());
+        };
+
+        let rejected_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        let mut line = serde_json::to_vec(&RejectedReport {
+            rejected_at,
+            reason,
+            report,
+        })
+        .context(CouldNotSerializeReportSnafu)?;
+        line.push(b'\n');
+        // kept for good before it is removed, so it is never lost in between
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.rejected)
+            .and_then(|mut file| file.write_all(&line).and_then(|()| file.sync_data()))
+            .context(CouldNotWriteRejectedSnafu {
+                path: self.rejected.display().to_string(),
+            })?;
+
+        self.remove_oldest()
+    }
+
+    fn len(&self) -> usize {
+        self.reports.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::app_service::ports::ReportSpool;
+    use crate::model::{ReportOrigin, TrackResult};
+    use tempfile::tempdir;
+
+    fn activity(sequence: u32) -> Report {
+        let origin = ReportOrigin {
+            serial_number: 0x0123_4567_89AB_CDEF,
+            boot_id: 0xB007,
+            sequence,
+        };
+        Report::Activity(origin, TrackResult::from(10))
+    }
+
+    fn sequence_of(report: Option<Report>) -> Option<u32> {
+        match report {
+            Some(Report::Activity(origin, _)) => Some(origin.sequence),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn open_reopened_spool_restores_reports_in_order() {
+        // arrange
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("spool.jsonl");
+        let mut spool = FileSpool::open(&path).unwrap();
+        spool.push(activity(0)).unwrap();
+        spool.push(activity(1)).unwrap();
+        drop(spool);
+
+        // act
+        let mut actual = FileSpool::open(&path).unwrap();
+
+        // assert
+        assert_eq!(actual.len(), 2);
+        assert_eq!(sequence_of(actual.oldest()), Some(0));
+        actual.remove_oldest().unwrap();
+        assert_eq!(sequence_of(actual.oldest()), Some(1));
+    }
+
+    #[test]
+    fn open_reopened_spool_forgets_forwarded_reports() {
+        // arrange
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("spool.jsonl");
+        let mut spool = FileSpool::open(&path).unwrap();
+        spool.push(activity(0)).unwrap();
+        spool.push(activity(1)).unwrap();
+        spool.remove_oldest().unwrap();
+        drop(spool);
+
+        // act
+        let actual = FileSpool::open(&path).unwrap();
+
+        // assert
+        assert_eq!(actual.len(), 1);
+        assert_eq!(sequence_of(actual.oldest()), Some(1));
+    }
+
+    #[test]
+    fn reject_oldest_puts_report_aside_and_forgets_it() {
+        // arrange
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("spool.jsonl");
+        let rejected = PathBuf::from(format!("{}.rejected", path.display()));
+        let mut spool = FileSpool::open(&path).unwrap();
+        spool.push(activity(0)).unwrap();
+        spool.push(activity(1)).unwrap();
+
+        // act
+        spool.reject_oldest("malformed").unwrap();
+
+        // assert
+        let lines = std::fs::read_to_string(&rejected).unwrap();
+        let entry: serde_json::Value = serde_json::from_str(lines.trim_end()).unwrap();
+        assert_eq!(entry["reason"], "malformed");
+        assert_eq!(entry["report"]["Activity"][0]["sequence"], 0);
+        drop(spool);
+        let reopened = FileSpool::open(&path).unwrap();
+        assert_eq!(reopened.len(), 1);
+        assert_eq!(sequence_of(reopened.oldest()), Some(1));
+    }
+
+    #[test]
+    fn open_journal_with_incomplete_entry_restores_complete_ones() {
+        // arrange
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("spool.jsonl");
+        let mut spool = FileSpool::open(&path).unwrap();
+        spool.push(activity(0)).unwrap();
+        drop(spool);
+        let mut journal = OpenOptions::new().append(true).open(&path).unwrap();
+        journal.write_all(b"{\"Received\":{\"id\":1,\"rep").unwrap();
+
+        // act
+        let mut actual = FileSpool::open(&path).unwrap();
+
+        // assert -> the incomplete entry is dropped, so it does not garble the next one
+        assert_eq!(actual.len(), 1);
+        actual.push(activity(1)).unwrap();
+        drop(actual);
+        assert_eq!(FileSpool::open(&path).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn open_keeps_file_with_same_stem_next_to_spool() {
+        // arrange
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("keret-adapter.spool");
+        let neighbour = dir.path().join("keret-adapter.tmp");
+        std::fs::write(&neighbour, "metrics").unwrap();
+        let mut spool = FileSpool::open(&path).unwrap();
+        spool.push(activity(0)).unwrap();
+        drop(spool);
+
+        // act
+        let actual = FileSpool::open(&path).unwrap();
+
+        // assert
+        assert_eq!(actual.len(), 1);
+        assert_eq!(std::fs::read_to_string(&neighbour).unwrap(), "metrics");
+    }
+
+    #[test]
+    fn remove_oldest_of_last_report_truncates_journal() {
+        // arrange
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("spool.jsonl");
+        let mut spool = FileSpool::open(&path).unwrap();
+        spool.push(activity(0)).unwrap();
+
+        // act
+        spool.remove_oldest().unwrap();
+
+        // assert
+        assert_eq!(spool.len(), 0);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn oldest_n_returns_reports_kept_longest_in_order() {
+        // arrange
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("spool.jsonl");
+        let mut spool = FileSpool::open(&path).unwrap();
+        for sequence in 0..3 {
+            spool.push(activity(sequence)).unwrap();
+        }
+
+        // act
+        let actual = spool.oldest_n(2);
+
+        // assert
+        let sequences: Vec<_> = actual
+            .into_iter()
+            .map(|report| sequence_of(Some(report)))
+            .collect();
+        assert_eq!(sequences, vec![Some(0), Some(1)]);
+    }
+}
@@ -1,53 +1,193 @@
 mod app_service;
+mod config;
 mod infrastructure;
 mod model;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use config::{Auth, Config, ConfigError};
 use std::path::PathBuf;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+/// how often the metrics file is rewritten
+const METRICS_INTERVAL: Duration = Duration::from_secs(15);
+
+/// the options take precedence over the environment, which takes precedence over the
+/// configuration file
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Cli {
-    /// Device Path (/dev/...)
-    device: PathBuf,
+    #[command(subcommand)]
+    command: Option<Tool>,
+
+    /// configuration file (TOML), see keret-adapter.example.toml
+    #[arg(long, env = "KERET_ADAPTER_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Device Path (/dev/...), may be given multiple times
+    /// all micro:bits are found by their USB id if not given, including those plugged in later
+    #[arg(long, env = "KERET_ADAPTER_DEVICE", value_delimiter = ',')]
+    device: Vec<String>,
 
     /// full URL to post to
-    url: String,
+    #[arg(env = "KERET_ADAPTER_URL")]
+    url: Option<String>,
+
+    /// token sent as bearer token to keret-service
+    #[arg(long, env = "KERET_ADAPTER_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+
+    /// file to write metrics to in prometheus text format (e.g. for the node exporter)
+    #[arg(long, env = "KERET_ADAPTER_METRICS_FILE")]
+    metrics_file: Option<PathBuf>,
+
+    /// file keeping the reports until they were forwarded, surviving restarts
+    /// [default: keret-adapter.spool]
+    #[arg(long, env = "KERET_ADAPTER_SPOOL_FILE")]
+    spool_file: Option<PathBuf>,
+
+    /// zone the times are shown in, as IANA name (e.g. Europe/Berlin) [default: UTC]
+    #[arg(long, env = "KERET_ADAPTER_TIMEZONE")]
+    timezone: Option<String>,
+}
+
+/// tools to debug a controller, instead of forwarding its reports
+#[derive(Subcommand)]
+enum Tool {
+    /// print every frame received from the device, including malformed ones
+    ///
+    /// nothing is forwarded, nor sent to the controller
+    Monitor {
+        /// Device Path (/dev/...)
+        device: String,
+
+        /// file to record the received bytes to, for `replay`
+        #[arg(long)]
+        capture: Option<PathBuf>,
+    },
+    /// feed a recorded capture through the decoder used for the devices, printing the reports
+    ///
+    /// nothing is forwarded
+    Replay {
+        /// file holding the bytes received from a controller, e.g. recorded by `monitor`
+        capture: PathBuf,
+    },
+}
+
+/// read the configuration file and apply the overrides, failing on invalid settings
+fn configure(cli: Cli) -> Result<Config, ConfigError> {
+    let mut config = match &cli.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+
+    if !cli.device.is_empty() {
+        config.devices.ports = cli.device;
+    }
+    if let Some(url) = cli.url {
+        config.service.url = Some(url);
+    }
+    if let Some(token) = cli.token {
+        config.service.auth = Some(Auth::Bearer { token });
+    }
+    if let Some(metrics_file) = cli.metrics_file {
+        config.metrics.file = Some(metrics_file);
+    }
+    if let Some(spool_file) = cli.spool_file {
+        config.spool.path = spool_file;
+    }
+    if let Some(timezone) = cli.timezone {
+        config.timezone = config::parse_timezone(&timezone)?;
+    }
+
+    config.validate()?;
+    Ok(config)
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cli = Cli::parse();
-    let url = cli.url;
-    let Some(device) = cli.device.to_str() else {
-        eprintln!("--device must be valid UTF-8");
-        std::process::exit(1);
+    let mut cli = Cli::parse();
+    match cli.command.take() {
+        Some(Tool::Monitor { device, capture }) => {
+            if let Err(e) = infrastructure::monitoring::monitor(&device, capture.as_deref()).await {
+                report(&e);
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Tool::Replay { capture }) => {
+            if let Err(e) = infrastructure::monitoring::replay(&capture).await {
+                report(&e);
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        None => {}
+    }
+
+    let config = match configure(cli) {
+        Ok(config) => config,
+        Err(e) => {
+            report(&e);
+            std::process::exit(1);
+        }
     };
+    eprintln!("[CONFIG] showing times in {}", config.timezone);
 
-    let listener = match infrastructure::listening::PortListener::new(device) {
-        Ok(l) => l,
+    let spool = match infrastructure::spool::FileSpool::open(&config.spool.path) {
+        Ok(s) => s,
         Err(e) => {
             report(&e);
             std::process::exit(1);
         }
     };
 
-    let sender = infrastructure::sending::ReportSender::new(url);
-    let mut app_service = app_service::ApplicationService::new(listener, sender);
+    let sinks = match infrastructure::sinks::FanOut::open(&config) {
+        Ok(sinks) => sinks,
+        Err(e) => {
+            report(&e);
+            std::process::exit(1);
+        }
+    };
+    eprintln!("[SINK] forwarding to {}", sinks.names().join(", "));
 
-    loop {
-        tokio::time::sleep(Duration::from_millis(500)).await;
+    // started last, so invalid settings of the spool and sinks fail before any report is taken
+    let selection = config.device_selection();
+    eprintln!("[PORT] listening to {}", selection);
+
+    let listener = infrastructure::devices::DeviceReports::start(selection);
+    let mut app_service = app_service::ApplicationService::new(listener, spool, sinks)
+        .with_backoff(config.retry.initial_backoff, config.retry.max_backoff)
+        .with_batch_size(config.service.batch_size);
+    let mut metrics_written = Instant::now();
 
+    loop {
         match app_service.read_and_forward().await {
             Ok(()) => {}
             Err(e) => {
                 report(&e);
             }
         }
+
+        if let Some(metrics_file) = &config.metrics.file {
+            if metrics_written.elapsed() >= METRICS_INTERVAL {
+                metrics_written = Instant::now();
+                if let Err(e) = infrastructure::metrics::write_metrics(metrics_file) {
+                    report(&e);
+                }
+            }
+        }
     }
 }
 
+/// the error followed by all its causes
+pub(crate) fn error_chain(error: &dyn std::error::Error) -> String {
+    std::iter::successors(Some(error), |e| e.source())
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(": ")
+}
+
 pub fn report<E>(err: &E)
 where
     E: 'static,
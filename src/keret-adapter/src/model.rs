@@ -1,14 +1,193 @@
-#[repr(transparent)]
-pub(crate) struct TrackResult(u64);
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+/// tracked time (in seconds) of an activity in the category chosen on the controller
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct TrackResult {
+    duration: u64,
+    category: u8,
+    /// seconds since the unix epoch when the tracking finished, if the controller knew
+    finished_at: Option<u64>,
+}
+
+impl TrackResult {
+    /// assign the result to the given category
+    pub(crate) fn with_category(mut self, category: u8) -> Self {
+        self.category = category;
+        self
+    }
+
+    pub(crate) fn category(&self) -> u8 {
+        self.category
+    }
+
+    /// tracked time in seconds
+    pub(crate) fn duration(&self) -> u64 {
+        self.duration
+    }
+
+    /// mark when the tracking finished, as calculated by the controller
+    pub(crate) fn with_finished_at(mut self, finished_at: Option<u64>) -> Self {
+        self.finished_at = finished_at;
+        self
+    }
+
+    pub(crate) fn finished_at(&self) -> Option<u64> {
+        self.finished_at
+    }
+}
 
 impl From<u64> for TrackResult {
     fn from(value: u64) -> Self {
-        Self(value)
+        Self {
+            duration: value,
+            category: 0,
+            finished_at: None,
+        }
     }
 }
 
 impl From<TrackResult> for u64 {
     fn from(value: TrackResult) -> Self {
-        value.0
+        value.duration
+    }
+}
+
+/// identifies a report uniquely: the n-th report (sequence) of a boot of a device
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ReportOrigin {
+    /// reports are only taken from identified controllers, their port may change
+    pub(crate) serial_number: u64,
+    pub(crate) boot_id: u32,
+    pub(crate) sequence: u32,
+}
+
+impl ReportOrigin {
+    /// the serial number of the controller, as shown to the outside
+    pub(crate) fn device(&self) -> String {
+        format!("{:016x}", self.serial_number)
+    }
+}
+
+/// sequences of the reports seen of the current boot of a device
+/// the controller resends every report not acknowledged, so they may arrive out of order
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct SeenSequences {
+    boot_id: u32,
+    highest: u32,
+    /// bit n is set if the report n sequences behind the highest one was seen
+    window: u64,
+}
+
+impl SeenSequences {
+    /// sequences further behind the highest one are taken as seen
+    const WINDOW: u32 = u64::BITS;
+
+    pub(crate) fn new(origin: &ReportOrigin) -> Self {
+        Self {
+            boot_id: origin.boot_id,
+            highest: origin.sequence,
+            window: 1,
+        }
+    }
+
+    /// whether the report was seen before
+    pub(crate) fn contains(&self, origin: &ReportOrigin) -> bool {
+        if origin.boot_id != self.boot_id || origin.sequence > self.highest {
+            return false;
+        }
+        let behind = self.highest - origin.sequence;
+        behind >= Self::WINDOW || self.window & (1 << behind) != 0
+    }
+
+    /// remember the report as seen, a report of another boot starts over
+    pub(crate) fn insert(&mut self, origin: &ReportOrigin) {
+        if origin.boot_id != self.boot_id {
+            *self = Self::new(origin);
+            return;
+        }
+        if origin.sequence > self.highest {
+            let ahead = origin.sequence - self.highest;
+            self.window = self.window.checked_shl(ahead).unwrap_or(0);
+            self.highest = origin.sequence;
+        }
+        let behind = self.highest - origin.sequence;
+        if behind < Self::WINDOW {
+            self.window |= 1 << behind;
+        }
+    }
+}
+
+/// information about a panic of the controller
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct CrashReport {
+    /// numbered like the activity reports, the controller repeats it until acknowledged
+    pub(crate) origin: ReportOrigin,
+    /// when the adapter received the report, a spooled one may be forwarded much later
+    pub(crate) received_at: SystemTime,
+    pub(crate) message: String,
+    pub(crate) file: String,
+    pub(crate) line: u32,
+}
+
+/// identity of the controller, announced when it connects
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct DeviceIdentity {
+    pub(crate) serial_number: u64,
+    pub(crate) firmware_version: String,
+    pub(crate) protocol_version: u8,
+}
+
+/// what the controller is doing, as of its last heartbeat
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) enum DeviceMode {
+    Idle,
+    Running,
+    Error,
+}
+
+impl DeviceMode {
+    /// lowercase name, as used in the records of the sinks
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            DeviceMode::Idle => "idle",
+            DeviceMode::Running => "running",
+            DeviceMode::Error => "error",
+        }
+    }
+}
+
+/// liveness of the controller, derived from its heartbeats
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct DeviceStatus {
+    pub(crate) serial_number: u64,
+    /// when the last heartbeat was received
+    pub(crate) last_seen: SystemTime,
+    /// whether heartbeats are still received
+    pub(crate) online: bool,
+    pub(crate) mode: DeviceMode,
+    /// seconds since the controller booted
+    pub(crate) uptime: u64,
+    /// reports the controller still waits to be acknowledged
+    pub(crate) unacknowledged: u8,
+}
+
+/// everything read from the controller which needs to be forwarded
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) enum Report {
+    Activity(ReportOrigin, TrackResult),
+    Crash(CrashReport),
+    Identity(DeviceIdentity),
+    Status(DeviceStatus),
+}
+
+impl Report {
+    /// origin of the reports the controller repeats until they are acknowledged
+    pub(crate) fn origin(&self) -> Option<&ReportOrigin> {
+        match self {
+            Report::Activity(origin, _) => Some(origin),
+            Report::Crash(crash) => Some(&crash.origin),
+            Report::Identity(_) | Report::Status(_) => None,
+        }
     }
 }
@@ -1,9 +1,14 @@
 use crate::{
     error::{DomainErrorOccurredSnafu, SendingMessageToOutsideFailedSnafu},
-    ports::{Display, OutsideMessaging, RunningTimeClock, UserInterface},
+    ports::{
+        Display, OutsideMessaging, RemoteCommand, RemoteControl, RunningTimeClock, UserInterface,
+    },
     Error,
 };
-use keret_controller_domain::{AppMode, StateUpdateResult};
+use keret_controller_domain::{
+    AppMode, Brightness, Category, DimmingPolicy, Instant, InteractionRequest, StateUpdateResult,
+    WallClock,
+};
 use snafu::ResultExt;
 
 #[cfg(test)]
@@ -15,7 +20,7 @@ where
     TClock: RunningTimeClock,
     TDisplay: Display,
     TUserInterface: UserInterface,
-    TSerialBus: OutsideMessaging,
+    TSerialBus: OutsideMessaging + RemoteControl,
     TReportFunc: FnMut(&Error<TSerialBus::Error>) + Send + Sync,
 {
     pub running_timer: TClock,
@@ -23,6 +28,11 @@ where
     pub controls: TUserInterface,
     serial_bus: TSerialBus,
     report_error: TReportFunc,
+    dimming: DimmingPolicy,
+    brightness: Brightness,
+    last_interaction: Option<Instant>,
+    category: Category,
+    wall_clock: WallClock,
 }
 
 impl<TClock, TDisplay, TUserInterface, TSerialBus, TReportFunc>
@@ -31,7 +41,7 @@ where
     TClock: RunningTimeClock,
     TDisplay: Display,
     TUserInterface: UserInterface,
-    TSerialBus: OutsideMessaging,
+    TSerialBus: OutsideMessaging + RemoteControl,
     TReportFunc: FnMut(&Error<TSerialBus::Error>) + Send + Sync,
 {
     /// setup a new `ApplicationService` instance
@@ -49,29 +59,62 @@ where
             controls,
             serial_bus,
             report_error,
+            dimming: DimmingPolicy::default(),
+            brightness: Brightness::default(),
+            last_interaction: None,
+            category: Category::default(),
+            wall_clock: WallClock::default(),
         }
     }
 
     /// run the next cycle of the main logic loop, returning the new state
     pub fn next_cycle(&mut self, mode: &AppMode) -> AppMode {
+        let request = self.controls.requested_interaction();
+        let time = self.running_timer.now();
+        let request = self.wake_on_interaction(request, time);
+
         let next = self
-            .calculate_next_state(mode)
+            .calculate_next_state(mode, request, time)
             .unwrap_or_else(|e| self.handle_runtime_error(e));
+        let next = self.handle_remote_commands(next, time);
         self.display.show_mode(&next);
+        self.adjust_brightness(time);
 
         next
     }
 
+    /// signal the outside the controller is still alive, in the given mode
+    pub fn heartbeat(&mut self, mode: &AppMode) {
+        let uptime = self.running_timer.now();
+        if let Err(e) = self
+            .serial_bus
+            .send_heartbeat(mode, uptime)
+            .context(SendingMessageToOutsideFailedSnafu)
+        {
+            (self.report_error)(&e);
+        }
+    }
+
+    /// send all results again, which the outside did not acknowledge yet
+    pub fn resend_unacknowledged(&mut self) {
+        if let Err(e) = self
+            .serial_bus
+            .resend_unacknowledged()
+            .context(SendingMessageToOutsideFailedSnafu)
+        {
+            (self.report_error)(&e);
+        }
+    }
+
     /// calculate the next state:
-    /// check what the user requested to do (by clicking on buttons) and
-    /// let domain layer calculate the next state based on this input
+    /// let domain layer calculate the next state based on what the user requested to do
+    /// (by clicking on buttons)
     fn calculate_next_state(
         &mut self,
         mode: &AppMode,
+        request: InteractionRequest,
+        time: Instant,
     ) -> Result<AppMode, Error<TSerialBus::Error>> {
-        let request = self.controls.requested_interaction();
-        let time = self.running_timer.now();
-
         let StateUpdateResult {
             mode,
             result: message,
@@ -81,13 +124,96 @@ where
 
         if let Some(message) = message {
             self.serial_bus
-                .send_result(message)
+                .send_result(message, self.category, self.wall_clock.wall_time(time))
                 .context(SendingMessageToOutsideFailedSnafu)?;
         }
 
         Ok(mode)
     }
 
+    /// handle all commands received from the outside since the last cycle
+    fn handle_remote_commands(&mut self, mut mode: AppMode, time: Instant) -> AppMode {
+        while let Some(command) = self.serial_bus.next_command() {
+            mode = self
+                .handle_remote_command(&mode, command, time)
+                .unwrap_or_else(|e| self.handle_runtime_error(e));
+        }
+
+        mode
+    }
+
+    /// apply a single command, replying with the resulting state.
+    /// starting & stopping is handled by the domain, like a button pressed by the user
+    fn handle_remote_command(
+        &mut self,
+        mode: &AppMode,
+        command: RemoteCommand,
+        time: Instant,
+    ) -> Result<AppMode, Error<TSerialBus::Error>> {
+        let next = match command {
+            RemoteCommand::QueryState => *mode,
+            RemoteCommand::SetCategory(category) => {
+                self.category = category;
+                *mode
+            }
+            RemoteCommand::SetThresholds(dimming) => {
+                self.dimming = dimming;
+                *mode
+            }
+            RemoteCommand::Start => {
+                self.calculate_next_state(mode, InteractionRequest::Start, time)?
+            }
+            RemoteCommand::Stop => {
+                self.calculate_next_state(mode, InteractionRequest::Stop, time)?
+            }
+            RemoteCommand::SyncTime(now) => {
+                // sent periodically, so it is not worth a reply
+                self.wall_clock.synchronize(time, now);
+                return Ok(*mode);
+            }
+        };
+
+        self.serial_bus
+            .send_state(&next, self.category)
+            .context(SendingMessageToOutsideFailedSnafu)?;
+
+        Ok(next)
+    }
+
+    /// remember when the user last interacted with the device.
+    /// if the display is turned off the interaction only wakes it up and is not handled any further
+    fn wake_on_interaction(
+        &mut self,
+        request: InteractionRequest,
+        time: Instant,
+    ) -> InteractionRequest {
+        if matches!(request, InteractionRequest::None) {
+            return request;
+        }
+
+        self.last_interaction = Some(time);
+        if self.brightness == Brightness::Off {
+            InteractionRequest::None
+        } else {
+            request
+        }
+    }
+
+    /// dim the display if the user did not interact with the device for some time
+    /// the display is only informed if the brightness actually changes
+    fn adjust_brightness(&mut self, time: Instant) {
+        let last_interaction = *self.last_interaction.get_or_insert(time);
+        if last_interaction > time {
+            return;
+        }
+
+        let brightness = self.dimming.brightness_after(time - last_interaction);
+        if brightness != self.brightness {
+            self.brightness = brightness;
+            self.display.set_brightness(brightness);
+        }
+    }
+
     /// report an error that happened while executing the main loop
     /// and switch the AppMode appropriately to indicate it's in a failure state
     fn handle_runtime_error(&mut self, err: Error<TSerialBus::Error>) -> AppMode {
@@ -1,13 +1,22 @@
-use crate::ports::{Display, OutsideMessaging, RunningTimeClock, UserInterface};
+use crate::ports::{
+    Display, OutsideMessaging, RemoteCommand, RemoteControl, RunningTimeClock, UserInterface,
+};
 use crate::{ApplicationService, Error};
-use keret_controller_domain::{AppMode, Duration, Instant, InteractionRequest, TrackResult};
+use keret_controller_domain::{
+    AppMode, Brightness, Category, DimmingPolicy, Duration, Instant, InteractionRequest,
+    TrackResult, WallTime,
+};
 use mockall::mock;
 use mockall::predicate::*;
+use mockall::Sequence;
 use snafu::Snafu;
 
 const FIRST_TIMESTAMP: u64 = 0xDA7A;
 const DURATION: u64 = 10;
 const SECOND_TIMESTAMP: u64 = FIRST_TIMESTAMP + DURATION;
+const DIMMED_TIMESTAMP: u64 = FIRST_TIMESTAMP + 30;
+const OFF_TIMESTAMP: u64 = FIRST_TIMESTAMP + 300;
+const WALL_TIME: u64 = 1_700_000_000;
 
 // errors used by the mocks
 
@@ -32,15 +41,28 @@ mock! {
 
     impl Display for MyDisplay {
         fn show_mode(&mut self, mode: &AppMode);
+        fn set_brightness(&mut self, brightness: Brightness);
     }
 }
 
 mock! {
-    MyOutsideMessaging {}
+    MySerialBus {}
 
-    impl OutsideMessaging for MyOutsideMessaging {
+    impl OutsideMessaging for MySerialBus {
         type Error = TestError;
-        fn send_result(&mut self, result: TrackResult) -> Result<(), TestError>;
+        fn send_result(
+            &mut self,
+            result: TrackResult,
+            category: Category,
+            finished_at: Option<WallTime>,
+        ) -> Result<(), TestError>;
+        fn send_state(&mut self, mode: &AppMode, category: Category) -> Result<(), TestError>;
+        fn send_heartbeat(&mut self, mode: &AppMode, uptime: Instant) -> Result<(), TestError>;
+        fn resend_unacknowledged(&mut self) -> Result<(), TestError>;
+    }
+
+    impl RemoteControl for MySerialBus {
+        fn next_command(&mut self) -> Option<RemoteCommand>;
     }
 }
 
@@ -77,7 +99,8 @@ fn next_cycle_calls_ports() {
         .once()
         .with(eq(AppMode::Idle))
         .return_const(());
-    let mut bus = MockMyOutsideMessaging::new();
+    let mut bus = MockMySerialBus::new();
+    bus.expect_next_command().returning(|| None);
     bus.expect_send_result().never();
 
     let mut service = ApplicationService::new(clock, display, ui, bus, &noop_report);
@@ -109,7 +132,8 @@ fn next_cycle_switches_to_running_using_timestamp() {
         .once()
         .with(eq(AppMode::Running(Instant::from(FIRST_TIMESTAMP))))
         .return_const(());
-    let mut bus = MockMyOutsideMessaging::new();
+    let mut bus = MockMySerialBus::new();
+    bus.expect_next_command().returning(|| None);
     bus.expect_send_result().never();
 
     let mut service = ApplicationService::new(clock, display, ui, bus, &noop_report);
@@ -142,11 +166,16 @@ fn next_cycle_sends_message_if_tracking_finished_and_returns_idle() {
         .once()
         .with(eq(AppMode::Idle))
         .return_const(());
-    let mut bus = MockMyOutsideMessaging::new();
+    let mut bus = MockMySerialBus::new();
+    bus.expect_next_command().returning(|| None);
     bus.expect_send_result()
         .once()
-        .with(eq(TrackResult::from(Duration::from(DURATION))))
-        .returning(|_| Ok(()));
+        .with(
+            eq(TrackResult::from(Duration::from(DURATION))),
+            eq(Category::default()),
+            eq(None),
+        )
+        .returning(|_, _, _| Ok(()));
 
     let mut service = ApplicationService::new(clock, display, ui, bus, &noop_report);
     let mode = AppMode::Running(Instant::from(FIRST_TIMESTAMP));
@@ -179,7 +208,8 @@ fn next_cycle_reports_error_on_inconsistent_timestamps() {
         .once()
         .with(eq(AppMode::Error))
         .return_const(());
-    let mut bus = MockMyOutsideMessaging::new();
+    let mut bus = MockMySerialBus::new();
+    bus.expect_next_command().returning(|| None);
     bus.expect_send_result().never();
 
     let mut service = ApplicationService::new(clock, display, ui, bus, |error| {
@@ -214,7 +244,8 @@ fn next_cycle_returns_error_on_inconsistent_timestamps() {
         .once()
         .with(eq(AppMode::Error))
         .return_const(());
-    let mut bus = MockMyOutsideMessaging::new();
+    let mut bus = MockMySerialBus::new();
+    bus.expect_next_command().returning(|| None);
     bus.expect_send_result().never();
 
     let mut service = ApplicationService::new(clock, display, ui, bus, &noop_report);
@@ -247,11 +278,16 @@ fn next_cycle_returns_error_when_sending_fails() {
         .once()
         .with(eq(AppMode::Error))
         .return_const(());
-    let mut bus = MockMyOutsideMessaging::new();
+    let mut bus = MockMySerialBus::new();
+    bus.expect_next_command().returning(|| None);
     bus.expect_send_result()
         .once()
-        .with(eq(TrackResult::from(Duration::from(DURATION))))
-        .returning(|_| ErrorDuringSendSnafu.fail());
+        .with(
+            eq(TrackResult::from(Duration::from(DURATION))),
+            eq(Category::default()),
+            eq(None),
+        )
+        .returning(|_, _, _| ErrorDuringSendSnafu.fail());
 
     let mut service = ApplicationService::new(clock, display, ui, bus, &noop_report);
     let mode = AppMode::Running(Instant::from(FIRST_TIMESTAMP));
@@ -284,11 +320,16 @@ fn next_cycle_reports_error_when_sending_fails() {
         .once()
         .with(eq(AppMode::Error))
         .return_const(());
-    let mut bus = MockMyOutsideMessaging::new();
+    let mut bus = MockMySerialBus::new();
+    bus.expect_next_command().returning(|| None);
     bus.expect_send_result()
         .once()
-        .with(eq(TrackResult::from(Duration::from(DURATION))))
-        .returning(|_| ErrorDuringSendSnafu.fail());
+        .with(
+            eq(TrackResult::from(Duration::from(DURATION))),
+            eq(Category::default()),
+            eq(None),
+        )
+        .returning(|_, _, _| ErrorDuringSendSnafu.fail());
 
     let mut service = ApplicationService::new(clock, display, ui, bus, |error| {
         error_was_reported = matches!(error, Error::SendingMessageToOutsideFailed { .. })
@@ -301,3 +342,431 @@ fn next_cycle_reports_error_when_sending_fails() {
     // assert -> + automatically by mockall mocks
     assert_eq!(error_was_reported, true);
 }
+
+#[test]
+fn next_cycle_dims_display_after_user_is_idle() {
+    // arrange
+    let mut timestamps = [FIRST_TIMESTAMP, DIMMED_TIMESTAMP].into_iter();
+    let mut clock = MockMyClock::new();
+    clock
+        .expect_now()
+        .times(2)
+        .returning(move || Instant::from(timestamps.next().unwrap()));
+
+    let mut ui = MockMyUserInterface::new();
+    ui.expect_requested_interaction()
+        .times(2)
+        .returning(|| InteractionRequest::None);
+
+    let mut display = MockMyDisplay::new();
+    display
+        .expect_show_mode()
+        .times(2)
+        .with(eq(AppMode::Idle))
+        .return_const(());
+    display
+        .expect_set_brightness()
+        .once()
+        .with(eq(Brightness::Dimmed))
+        .return_const(());
+    let mut bus = MockMySerialBus::new();
+    bus.expect_next_command().returning(|| None);
+    bus.expect_send_result().never();
+
+    let mut service = ApplicationService::new(clock, display, ui, bus, &noop_report);
+    let mode = AppMode::Idle;
+
+    // act
+    let mode = service.next_cycle(&mode);
+    let _ = service.next_cycle(&mode);
+
+    // assert -> automatically by mockall mocks
+}
+
+#[test]
+fn next_cycle_with_display_off_only_wakes_display_on_interaction() {
+    // arrange
+    let mut timestamps = [FIRST_TIMESTAMP, OFF_TIMESTAMP, OFF_TIMESTAMP + 1].into_iter();
+    let mut clock = MockMyClock::new();
+    clock
+        .expect_now()
+        .times(3)
+        .returning(move || Instant::from(timestamps.next().unwrap()));
+
+    let mut requests = [
+        InteractionRequest::None,
+        InteractionRequest::None,
+        InteractionRequest::ToggleMode,
+    ]
+    .into_iter();
+    let mut ui = MockMyUserInterface::new();
+    ui.expect_requested_interaction()
+        .times(3)
+        .returning(move || requests.next().unwrap());
+
+    let mut sequence = Sequence::new();
+    let mut display = MockMyDisplay::new();
+    display
+        .expect_show_mode()
+        .times(3)
+        .with(eq(AppMode::Idle))
+        .return_const(());
+    display
+        .expect_set_brightness()
+        .once()
+        .with(eq(Brightness::Off))
+        .in_sequence(&mut sequence)
+        .return_const(());
+    display
+        .expect_set_brightness()
+        .once()
+        .with(eq(Brightness::Full))
+        .in_sequence(&mut sequence)
+        .return_const(());
+    let mut bus = MockMySerialBus::new();
+    bus.expect_next_command().returning(|| None);
+    bus.expect_send_result().never();
+
+    let mut service = ApplicationService::new(clock, display, ui, bus, &noop_report);
+    let mode = AppMode::Idle;
+
+    // act
+    let mode = service.next_cycle(&mode);
+    let mode = service.next_cycle(&mode);
+    let actual = service.next_cycle(&mode);
+
+    // assert -> + automatically by mockall mocks
+    assert_eq!(actual, AppMode::Idle);
+}
+
+#[test]
+fn next_cycle_query_state_command_replies_with_state() {
+    // arrange
+    let mut clock = MockMyClock::new();
+    clock
+        .expect_now()
+        .once()
+        .returning(|| Instant::from(FIRST_TIMESTAMP));
+
+    let mut ui = MockMyUserInterface::new();
+    ui.expect_requested_interaction()
+        .once()
+        .returning(|| InteractionRequest::None);
+
+    let mut display = MockMyDisplay::new();
+    display.expect_show_mode().return_const(());
+    let mut commands = [Some(RemoteCommand::QueryState), None].into_iter();
+    let mut bus = MockMySerialBus::new();
+    bus.expect_next_command()
+        .times(2)
+        .returning(move || commands.next().unwrap());
+    bus.expect_send_state()
+        .once()
+        .with(eq(AppMode::Idle), eq(Category::default()))
+        .returning(|_, _| Ok(()));
+
+    let mut service = ApplicationService::new(clock, display, ui, bus, &noop_report);
+
+    // act
+    let actual = service.next_cycle(&AppMode::Idle);
+
+    // assert -> + automatically by mockall mocks
+    assert_eq!(actual, AppMode::Idle);
+}
+
+#[test]
+fn next_cycle_start_command_switches_to_running() {
+    // arrange
+    let mut clock = MockMyClock::new();
+    clock
+        .expect_now()
+        .once()
+        .returning(|| Instant::from(FIRST_TIMESTAMP));
+
+    let mut ui = MockMyUserInterface::new();
+    ui.expect_requested_interaction()
+        .once()
+        .returning(|| InteractionRequest::None);
+
+    let mut display = MockMyDisplay::new();
+    display
+        .expect_show_mode()
+        .once()
+        .with(eq(AppMode::Running(Instant::from(FIRST_TIMESTAMP))))
+        .return_const(());
+    let mut commands = [Some(RemoteCommand::Start), None].into_iter();
+    let mut bus = MockMySerialBus::new();
+    bus.expect_next_command()
+        .times(2)
+        .returning(move || commands.next().unwrap());
+    bus.expect_send_state()
+        .once()
+        .with(
+            eq(AppMode::Running(Instant::from(FIRST_TIMESTAMP))),
+            eq(Category::default()),
+        )
+        .returning(|_, _| Ok(()));
+
+    let mut service = ApplicationService::new(clock, display, ui, bus, &noop_report);
+
+    // act
+    let actual = service.next_cycle(&AppMode::Idle);
+
+    // assert -> + automatically by mockall mocks
+    assert_eq!(actual, AppMode::Running(Instant::from(FIRST_TIMESTAMP)));
+}
+
+#[test]
+fn next_cycle_stop_command_sends_result_in_category_set_before() {
+    // arrange
+    let mut clock = MockMyClock::new();
+    clock
+        .expect_now()
+        .once()
+        .returning(|| Instant::from(SECOND_TIMESTAMP));
+
+    let mut ui = MockMyUserInterface::new();
+    ui.expect_requested_interaction()
+        .once()
+        .returning(|| InteractionRequest::None);
+
+    let mut display = MockMyDisplay::new();
+    display
+        .expect_show_mode()
+        .once()
+        .with(eq(AppMode::Idle))
+        .return_const(());
+    let mut commands = [
+        Some(RemoteCommand::SetCategory(Category::from(3))),
+        Some(RemoteCommand::Stop),
+        None,
+    ]
+    .into_iter();
+    let mut bus = MockMySerialBus::new();
+    bus.expect_next_command()
+        .times(3)
+        .returning(move || commands.next().unwrap());
+    bus.expect_send_state().times(2).returning(|_, _| Ok(()));
+    bus.expect_send_result()
+        .once()
+        .with(
+            eq(TrackResult::from(Duration::from(DURATION))),
+            eq(Category::from(3)),
+            eq(None),
+        )
+        .returning(|_, _, _| Ok(()));
+
+    let mut service = ApplicationService::new(clock, display, ui, bus, &noop_report);
+    let mode = AppMode::Running(Instant::from(FIRST_TIMESTAMP));
+
+    // act
+    let actual = service.next_cycle(&mode);
+
+    // assert -> + automatically by mockall mocks
+    assert_eq!(actual, AppMode::Idle);
+}
+
+#[test]
+fn next_cycle_set_thresholds_command_changes_dimming() {
+    // arrange
+    let mut timestamps = [FIRST_TIMESTAMP, FIRST_TIMESTAMP + 5].into_iter();
+    let mut clock = MockMyClock::new();
+    clock
+        .expect_now()
+        .times(2)
+        .returning(move || Instant::from(timestamps.next().unwrap()));
+
+    let mut ui = MockMyUserInterface::new();
+    ui.expect_requested_interaction()
+        .times(2)
+        .returning(|| InteractionRequest::None);
+
+    let mut display = MockMyDisplay::new();
+    display.expect_show_mode().return_const(());
+    display
+        .expect_set_brightness()
+        .once()
+        .with(eq(Brightness::Dimmed))
+        .return_const(());
+    let policy = DimmingPolicy::new(Duration::from(5), Duration::from(10));
+    let mut commands = [Some(RemoteCommand::SetThresholds(policy)), None, None].into_iter();
+    let mut bus = MockMySerialBus::new();
+    bus.expect_next_command()
+        .times(3)
+        .returning(move || commands.next().unwrap());
+    bus.expect_send_state().once().returning(|_, _| Ok(()));
+
+    let mut service = ApplicationService::new(clock, display, ui, bus, &noop_report);
+
+    // act
+    let mode = service.next_cycle(&AppMode::Idle);
+    let _ = service.next_cycle(&mode);
+
+    // assert -> automatically by mockall mocks
+}
+
+#[test]
+fn next_cycle_returns_error_when_replying_state_fails() {
+    // arrange
+    let mut clock = MockMyClock::new();
+    clock
+        .expect_now()
+        .once()
+        .returning(|| Instant::from(FIRST_TIMESTAMP));
+
+    let mut ui = MockMyUserInterface::new();
+    ui.expect_requested_interaction()
+        .once()
+        .returning(|| InteractionRequest::None);
+
+    let mut display = MockMyDisplay::new();
+    display
+        .expect_show_mode()
+        .once()
+        .with(eq(AppMode::Error))
+        .return_const(());
+    let mut commands = [Some(RemoteCommand::QueryState), None].into_iter();
+    let mut bus = MockMySerialBus::new();
+    bus.expect_next_command()
+        .times(2)
+        .returning(move || commands.next().unwrap());
+    bus.expect_send_state()
+        .once()
+        .returning(|_, _| ErrorDuringSendSnafu.fail());
+
+    let mut service = ApplicationService::new(clock, display, ui, bus, &noop_report);
+
+    // act
+    let actual = service.next_cycle(&AppMode::Idle);
+
+    // assert -> + automatically by mockall mocks
+    assert_eq!(actual, AppMode::Error);
+}
+
+#[test]
+fn next_cycle_after_sync_time_command_sends_result_with_wall_time() {
+    // arrange
+    let mut timestamps = [FIRST_TIMESTAMP, SECOND_TIMESTAMP].into_iter();
+    let mut clock = MockMyClock::new();
+    clock
+        .expect_now()
+        .times(2)
+        .returning(move || Instant::from(timestamps.next().unwrap()));
+
+    let mut requests = [InteractionRequest::None, InteractionRequest::ToggleMode].into_iter();
+    let mut ui = MockMyUserInterface::new();
+    ui.expect_requested_interaction()
+        .times(2)
+        .returning(move || requests.next().unwrap());
+
+    let mut display = MockMyDisplay::new();
+    display.expect_show_mode().return_const(());
+    let mut commands = [Some(RemoteCommand::SyncTime(WALL_TIME.into())), None, None].into_iter();
+    let mut bus = MockMySerialBus::new();
+    bus.expect_next_command()
+        .times(3)
+        .returning(move || commands.next().unwrap());
+    bus.expect_send_state().never();
+    bus.expect_send_result()
+        .once()
+        .with(
+            eq(TrackResult::from(Duration::from(DURATION))),
+            eq(Category::default()),
+            eq(Some(WallTime::from(WALL_TIME + DURATION))),
+        )
+        .returning(|_, _, _| Ok(()));
+
+    let mut service = ApplicationService::new(clock, display, ui, bus, &noop_report);
+    let mode = AppMode::Running(Instant::from(FIRST_TIMESTAMP));
+
+    // act
+    let mode = service.next_cycle(&mode);
+    let actual = service.next_cycle(&mode);
+
+    // assert -> + automatically by mockall mocks
+    assert_eq!(actual, AppMode::Idle);
+}
+
+#[test]
+fn heartbeat_sends_mode_and_uptime() {
+    // arrange
+    let mut clock = MockMyClock::new();
+    clock
+        .expect_now()
+        .once()
+        .returning(|| Instant::from(SECOND_TIMESTAMP));
+    let mut bus = MockMySerialBus::new();
+    bus.expect_send_heartbeat()
+        .once()
+        .with(
+            eq(AppMode::Running(Instant::from(FIRST_TIMESTAMP))),
+            eq(Instant::from(SECOND_TIMESTAMP)),
+        )
+        .returning(|_, _| Ok(()));
+
+    let mut service = ApplicationService::new(
+        clock,
+        MockMyDisplay::new(),
+        MockMyUserInterface::new(),
+        bus,
+        &noop_report,
+    );
+
+    // act
+    service.heartbeat(&AppMode::Running(Instant::from(FIRST_TIMESTAMP)));
+
+    // assert -> automatically by mockall mocks
+}
+
+#[test]
+fn heartbeat_reports_error_when_sending_fails() {
+    // arrange
+    let mut error_was_reported = false;
+    let mut clock = MockMyClock::new();
+    clock
+        .expect_now()
+        .once()
+        .returning(|| Instant::from(FIRST_TIMESTAMP));
+    let mut bus = MockMySerialBus::new();
+    bus.expect_send_heartbeat()
+        .once()
+        .returning(|_, _| ErrorDuringSendSnafu.fail());
+
+    let mut service = ApplicationService::new(
+        clock,
+        MockMyDisplay::new(),
+        MockMyUserInterface::new(),
+        bus,
+        |error| error_was_reported = matches!(error, Error::SendingMessageToOutsideFailed { .. }),
+    );
+
+    // act
+    service.heartbeat(&AppMode::Idle);
+
+    // assert -> + automatically by mockall mocks
+    assert!(error_was_reported);
+}
+
+#[test]
+fn resend_unacknowledged_reports_error_when_sending_fails() {
+    // arrange
+    let mut error_was_reported = false;
+    let mut bus = MockMySerialBus::new();
+    bus.expect_resend_unacknowledged()
+        .once()
+        .returning(|| ErrorDuringSendSnafu.fail());
+
+    let mut service = ApplicationService::new(
+        MockMyClock::new(),
+        MockMyDisplay::new(),
+        MockMyUserInterface::new(),
+        bus,
+        |error| error_was_reported = matches!(error, Error::SendingMessageToOutsideFailed { .. }),
+    );
+
+    // act
+    service.resend_unacknowledged();
+
+    // assert -> + automatically by mockall mocks
+    assert!(error_was_reported);
+}
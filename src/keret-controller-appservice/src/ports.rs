@@ -1,16 +1,37 @@
-use keret_controller_domain::{AppMode, Instant, InteractionRequest, TrackResult};
+use keret_controller_domain::{
+    AppMode, Brightness, Category, DimmingPolicy, Instant, InteractionRequest, TrackResult,
+    WallTime,
+};
 
 /// Show domain-specific content on the display
 pub trait Display {
     /// display a sprite associated with the given `AppMode`
     fn show_mode(&mut self, mode: &AppMode);
+
+    /// change how bright the display shines
+    fn set_brightness(&mut self, brightness: Brightness);
 }
 
 /// Send domain-specific messages to the outside
 pub trait OutsideMessaging {
     type Error: snafu::Error + 'static;
-    /// inform the outside of the time tracking result
-    fn send_result(&mut self, result: TrackResult) -> Result<(), Self::Error>;
+    /// inform the outside of the time tracking result of an activity in the given category
+    /// finished at the given wall time (if known)
+    fn send_result(
+        &mut self,
+        result: TrackResult,
+        category: Category,
+        finished_at: Option<WallTime>,
+    ) -> Result<(), Self::Error>;
+
+    /// inform the outside of the current state, in reply to a remote command
+    fn send_state(&mut self, mode: &AppMode, category: Category) -> Result<(), Self::Error>;
+
+    /// signal the outside the controller is still alive, in the given mode since its boot
+    fn send_heartbeat(&mut self, mode: &AppMode, uptime: Instant) -> Result<(), Self::Error>;
+
+    /// send all results again, which the outside did not acknowledge yet
+    fn resend_unacknowledged(&mut self) -> Result<(), Self::Error>;
 }
 
 /// Keep track of the running time, producing an ever-increasing, never resetting timestamp
@@ -24,3 +45,26 @@ pub trait UserInterface {
     /// return the requested interface (as calculated from the inputs the user made)
     fn requested_interaction(&mut self) -> InteractionRequest;
 }
+
+/// commands received from the outside
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RemoteCommand {
+    /// only reply with the current state
+    QueryState,
+    /// report the following time trackings in the given category
+    SetCategory(Category),
+    /// dim and turn off the display according to the given policy
+    SetThresholds(DimmingPolicy),
+    /// start the time tracking, as if the user pressed the button
+    Start,
+    /// stop the time tracking, as if the user pressed the button
+    Stop,
+    /// the current wall time, as known by the outside
+    SyncTime(WallTime),
+}
+
+/// retrieve commands from the outside
+pub trait RemoteControl {
+    /// return the next command received from the outside, if any
+    fn next_command(&mut self) -> Option<RemoteCommand>;
+}
@@ -22,6 +22,8 @@ impl AppMode {
     ) -> Result<StateUpdateResult, Error> {
         match request {
             InteractionRequest::ToggleMode => self.toggle_mode(timestamp),
+            InteractionRequest::Start => self.start(timestamp),
+            InteractionRequest::Stop => self.stop(timestamp),
             InteractionRequest::Reset => Ok(StateUpdateResult::new(AppMode::Idle)),
             InteractionRequest::None => Ok(StateUpdateResult::new(*self)),
         }
@@ -38,6 +40,24 @@ impl AppMode {
         }
     }
 
+    /// start the time tracking, unless it is already running
+    #[inline(always)]
+    fn start(&self, timestamp: Instant) -> Result<StateUpdateResult, Error> {
+        match self {
+            AppMode::Idle => Ok(StateUpdateResult::new(AppMode::Running(timestamp))),
+            AppMode::Running(_) | AppMode::Error => Ok(StateUpdateResult::new(*self)),
+        }
+    }
+
+    /// stop the time tracking if it is running, sending the report
+    #[inline(always)]
+    fn stop(&self, timestamp: Instant) -> Result<StateUpdateResult, Error> {
+        match self {
+            AppMode::Running(start) => self.finish_report(start, timestamp),
+            AppMode::Idle | AppMode::Error => Ok(StateUpdateResult::new(*self)),
+        }
+    }
+
     /// user ended the timer, calculate duration and send it over the wire
     fn finish_report(
         &self,
@@ -239,4 +259,90 @@ mod test {
         // assert
         assert_eq!(actual, Ok(StateUpdateResult::new(AppMode::Idle)));
     }
+
+    #[test]
+    fn app_mode_of_idle_handle_start_interaction_request_returns_running() {
+        // arrange
+        let mode = AppMode::Idle;
+        let interaction_request = InteractionRequest::Start;
+        let timestamp: Instant = SOME_TIMESTAMP.into();
+
+        // act
+        let actual = mode.handle_interaction_request(interaction_request, timestamp);
+
+        // assert
+        assert_eq!(
+            actual,
+            Ok(StateUpdateResult::new(AppMode::Running(
+                SOME_TIMESTAMP.into()
+            )))
+        );
+    }
+
+    #[test]
+    fn app_mode_of_idle_handle_stop_interaction_request_keeps_idle() {
+        // arrange
+        let mode = AppMode::Idle;
+        let interaction_request = InteractionRequest::Stop;
+        let timestamp: Instant = SOME_TIMESTAMP.into();
+
+        // act
+        let actual = mode.handle_interaction_request(interaction_request, timestamp);
+
+        // assert
+        assert_eq!(actual, Ok(StateUpdateResult::new(AppMode::Idle)));
+    }
+
+    #[test]
+    fn app_mode_of_running_handle_start_interaction_request_keeps_start_timestamp() {
+        // arrange
+        let mode = AppMode::Running(SOME_TIMESTAMP.into());
+        let interaction_request = InteractionRequest::Start;
+        let timestamp: Instant = BIGGER_TIMESTAMP.into();
+
+        // act
+        let actual = mode.handle_interaction_request(interaction_request, timestamp);
+
+        // assert
+        assert_eq!(
+            actual,
+            Ok(StateUpdateResult::new(AppMode::Running(
+                SOME_TIMESTAMP.into()
+            )))
+        );
+    }
+
+    #[test]
+    fn app_mode_of_running_handle_stop_interaction_request_returns_idle_with_result() {
+        // arrange
+        let mode = AppMode::Running(SOME_TIMESTAMP.into());
+        let interaction_request = InteractionRequest::Stop;
+        let timestamp: Instant = BIGGER_TIMESTAMP.into();
+
+        // act
+        let actual = mode.handle_interaction_request(interaction_request, timestamp);
+
+        // assert
+        assert_eq!(
+            actual,
+            Ok(StateUpdateResult::with_result(
+                AppMode::Idle,
+                TrackResult::from(Duration::from(DIFFERENCE))
+            ))
+        );
+    }
+
+    #[test]
+    fn app_mode_of_error_handle_start_interaction_request_keeps_error() {
+        // arrange
+        let mode = AppMode::Error;
+        let interaction_request = InteractionRequest::Start;
+        let timestamp: Instant = SOME_TIMESTAMP.into();
+
+        // act
+        let actual = mode.handle_interaction_request(interaction_request, timestamp);
+
+        // assert
+        assert_eq!(actual, Ok(StateUpdateResult::new(AppMode::Error)));
+    }
 }
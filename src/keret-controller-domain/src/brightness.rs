@@ -0,0 +1,120 @@
+use crate::Duration;
+
+/// how bright the display should currently shine
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum Brightness {
+    /// the user is interacting with the device, shine as bright as possible
+    #[default]
+    Full,
+    /// the user did not interact for some time, reduce the brightness
+    Dimmed,
+    /// the user did not interact for a long time, turn the display off
+    Off,
+}
+
+/// time without any user interaction after which the display is dimmed or turned off
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DimmingPolicy {
+    dim_after: Duration,
+    off_after: Duration,
+}
+
+impl DimmingPolicy {
+    /// default time (in seconds) without interaction after which the display is dimmed
+    const DEFAULT_DIM_AFTER: u64 = 30;
+    /// default time (in seconds) without interaction after which the display is turned off
+    const DEFAULT_OFF_AFTER: u64 = 300;
+
+    /// create a new policy using the given thresholds
+    pub fn new(dim_after: Duration, off_after: Duration) -> Self {
+        Self {
+            dim_after,
+            off_after,
+        }
+    }
+
+    /// calculate the brightness of the display after the user did not interact for `idle` time
+    pub fn brightness_after(&self, idle: Duration) -> Brightness {
+        if idle >= self.off_after {
+            Brightness::Off
+        } else if idle >= self.dim_after {
+            Brightness::Dimmed
+        } else {
+            Brightness::Full
+        }
+    }
+}
+
+impl Default for DimmingPolicy {
+    fn default() -> Self {
+        Self::new(
+            Self::DEFAULT_DIM_AFTER.into(),
+            Self::DEFAULT_OFF_AFTER.into(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DIM_AFTER: u64 = 10;
+    const OFF_AFTER: u64 = 100;
+
+    #[test]
+    fn brightness_default_is_full() {
+        // act
+        let actual = Brightness::default();
+
+        // assert
+        assert_eq!(actual, Brightness::Full);
+    }
+
+    #[test]
+    fn dimming_policy_before_dim_threshold_returns_full() {
+        // arrange
+        let policy = DimmingPolicy::new(DIM_AFTER.into(), OFF_AFTER.into());
+
+        // act
+        let actual = policy.brightness_after((DIM_AFTER - 1).into());
+
+        // assert
+        assert_eq!(actual, Brightness::Full);
+    }
+
+    #[test]
+    fn dimming_policy_at_dim_threshold_returns_dimmed() {
+        // arrange
+        let policy = DimmingPolicy::new(DIM_AFTER.into(), OFF_AFTER.into());
+
+        // act
+        let actual = policy.brightness_after(DIM_AFTER.into());
+
+        // assert
+        assert_eq!(actual, Brightness::Dimmed);
+    }
+
+    #[test]
+    fn dimming_policy_at_off_threshold_returns_off() {
+        // arrange
+        let policy = DimmingPolicy::new(DIM_AFTER.into(), OFF_AFTER.into());
+
+        // act
+        let actual = policy.brightness_after(OFF_AFTER.into());
+
+        // assert
+        assert_eq!(actual, Brightness::Off);
+    }
+
+    #[test]
+    fn dimming_policy_with_off_before_dim_skips_dimmed() {
+        // arrange
+        let policy = DimmingPolicy::new(OFF_AFTER.into(), DIM_AFTER.into());
+
+        // act
+        let actual = policy.brightness_after(DIM_AFTER.into());
+
+        // assert
+        assert_eq!(actual, Brightness::Off);
+    }
+}
@@ -0,0 +1,46 @@
+/// category of the tracked activity, reported together with the tracked time
+/// chosen remotely, the controller itself has no notion of what the categories mean
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Category(u8);
+
+// create a category from its raw number
+impl From<u8> for Category {
+    #[inline]
+    fn from(value: u8) -> Self {
+        Self(value)
+    }
+}
+
+// extract the raw number of the category
+impl From<Category> for u8 {
+    #[inline]
+    fn from(value: Category) -> Self {
+        value.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SOME_CATEGORY: u8 = 3;
+
+    #[test]
+    fn category_default_is_zero() {
+        // act
+        let actual: u8 = Category::default().into();
+
+        // assert
+        assert_eq!(actual, 0);
+    }
+
+    #[test]
+    fn category_from_u8_contains_value() {
+        // act
+        let actual: u8 = Category::from(SOME_CATEGORY).into();
+
+        // assert
+        assert_eq!(actual, SOME_CATEGORY);
+    }
+}
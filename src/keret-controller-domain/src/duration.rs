@@ -1,5 +1,5 @@
 /// measure of how long an action took
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
 #[repr(transparent)]
 pub struct Duration(u64);
 
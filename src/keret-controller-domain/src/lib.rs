@@ -1,24 +1,34 @@
 #![cfg_attr(not(test), no_std)]
 mod app_mode;
+mod brightness;
+mod category;
 mod duration;
 mod error;
 mod instant;
 mod results;
+mod wall_clock;
 
 // re-export everything relevant from the submodules as if it was directly coded here
 // hides internal structure of the module
 pub use app_mode::AppMode;
+pub use brightness::{Brightness, DimmingPolicy};
+pub use category::Category;
 pub use duration::Duration;
 pub use error::Error;
 pub use instant::Instant;
 pub use results::{StateUpdateResult, TrackResult};
+pub use wall_clock::{WallClock, WallTime};
 
 /// enum to indicate the users desired interaction
-/// which is calculated by which button was pressed
+/// which is calculated by which button was pressed (or which command was received remotely)
 #[derive(Debug, Copy, Clone, Default)]
 pub enum InteractionRequest {
     #[default]
     None,
     ToggleMode,
     Reset,
+    /// start the time tracking, if not running already
+    Start,
+    /// stop the time tracking, if running
+    Stop,
 }
@@ -0,0 +1,187 @@
+use crate::Instant;
+
+/// point in time as seconds since the unix epoch (UTC), as known by the outside
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+#[repr(transparent)]
+pub struct WallTime(u64);
+
+// create the wall time from seconds since the unix epoch
+impl From<u64> for WallTime {
+    #[inline(always)]
+    fn from(value: u64) -> Self {
+        Self(value)
+    }
+}
+
+// extract the seconds since the unix epoch
+impl From<WallTime> for u64 {
+    #[inline(always)]
+    fn from(val: WallTime) -> Self {
+        val.0
+    }
+}
+
+/// running time at which the wall time was pushed from the outside
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct SyncPoint {
+    running: i64,
+    wall: i64,
+}
+
+impl SyncPoint {
+    fn new(running: Instant, wall: WallTime) -> Self {
+        Self {
+            running: u64::from(&running) as i64,
+            wall: wall.0 as i64,
+        }
+    }
+}
+
+/// converts the running time into wall time, using the wall time pushed from the outside
+/// the running time drifts against the wall time, so the drift is estimated
+/// between the first and the latest synchronization, getting more accurate the longer it runs
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct WallClock {
+    first: Option<SyncPoint>,
+    latest: Option<SyncPoint>,
+}
+
+impl WallClock {
+    /// running time (in seconds) between synchronizations before the drift is estimated
+    const MIN_DRIFT_SPAN: i64 = 600;
+    /// more drift can't be explained by the oscillator, the wall time must have jumped
+    const MAX_DRIFT_PPM: i64 = 50_000;
+
+    /// remember the wall time at the given running time
+    /// if the wall time jumped, the drift estimation starts anew
+    pub fn synchronize(&mut self, running: Instant, wall: WallTime) {
+        let point = SyncPoint::new(running, wall);
+        self.latest = Some(point);
+
+        let jumped = self
+            .first
+            .and_then(|first| Self::drift_between(&first, &point))
+            .is_some_and(|drift| drift.abs() > Self::MAX_DRIFT_PPM);
+        if self.first.is_none() || jumped {
+            self.first = Some(point);
+        }
+    }
+
+    /// estimated drift of the running time in parts per million
+    /// positive if the wall time passes faster than the running time
+    pub fn drift_ppm(&self) -> Option<i64> {
+        Self::drift_between(&self.first?, &self.latest?)
+    }
+
+    /// calculate the wall time of the given running time, if it was synchronized at all
+    pub fn wall_time(&self, running: Instant) -> Option<WallTime> {
+        let latest = self.latest?;
+        let elapsed = u64::from(&running) as i64 - latest.running;
+        let correction = elapsed * self.drift_ppm().unwrap_or(0) / 1_000_000;
+
+        let wall = latest.wall + elapsed + correction;
+        u64::try_from(wall).ok().map(WallTime)
+    }
+
+    /// drift between two synchronizations, if they are far enough apart to tell
+    fn drift_between(first: &SyncPoint, latest: &SyncPoint) -> Option<i64> {
+        let running_span = latest.running - first.running;
+        if running_span < Self::MIN_DRIFT_SPAN {
+            return None;
+        }
+
+        let wall_span = latest.wall - first.wall;
+        Some((wall_span - running_span) * 1_000_000 / running_span)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SOME_RUNNING: u64 = 0xDA7A;
+    const SOME_WALL: u64 = 1_700_000_000;
+
+    #[test]
+    fn wall_clock_not_synchronized_has_no_wall_time() {
+        // arrange
+        let clock = WallClock::default();
+
+        // act
+        let actual = clock.wall_time(SOME_RUNNING.into());
+
+        // assert
+        assert_eq!(actual, None);
+    }
+
+    #[test]
+    fn wall_clock_synchronized_once_adds_elapsed_running_time() {
+        // arrange
+        let mut clock = WallClock::default();
+        clock.synchronize(SOME_RUNNING.into(), SOME_WALL.into());
+
+        // act
+        let actual = clock.wall_time((SOME_RUNNING + 10).into());
+
+        // assert
+        assert_eq!(actual, Some(WallTime::from(SOME_WALL + 10)));
+    }
+
+    #[test]
+    fn wall_clock_running_time_before_synchronization_is_converted() {
+        // arrange
+        let mut clock = WallClock::default();
+        clock.synchronize(SOME_RUNNING.into(), SOME_WALL.into());
+
+        // act
+        let actual = clock.wall_time((SOME_RUNNING - 10).into());
+
+        // assert
+        assert_eq!(actual, Some(WallTime::from(SOME_WALL - 10)));
+    }
+
+    #[test]
+    fn wall_clock_synchronized_shortly_after_has_no_drift() {
+        // arrange
+        let mut clock = WallClock::default();
+        clock.synchronize(SOME_RUNNING.into(), SOME_WALL.into());
+        clock.synchronize((SOME_RUNNING + 100).into(), (SOME_WALL + 101).into());
+
+        // act
+        let actual = clock.wall_time((SOME_RUNNING + 200).into());
+
+        // assert
+        assert_eq!(clock.drift_ppm(), None);
+        assert_eq!(actual, Some(WallTime::from(SOME_WALL + 201)));
+    }
+
+    #[test]
+    fn wall_clock_synchronized_long_after_corrects_drift() {
+        // arrange
+        let mut clock = WallClock::default();
+        clock.synchronize(SOME_RUNNING.into(), SOME_WALL.into());
+        clock.synchronize((SOME_RUNNING + 1_000).into(), (SOME_WALL + 1_001).into());
+
+        // act
+        let actual = clock.wall_time((SOME_RUNNING + 3_000).into());
+
+        // assert
+        assert_eq!(clock.drift_ppm(), Some(1_000));
+        assert_eq!(actual, Some(WallTime::from(SOME_WALL + 3_003)));
+    }
+
+    #[test]
+    fn wall_clock_wall_time_jumped_restarts_drift_estimation() {
+        // arrange
+        let mut clock = WallClock::default();
+        clock.synchronize(SOME_RUNNING.into(), SOME_WALL.into());
+        clock.synchronize((SOME_RUNNING + 1_000).into(), (SOME_WALL + 5_000).into());
+
+        // act
+        let actual = clock.wall_time((SOME_RUNNING + 1_010).into());
+
+        // assert
+        assert_eq!(clock.drift_ppm(), None);
+        assert_eq!(actual, Some(WallTime::from(SOME_WALL + 5_010)));
+    }
+}
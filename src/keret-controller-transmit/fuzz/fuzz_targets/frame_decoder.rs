@@ -0,0 +1,33 @@
+#![no_main]
+
+//! feed arbitrary bytes into the frame decoder, as received from a noisy serial line
+//! the first byte chooses the size of the chunks the rest is fed in
+
+use keret_controller_transmit::{FrameDecoder, Transport};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&chunk_size, data)) = data.split_first() else {
+        return;
+    };
+
+    let mut decoder = FrameDecoder::new();
+    let mut frames = 0;
+    for chunk in data.chunks(usize::from(chunk_size).max(1)) {
+        for result in decoder.decode(chunk) {
+            frames += 1;
+
+            // whatever was decoded, must be encoded again without loss
+            if let Ok(message) = result {
+                for transport in [Transport::Binary, Transport::Text] {
+                    let frame = message.as_frame(transport).unwrap();
+                    let decoded = FrameDecoder::new().decode(&frame).next();
+                    assert_eq!(decoded.map(Result::unwrap), Some(message.clone()));
+                }
+            }
+        }
+    }
+
+    let statistics = decoder.statistics();
+    assert_eq!(statistics.good + statistics.corrupted, frames);
+});
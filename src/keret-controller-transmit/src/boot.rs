@@ -0,0 +1,30 @@
+use crate::ResetReason;
+use core::fmt::{Display, Formatter};
+use serde::{Deserialize, Serialize};
+
+/// sent once by the controller after each boot
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BootAnnouncement {
+    /// why the controller was reset before this boot
+    pub reason: ResetReason,
+    /// random id chosen at this boot, also part of every report sent until the next one
+    pub boot_id: u32,
+}
+
+impl BootAnnouncement {
+    pub fn new(reason: ResetReason, boot_id: u32) -> Self {
+        Self { reason, boot_id }
+    }
+}
+
+// display the announcement in a human-readable way
+impl Display for BootAnnouncement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "Controller booted with id {:08x}: {}",
+            self.boot_id, self.reason
+        )
+    }
+}
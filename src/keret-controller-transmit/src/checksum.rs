@@ -0,0 +1,44 @@
+//! CRC-16/CCITT-FALSE checksum protecting the content of each frame
+
+/// size of the checksum on the wire
+pub(crate) const CHECKSUM_SIZE: usize = 2;
+
+const POLYNOMIAL: u16 = 0x1021;
+const INITIAL: u16 = 0xFFFF;
+
+/// calculate the checksum of the given data
+/// computed bitwise, as frames are small and a lookup table would cost 512 bytes of flash
+pub(crate) fn checksum(data: &[u8]) -> u16 {
+    data.iter().fold(INITIAL, |crc, &byte| {
+        (0..8).fold(crc ^ ((byte as u16) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ POLYNOMIAL
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn checksum_matches_reference_value() {
+        // act
+        let actual = checksum(b"123456789");
+
+        // assert
+        assert_eq!(actual, 0x29B1);
+    }
+
+    #[test]
+    fn checksum_of_empty_data_is_initial_value() {
+        // act
+        let actual = checksum(&[]);
+
+        // assert
+        assert_eq!(actual, INITIAL);
+    }
+}
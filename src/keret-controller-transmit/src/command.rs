@@ -0,0 +1,70 @@
+use core::fmt::{Display, Formatter};
+use serde::{Deserialize, Serialize};
+
+/// instructions sent to the controller
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Command {
+    /// check the controller is responsive, answered with a `Pong`
+    Ping,
+    /// request the controller to identify itself with a `Hello`
+    Identify,
+    /// request the current state of the controller, answered with a `State`
+    QueryState,
+    /// change the category reported with the next time trackings, answered with a `State`
+    SetCategory { category: u8 },
+    /// change after how many seconds without interaction the display is dimmed and turned off,
+    /// answered with a `State`
+    SetThresholds { dim_after: u64, off_after: u64 },
+    /// start the time tracking as if the button was pressed, answered with a `State`
+    Start,
+    /// stop a running time tracking as if the button was pressed, answered with a `State`
+    Stop,
+    /// the current wall time in seconds since the unix epoch (UTC), sent periodically.
+    /// not answered
+    SyncTime { utc: u64 },
+}
+
+/// confirms a message was received and handled
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Ack {
+    /// sequence number of the confirmed message
+    pub sequence: u32,
+}
+
+impl Ack {
+    pub fn new(sequence: u32) -> Self {
+        Self { sequence }
+    }
+}
+
+// display the command in a human-readable way
+impl Display for Command {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Command::Ping => f.write_str("ping"),
+            Command::Identify => f.write_str("identify"),
+            Command::QueryState => f.write_str("query state"),
+            Command::SetCategory { category } => write!(f, "set category to {}", category),
+            Command::SetThresholds {
+                dim_after,
+                off_after,
+            } => write!(
+                f,
+                "dim display after {}s, turn it off after {}s",
+                dim_after, off_after
+            ),
+            Command::Start => f.write_str("start"),
+            Command::Stop => f.write_str("stop"),
+            Command::SyncTime { utc } => write!(f, "synchronize time to {}", utc),
+        }
+    }
+}
+
+// display the acknowledgement in a human-readable way
+impl Display for Ack {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "acknowledged #{}", self.sequence)
+    }
+}
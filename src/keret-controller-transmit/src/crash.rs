@@ -0,0 +1,77 @@
+use core::fmt::{Display, Formatter};
+use heapless::String;
+use serde::{Deserialize, Serialize};
+
+/// maximum length of the panic message in a crash report, longer messages are truncated
+pub const CRASH_MESSAGE_LENGTH: usize = 64;
+
+/// maximum length of the source file name in a crash report, longer names are truncated
+pub const CRASH_FILE_LENGTH: usize = 48;
+
+/// information about a panic of the controller, reported after the following reboot
+/// numbered like the activity reports, so receivers can drop repeated deliveries and confirm
+/// it with an `Ack` of the sequence number
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CrashReport {
+    boot_id: u32,
+    sequence: u32,
+    message: String<CRASH_MESSAGE_LENGTH>,
+    file: String<CRASH_FILE_LENGTH>,
+    line: u32,
+}
+
+impl CrashReport {
+    pub fn new(
+        message: String<CRASH_MESSAGE_LENGTH>,
+        file: String<CRASH_FILE_LENGTH>,
+        line: u32,
+    ) -> Self {
+        Self {
+            boot_id: 0,
+            sequence: 0,
+            message,
+            file,
+            line,
+        }
+    }
+
+    /// number the report with the boot id and the next sequence number of the sender
+    pub fn with_origin(mut self, boot_id: u32, sequence: u32) -> Self {
+        self.boot_id = boot_id;
+        self.sequence = sequence;
+        self
+    }
+
+    /// random id chosen by the controller at the boot the report was sent in
+    pub fn boot_id(&self) -> u32 {
+        self.boot_id
+    }
+
+    /// number of the report since boot, shared with the activity reports
+    pub fn sequence(&self) -> u32 {
+        self.sequence
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+}
+
+// display the crash report in a human-readable way
+impl Display for CrashReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "Controller panicked at {}:{}: {}",
+            self.file, self.line, self.message
+        )
+    }
+}
@@ -0,0 +1,77 @@
+use core::fmt::{Display, Formatter};
+use serde::{Deserialize, Serialize};
+
+/// diagnostic information about the controller itself, not related to time tracking
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Diagnostic {
+    /// initializing the controller failed, it will be retried after a backoff
+    InitializationFailed {
+        failure: InitializationFailure,
+        attempt: u8,
+    },
+}
+
+/// which part of the controller could not be initialized
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum InitializationFailure {
+    /// the clock keeping track of the running time
+    Clock,
+}
+
+/// why the controller was reset before the current boot
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ResetReason {
+    /// the controller was powered on
+    PowerOn,
+    /// the reset pin (or the reset button) was used
+    ResetPin,
+    /// the watchdog was not fed in time, the main loop stalled
+    Watchdog,
+    /// the firmware requested a reset itself
+    SoftReset,
+    /// the CPU locked up
+    CpuLockup,
+    /// any other reason (e.g. waking up from system off)
+    Other,
+}
+
+// display the diagnostic in a human-readable way
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Diagnostic::InitializationFailed { failure, attempt } => write!(
+                f,
+                "Controller initialization failed (attempt {}): {}",
+                attempt, failure
+            ),
+        }
+    }
+}
+
+// display the reset reason in a human-readable way
+impl Display for ResetReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let text = match self {
+            ResetReason::PowerOn => "powered on",
+            ResetReason::ResetPin => "reset pin triggered",
+            ResetReason::Watchdog => "watchdog triggered, main loop stalled",
+            ResetReason::SoftReset => "reset requested by firmware",
+            ResetReason::CpuLockup => "CPU locked up",
+            ResetReason::Other => "unknown reason",
+        };
+        f.write_str(text)
+    }
+}
+
+// display the initialization failure in a human-readable way
+impl Display for InitializationFailure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let text = match self {
+            InitializationFailure::Clock => "clock could not be initialized",
+        };
+        f.write_str(text)
+    }
+}
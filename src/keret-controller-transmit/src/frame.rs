@@ -0,0 +1,636 @@
+//! framing of messages on the wire, using one of two transports
+//! binary frames are encoded with Consistent Overhead Byte Stuffing (COBS) and terminated by a
+//! zero byte, which never shows up inside an encoded frame. Text frames are base64 encoded lines,
+//! readable (and typeable) on a serial console.
+//! a receiver can therefore always resynchronize on the next delimiter, no matter where it
+//! started listening or how much garbage it received in between.
+//! the data of each frame is followed by a CRC, so corrupted frames are rejected instead of
+//! being decoded into wrong values
+
+use crate::checksum::{checksum, CHECKSUM_SIZE};
+use crate::{
+    ChecksumMismatchSnafu, Error, FrameTooLongSnafu, InvalidFrameSnafu, Message, MAX_MESSAGE_SIZE,
+};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+/// byte terminating every binary frame
+pub const FRAME_DELIMITER: u8 = 0;
+
+/// byte terminating every text frame, preceded by a carriage return for the sake of terminals
+pub const TEXT_FRAME_DELIMITER: u8 = b'\n';
+
+const CARRIAGE_RETURN: u8 = b'\r';
+
+/// maximum size of the data inside a frame, a message followed by its checksum
+const MAX_PAYLOAD_SIZE: usize = MAX_MESSAGE_SIZE + CHECKSUM_SIZE;
+
+/// base64 decodes in blocks of 3 bytes, so the buffer needs to fit a complete last block
+const MAX_TEXT_PAYLOAD_SIZE: usize = MAX_PAYLOAD_SIZE.div_ceil(3) * 3;
+
+/// maximum size of a binary frame, including the overhead of COBS and the delimiter
+const MAX_BINARY_FRAME_SIZE: usize = MAX_PAYLOAD_SIZE + MAX_PAYLOAD_SIZE / 254 + 2;
+
+/// maximum size of a text frame, including the line ending
+const MAX_TEXT_FRAME_SIZE: usize = MAX_PAYLOAD_SIZE.div_ceil(3) * 4 + 2;
+
+/// maximum size of an encoded frame of any transport
+pub const MAX_FRAME_SIZE: usize = if MAX_TEXT_FRAME_SIZE > MAX_BINARY_FRAME_SIZE {
+    MAX_TEXT_FRAME_SIZE
+} else {
+    MAX_BINARY_FRAME_SIZE
+};
+
+/// largest block of non-zero bytes COBS can describe with a single code byte
+const MAX_BLOCK_CODE: u8 = 0xFF;
+
+/// a single encoded frame, ready to be written to the wire
+pub type Frame = heapless::Vec<u8, MAX_FRAME_SIZE>;
+
+/// how frames are encoded on the wire
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Transport {
+    /// COBS encoded frames terminated by a zero byte, compact
+    Binary,
+    /// base64 encoded lines, readable on a serial console
+    Text,
+}
+
+/// encode the given data as a single frame, including checksum and the trailing delimiter
+pub fn encode_frame(data: &[u8], transport: Transport) -> Result<Frame, Error> {
+    let crc = checksum(data).to_le_bytes();
+
+    match transport {
+        Transport::Binary => stuff(data.iter().chain(crc.iter()).copied()),
+        Transport::Text => encode_line(data, &crc),
+    }
+}
+
+/// apply the byte stuffing of COBS and terminate the frame
+fn stuff(payload: impl Iterator<Item = u8>) -> Result<Frame, Error> {
+    let mut frame = Frame::new();
+    let mut code_index = 0;
+    let mut code = 1_u8;
+    push(&mut frame, 0)?;
+
+    for byte in payload {
+        if byte != FRAME_DELIMITER {
+            push(&mut frame, byte)?;
+            code += 1;
+        }
+
+        if byte == FRAME_DELIMITER || code == MAX_BLOCK_CODE {
+            frame[code_index] = code;
+            code_index = frame.len();
+            code = 1;
+            push(&mut frame, 0)?;
+        }
+    }
+
+    frame[code_index] = code;
+    push(&mut frame, FRAME_DELIMITER)?;
+
+    Ok(frame)
+}
+
+/// encode data and checksum as base64 and terminate the line
+fn encode_line(data: &[u8], crc: &[u8]) -> Result<Frame, Error> {
+    let mut payload = heapless::Vec::<u8, MAX_PAYLOAD_SIZE>::new();
+    payload
+        .extend_from_slice(data)
+        .and_then(|()| payload.extend_from_slice(crc))
+        .map_err(|()| FrameTooLongSnafu.build())?;
+
+    let mut frame = Frame::new();
+    let _ = frame.resize_default(MAX_FRAME_SIZE);
+    let length = BASE64
+        .encode_slice(&payload, &mut frame)
+        .map_err(|_| FrameTooLongSnafu.build())?;
+    frame.truncate(length);
+
+    push(&mut frame, CARRIAGE_RETURN)?;
+    push(&mut frame, TEXT_FRAME_DELIMITER)?;
+
+    Ok(frame)
+}
+
+/// decode the content of a binary frame, given without its delimiter, and verify its checksum
+fn decode_frame(frame: &[u8]) -> Result<heapless::Vec<u8, MAX_PAYLOAD_SIZE>, Error> {
+    verify(unstuff(frame)?)
+}
+
+/// decode the content of a text frame, given without its line ending, and verify its checksum
+fn decode_line(line: &[u8]) -> Result<heapless::Vec<u8, MAX_TEXT_PAYLOAD_SIZE>, Error> {
+    let mut data = heapless::Vec::new();
+    let _ = data.resize_default(MAX_TEXT_PAYLOAD_SIZE);
+    let length = BASE64
+        .decode_slice(line, &mut data)
+        .map_err(|_| InvalidFrameSnafu.build())?;
+    data.truncate(length);
+
+    verify(data)
+}
+
+/// verify the checksum at the end of the data, returning the data without it
+fn verify<const N: usize>(mut data: heapless::Vec<u8, N>) -> Result<heapless::Vec<u8, N>, Error> {
+    if data.len() < CHECKSUM_SIZE {
+        return InvalidFrameSnafu.fail();
+    }
+    let crc_start = data.len() - CHECKSUM_SIZE;
+    let expected = u16::from_le_bytes([data[crc_start], data[crc_start + 1]]);
+    data.truncate(crc_start);
+
+    let actual = checksum(&data);
+    if actual != expected {
+        return ChecksumMismatchSnafu { expected, actual }.fail();
+    }
+
+    Ok(data)
+}
+
+/// revert the byte stuffing of COBS
+fn unstuff(frame: &[u8]) -> Result<heapless::Vec<u8, MAX_PAYLOAD_SIZE>, Error> {
+    let mut data = heapless::Vec::new();
+    let mut index = 0;
+
+    while index < frame.len() {
+        let code = frame[index];
+        let end = index + code as usize;
+        if code == FRAME_DELIMITER || end > frame.len() {
+            return InvalidFrameSnafu.fail();
+        }
+
+        for &byte in &frame[index + 1..end] {
+            push(&mut data, byte)?;
+        }
+
+        index = end;
+        if code != MAX_BLOCK_CODE && index < frame.len() {
+            push(&mut data, FRAME_DELIMITER)?;
+        }
+    }
+
+    Ok(data)
+}
+
+#[inline]
+fn push<const N: usize>(buffer: &mut heapless::Vec<u8, N>, byte: u8) -> Result<(), Error> {
+    buffer.push(byte).map_err(|_| FrameTooLongSnafu.build())
+}
+
+/// whether the byte can be part of a text frame
+#[inline]
+fn is_text(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || matches!(byte, b'+' | b'/' | b'=' | CARRIAGE_RETURN)
+}
+
+/// the received line without the newlines kept at its start, see `FrameDecoder::push`
+#[inline]
+fn text_line(buffer: &[u8]) -> &[u8] {
+    let start = buffer
+        .iter()
+        .position(|&byte| byte != TEXT_FRAME_DELIMITER)
+        .unwrap_or(buffer.len());
+    &buffer[start..]
+}
+
+/// counters about the frames seen by a `FrameDecoder`
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct FrameStatistics {
+    /// frames decoded into a message
+    pub good: u64,
+    /// frames rejected, because they were malformed, oversized or failed the checksum
+    pub corrupted: u64,
+    /// times decoding recovered with a good frame after one or more rejected frames
+    pub resynchronized: u64,
+}
+
+/// streaming decoder turning a sequence of received bytes into messages
+/// bytes may arrive in arbitrary chunks. Corrupted or oversized frames are reported as errors,
+/// after which the decoder continues with the next frame.
+/// the transport is detected automatically: the decoder sticks to the transport of the last
+/// good frame and detects it again after a frame was rejected
+#[derive(Debug, Default)]
+pub struct FrameDecoder {
+    buffer: heapless::Vec<u8, MAX_FRAME_SIZE>,
+    overflowed: bool,
+    out_of_sync: bool,
+    transport: Option<Transport>,
+    statistics: FrameStatistics,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// counters about all frames decoded so far
+    pub fn statistics(&self) -> FrameStatistics {
+        self.statistics
+    }
+
+    /// the transport used by the sender, if detected already
+    pub fn transport(&self) -> Option<Transport> {
+        self.transport
+    }
+
+    /// feed a single byte into the decoder
+    /// returns the decoded message (or the reason it could not be decoded) once a frame is complete
+    pub fn push(&mut self, byte: u8) -> Option<Result<Message, Error>> {
+        let is_delimiter = byte == FRAME_DELIMITER || byte == TEXT_FRAME_DELIMITER;
+        if self.overflowed && is_delimiter {
+            // whatever was received, it did not fit the detected transport
+            self.buffer.clear();
+            self.overflowed = false;
+            self.transport = None;
+            return Some(self.count(FrameTooLongSnafu.fail()));
+        }
+
+        // a newline starting a frame of undetected transport may just as well be the first code
+        // byte of a binary frame, the bytes following it tell which one it was
+        let undetected_start = self.transport.is_none() && self.buffer.is_empty();
+        let transport = match byte {
+            FRAME_DELIMITER if self.transport != Some(Transport::Text) => Transport::Binary,
+            TEXT_FRAME_DELIMITER
+                if !undetected_start
+                    && self.transport != Some(Transport::Binary)
+                    && text_line(&self.buffer).iter().all(|&byte| is_text(byte)) =>
+            {
+                Transport::Text
+            }
+            _ => {
+                self.overflowed |= self.buffer.push(byte).is_err();
+                return None;
+            }
+        };
+
+        let frame = match transport {
+            Transport::Binary => &self.buffer[..],
+            Transport::Text => {
+                let line = text_line(&self.buffer);
+                line.strip_suffix(&[CARRIAGE_RETURN]).unwrap_or(line)
+            }
+        };
+        if frame.is_empty() {
+            // consecutive delimiters or empty lines carry no frame, used to flush the line
+            self.buffer.clear();
+            return None;
+        }
+
+        let result = match transport {
+            Transport::Binary => decode_frame(frame).and_then(|data| Message::from_message(&data)),
+            Transport::Text => decode_line(frame).and_then(|data| Message::from_message(&data)),
+        };
+        self.buffer.clear();
+        self.transport = result.is_ok().then_some(transport);
+
+        Some(self.count(result))
+    }
+
+    /// update the statistics with the outcome of a completed frame
+    fn count(&mut self, result: Result<Message, Error>) -> Result<Message, Error> {
+        let good = result.is_ok();
+        if good {
+            self.statistics.good += 1;
+            if self.out_of_sync {
+                self.statistics.resynchronized += 1;
+            }
+        } else {
+            self.statistics.corrupted += 1;
+        }
+
+        self.out_of_sync = !good;
+
+        result
+    }
+
+    /// feed a chunk of received bytes into the decoder
+    /// returns an iterator over all frames completed by this chunk
+    pub fn decode<'d, 'b>(&'d mut self, data: &'b [u8]) -> Frames<'d, 'b> {
+        Frames {
+            decoder: self,
+            data: data.iter(),
+        }
+    }
+}
+
+/// iterator over the frames completed by a chunk of data, see `FrameDecoder::decode`
+pub struct Frames<'d, 'b> {
+    decoder: &'d mut FrameDecoder,
+    data: core::slice::Iter<'b, u8>,
+}
+
+impl Iterator for Frames<'_, '_> {
+    type Item = Result<Message, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.data.find_map(|&byte| self.decoder.push(byte))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ActionReport;
+
+    const BOOT_ID: u32 = 0xB007;
+
+    fn duration_of(message: Result<Message, Error>) -> u64 {
+        match message {
+            Ok(Message::ActionReport(report)) => report.duration(),
+            other => panic!("expected action report, got {:?}", other),
+        }
+    }
+
+    fn action_report_frame(duration: u64) -> Frame {
+        Message::ActionReport(ActionReport::new(BOOT_ID, 0, 0, duration))
+            .as_frame(Transport::Binary)
+            .unwrap()
+    }
+
+    fn action_report_line(duration: u64) -> Frame {
+        Message::ActionReport(ActionReport::new(BOOT_ID, 0, 0, duration))
+            .as_frame(Transport::Text)
+            .unwrap()
+    }
+
+    #[test]
+    fn encode_frame_replaces_zero_bytes() {
+        // act
+        let actual = encode_frame(&[0x11, 0x00, 0x00, 0x22], Transport::Binary).unwrap();
+
+        // assert
+        assert_eq!(
+            actual.as_slice(),
+            &[0x02, 0x11, 0x01, 0x04, 0x22, 0xF3, 0xED, 0x00]
+        );
+    }
+
+    #[test]
+    fn encode_frame_of_empty_data_contains_checksum() {
+        // act
+        let actual = encode_frame(&[], Transport::Binary).unwrap();
+
+        // assert
+        assert_eq!(actual.as_slice(), &[0x03, 0xFF, 0xFF, 0x00]);
+    }
+
+    #[test]
+    fn decode_frame_restores_encoded_data() {
+        // arrange
+        let data = [0x00, 0x0A, 0x00, 0xFF, 0x01, 0x00];
+        let frame = encode_frame(&data, Transport::Binary).unwrap();
+
+        // act
+        let actual = decode_frame(&frame[..frame.len() - 1]).unwrap();
+
+        // assert
+        assert_eq!(actual.as_slice(), &data);
+    }
+
+    #[test]
+    fn decode_frame_handles_long_blocks() {
+        // arrange
+        let data = [0x42; MAX_MESSAGE_SIZE];
+        let frame = encode_frame(&data, Transport::Binary).unwrap();
+
+        // act
+        let actual = decode_frame(&frame[..frame.len() - 1]).unwrap();
+
+        // assert
+        assert_eq!(actual.as_slice(), &data);
+    }
+
+    #[test]
+    fn decode_frame_rejects_truncated_block() {
+        // act
+        let actual = decode_frame(&[0x05, 0x11, 0x22]);
+
+        // assert
+        assert!(matches!(actual, Err(Error::InvalidFrame)));
+    }
+
+    #[test]
+    fn decode_frame_rejects_corrupted_data() {
+        // arrange
+        let mut frame = encode_frame(&[0x11, 0x22, 0x33], Transport::Binary).unwrap();
+        frame[2] = 0x23;
+
+        // act
+        let actual = decode_frame(&frame[..frame.len() - 1]);
+
+        // assert
+        assert!(matches!(actual, Err(Error::ChecksumMismatch { .. })));
+    }
+
+    #[test]
+    fn decoder_counts_corrupted_and_resynchronized_frames() {
+        // arrange
+        let mut corrupted = action_report_frame(300);
+        corrupted[2] ^= 0x01;
+        let mut data = Vec::new();
+        data.extend_from_slice(&action_report_frame(1));
+        data.extend_from_slice(&corrupted);
+        data.extend_from_slice(&corrupted);
+        data.extend_from_slice(&action_report_frame(2));
+        data.extend_from_slice(&action_report_frame(3));
+        let mut decoder = FrameDecoder::new();
+
+        // act
+        let results: Vec<_> = decoder.decode(&data).collect();
+        let actual = decoder.statistics();
+
+        // assert
+        assert_eq!(results.len(), 5);
+        assert_eq!(
+            actual,
+            FrameStatistics {
+                good: 3,
+                corrupted: 2,
+                resynchronized: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn decoder_returns_message_split_over_chunks() {
+        // arrange
+        let frame = action_report_frame(256);
+        let (first, second) = frame.split_at(2);
+        let mut decoder = FrameDecoder::new();
+
+        // act
+        let first_frames = decoder.decode(first).count();
+        let actual: Vec<_> = decoder.decode(second).collect();
+
+        // assert
+        assert_eq!(first_frames, 0);
+        assert_eq!(actual.len(), 1);
+        assert_eq!(duration_of(actual.into_iter().next().unwrap()), 256);
+    }
+
+    #[test]
+    fn decoder_returns_all_messages_of_chunk() {
+        // arrange
+        let mut data = Vec::new();
+        for duration in [1, 0, 10] {
+            data.extend_from_slice(&action_report_frame(duration));
+        }
+        let mut decoder = FrameDecoder::new();
+
+        // act
+        let actual: Vec<_> = decoder.decode(&data).map(duration_of).collect();
+
+        // assert
+        assert_eq!(actual, vec![1, 0, 10]);
+    }
+
+    #[test]
+    fn decoder_resynchronizes_after_garbage() {
+        // arrange
+        let frame = action_report_frame(42);
+        let mut data = vec![0x13, 0x37, 0xFF, FRAME_DELIMITER];
+        data.extend_from_slice(&frame);
+        let mut decoder = FrameDecoder::new();
+
+        // act
+        let mut actual = decoder.decode(&data);
+
+        // assert
+        assert!(actual.next().unwrap().is_err());
+        assert_eq!(duration_of(actual.next().unwrap()), 42);
+        assert!(actual.next().is_none());
+    }
+
+    #[test]
+    fn decoder_rejects_oversized_frame() {
+        // arrange
+        let frame = action_report_frame(42);
+        let mut data = vec![0x01; MAX_FRAME_SIZE * 2];
+        data.push(FRAME_DELIMITER);
+        data.extend_from_slice(&frame);
+        let mut decoder = FrameDecoder::new();
+
+        // act
+        let mut actual = decoder.decode(&data);
+
+        // assert
+        assert!(matches!(actual.next(), Some(Err(Error::FrameTooLong))));
+        assert_eq!(duration_of(actual.next().unwrap()), 42);
+    }
+
+    #[test]
+    fn decoder_ignores_empty_frames() {
+        // arrange
+        let mut decoder = FrameDecoder::new();
+
+        // act
+        let actual = decoder.decode(&[FRAME_DELIMITER; 3]).count();
+
+        // assert
+        assert_eq!(actual, 0);
+    }
+
+    #[test]
+    fn encode_frame_as_text_is_printable_line() {
+        // act
+        let actual = encode_frame(&[0x11, 0x00, 0x00, 0x22], Transport::Text).unwrap();
+
+        // assert
+        assert_eq!(actual.as_slice(), b"EQAAIvPt\r\n");
+    }
+
+    #[test]
+    fn decode_line_restores_encoded_data() {
+        // arrange
+        let data = [0x42; MAX_MESSAGE_SIZE];
+        let frame = encode_frame(&data, Transport::Text).unwrap();
+
+        // act
+        let actual = decode_line(&frame[..frame.len() - 2]).unwrap();
+
+        // assert
+        assert_eq!(actual.as_slice(), &data);
+    }
+
+    #[test]
+    fn decoder_detects_text_transport() {
+        // arrange
+        let mut data = Vec::new();
+        data.extend_from_slice(&action_report_line(1));
+        data.extend_from_slice(&action_report_line(10));
+        let mut decoder = FrameDecoder::new();
+
+        // act
+        let actual: Vec<_> = decoder.decode(&data).map(duration_of).collect();
+
+        // assert
+        assert_eq!(actual, vec![1, 10]);
+        assert_eq!(decoder.transport(), Some(Transport::Text));
+    }
+
+    #[test]
+    fn decoder_sticks_to_binary_transport_with_line_breaks_in_frame() {
+        // arrange
+        let mut data = Vec::new();
+        data.extend_from_slice(&action_report_frame(1));
+        data.extend_from_slice(&action_report_frame(u64::from(TEXT_FRAME_DELIMITER)));
+        let mut decoder = FrameDecoder::new();
+
+        // act
+        let actual: Vec<_> = decoder.decode(&data).map(duration_of).collect();
+
+        // assert
+        assert_eq!(actual, vec![1, 10]);
+        assert_eq!(decoder.transport(), Some(Transport::Binary));
+    }
+
+    #[test]
+    fn decoder_detects_binary_frame_starting_with_line_break() {
+        // arrange -> nine bytes without zero make the first code byte a line break
+        let frame = Message::Ack(crate::Ack::new(0x1000_0003))
+            .as_frame(Transport::Binary)
+            .unwrap();
+        assert_eq!(frame[0], TEXT_FRAME_DELIMITER);
+        let mut decoder = FrameDecoder::new();
+
+        // act
+        let actual: Vec<_> = decoder.decode(&frame).map(Result::unwrap).collect();
+
+        // assert
+        assert_eq!(actual, vec![Message::Ack(crate::Ack::new(0x1000_0003))]);
+        assert_eq!(decoder.transport(), Some(Transport::Binary));
+    }
+
+    #[test]
+    fn decoder_switches_transport_after_overflow() {
+        // arrange
+        let mut data = Vec::new();
+        data.extend_from_slice(&action_report_frame(1));
+        // the lines lack any binary delimiter, so they fill up the buffer first
+        for _ in 0..15 {
+            data.extend_from_slice(&action_report_line(2));
+        }
+        let mut decoder = FrameDecoder::new();
+
+        // act
+        let actual: Vec<_> = decoder.decode(&data).collect();
+
+        // assert
+        assert!(matches!(actual.last(), Some(Ok(Message::ActionReport(_)))));
+        assert_eq!(decoder.transport(), Some(Transport::Text));
+    }
+
+    #[test]
+    fn decoder_ignores_empty_lines() {
+        // arrange
+        let mut decoder = FrameDecoder::new();
+
+        // act
+        let actual = decoder.decode(b"\r\n\n").count();
+
+        // assert
+        assert_eq!(actual, 0);
+    }
+}
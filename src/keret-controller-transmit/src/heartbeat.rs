@@ -0,0 +1,36 @@
+use crate::ControllerMode;
+use core::fmt::{Display, Formatter};
+use serde::{Deserialize, Serialize};
+
+/// sent periodically by the controller to signal it is still alive
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Heartbeat {
+    /// seconds since the controller booted
+    pub uptime: u64,
+    /// what the controller is currently doing
+    pub mode: ControllerMode,
+    /// how many reports were sent, but not acknowledged yet
+    pub unacknowledged: u8,
+}
+
+impl Heartbeat {
+    pub fn new(uptime: u64, mode: ControllerMode, unacknowledged: u8) -> Self {
+        Self {
+            uptime,
+            mode,
+            unacknowledged,
+        }
+    }
+}
+
+// display the heartbeat in a human-readable way
+impl Display for Heartbeat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "Controller {} and alive for {}s, {} unacknowledged reports",
+            self.mode, self.uptime, self.unacknowledged
+        )
+    }
+}
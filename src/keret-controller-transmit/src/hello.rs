@@ -0,0 +1,60 @@
+use core::fmt::{Display, Formatter};
+use serde::{Deserialize, Serialize};
+
+/// identifies the controller
+/// sent by the controller at boot and whenever requested with `Command::Identify`.
+/// the protocol it speaks is the version prefixing every message, decoding fails for others
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Hello {
+    /// version of the firmware running on the controller
+    pub firmware_version: FirmwareVersion,
+    /// unique serial number of the device, as programmed into the chip by the manufacturer
+    pub serial_number: u64,
+}
+
+/// semantic version of the controller firmware
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FirmwareVersion {
+    pub major: u8,
+    pub minor: u8,
+    pub patch: u8,
+}
+
+impl Hello {
+    pub fn new(firmware_version: FirmwareVersion, serial_number: u64) -> Self {
+        Self {
+            firmware_version,
+            serial_number,
+        }
+    }
+}
+
+impl FirmwareVersion {
+    pub fn new(major: u8, minor: u8, patch: u8) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+// display the hello in a human-readable way
+impl Display for Hello {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "Controller {:016x} running firmware {}",
+            self.serial_number, self.firmware_version
+        )
+    }
+}
+
+// display the firmware version as usual for semantic versions
+impl Display for FirmwareVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
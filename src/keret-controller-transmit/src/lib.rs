@@ -1,4 +1,4 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
 // we need the postcard wrapper only on "no_std" environments as core::error::Error is not yet
 // supported in stabled fashion in snafu.
@@ -7,7 +7,31 @@
 #[cfg(not(feature = "std"))]
 mod postcard_error;
 
-use postcard::{from_bytes, to_vec};
+mod boot;
+mod checksum;
+mod command;
+mod crash;
+mod diagnostic;
+mod frame;
+mod heartbeat;
+mod hello;
+mod log;
+mod state;
+
+pub use boot::BootAnnouncement;
+pub use command::{Ack, Command};
+pub use crash::{CrashReport, CRASH_FILE_LENGTH, CRASH_MESSAGE_LENGTH};
+pub use diagnostic::{Diagnostic, InitializationFailure, ResetReason};
+pub use frame::{
+    encode_frame, Frame, FrameDecoder, FrameStatistics, Frames, Transport, FRAME_DELIMITER,
+    MAX_FRAME_SIZE, TEXT_FRAME_DELIMITER,
+};
+pub use heartbeat::Heartbeat;
+pub use hello::{FirmwareVersion, Hello};
+pub use log::{LogEvent, LogLevel, LogRecord};
+pub use state::{ControllerMode, ControllerState};
+
+use postcard::{from_bytes, take_from_bytes, to_vec};
 use serde::{self, Deserialize, Serialize};
 use snafu::{ResultExt, Snafu};
 
@@ -31,29 +55,204 @@ pub enum Error {
         #[snafu(source(from(postcard::Error, postcard_error::PostcardError::new)))]
         source: postcard_error::PostcardError,
     },
+    #[snafu(display("Frame is not properly encoded"))]
+    InvalidFrame,
+    #[snafu(display("Frame exceeds the maximum size"))]
+    FrameTooLong,
+    #[snafu(display("Frame checksum {actual:#06x} does not match {expected:#06x}"))]
+    ChecksumMismatch { expected: u16, actual: u16 },
+    #[snafu(display("Protocol version {version} is not supported, expected {PROTOCOL_VERSION}"))]
+    UnsupportedVersion { version: u8 },
+}
+
+/// version of the wire protocol, increased on every incompatible change of `Message`
+pub const PROTOCOL_VERSION: u8 = 7;
+
+/// maximum size of a single serialized message
+pub const MAX_MESSAGE_SIZE: usize = 144;
+
+/// all messages exchanged between controller and adapter
+/// new variants are only added at the end, so the encoding of existing ones stays stable
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Message {
+    /// result of a finished time tracking
+    ActionReport(ActionReport),
+    /// diagnostic information about the controller itself
+    Diagnostic(Diagnostic),
+    /// structured log record of the controller
+    Log(LogRecord),
+    /// information about a panic, sent after the following reboot
+    CrashReport(CrashReport),
+    /// the controller just booted
+    BootAnnouncement(BootAnnouncement),
+    /// the controller is still alive
+    Heartbeat(Heartbeat),
+    /// confirmation of a received message, sent by either side
+    Ack(Ack),
+    /// instruction for the controller
+    Command(Command),
+    /// identification of the controller
+    Hello(Hello),
+    /// current state of the controller, in reply to a command
+    State(ControllerState),
+    /// reply to a `Command::Ping`
+    Pong,
+}
+
+impl Message {
+    /// deserialize a message, which is prefixed by the protocol version it was serialized with
+    pub fn from_message(data: &[u8]) -> Result<Self, Error> {
+        let (version, payload) = take_from_bytes::<u8>(data).context(CantDeserializeSnafu)?;
+        if version != PROTOCOL_VERSION {
+            return UnsupportedVersionSnafu { version }.fail();
+        }
+
+        from_bytes(payload).context(CantDeserializeSnafu)
+    }
+
+    /// serialize the message, prefixed by the current protocol version
+    pub fn as_message(&self) -> Result<heapless::Vec<u8, MAX_MESSAGE_SIZE>, Error> {
+        to_vec(&(PROTOCOL_VERSION, self)).context(CantSerializeSnafu)
+    }
+
+    /// serialize the message and encode it as a single frame, ready to be sent over the wire
+    pub fn as_frame(&self, transport: Transport) -> Result<Frame, Error> {
+        encode_frame(&self.as_message()?, transport)
+    }
 }
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
-#[repr(transparent)]
-#[serde(transparent)]
+/// result of a finished time tracking
+/// boot id and sequence number identify the report, so receivers can drop repeated deliveries
+/// and confirm it with an `Ack` of the sequence number
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ActionReport {
+    boot_id: u32,
+    sequence: u32,
+    category: u8,
     duration: u64,
+    finished_at: Option<u64>,
 }
 
 impl ActionReport {
-    pub fn new(duration: u64) -> Self {
-        Self { duration }
+    pub fn new(boot_id: u32, sequence: u32, category: u8, duration: u64) -> Self {
+        Self {
+            boot_id,
+            sequence,
+            category,
+            duration,
+            finished_at: None,
+        }
     }
 
-    pub fn from_message(data: &[u8]) -> Result<Self, Error> {
-        from_bytes(data).context(CantDeserializeSnafu)
+    /// mark when the time tracking finished, if the controller knows the wall time
+    pub fn with_finished_at(mut self, finished_at: Option<u64>) -> Self {
+        self.finished_at = finished_at;
+        self
+    }
+
+    /// random id chosen by the controller at boot
+    pub fn boot_id(&self) -> u32 {
+        self.boot_id
     }
 
-    pub fn as_message(&self) -> Result<heapless::Vec<u8, 8>, Error> {
-        to_vec(&self).context(CantSerializeSnafu)
+    /// number of the report since boot, increasing with every report
+    pub fn sequence(&self) -> u32 {
+        self.sequence
+    }
+
+    /// category of the tracked activity, as set remotely
+    pub fn category(&self) -> u8 {
+        self.category
     }
 
     pub fn duration(&self) -> u64 {
         self.duration
     }
+
+    /// wall time (seconds since the unix epoch, UTC) when the time tracking finished
+    pub fn finished_at(&self) -> Option<u64> {
+        self.finished_at
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn as_message_is_prefixed_by_version() {
+        // arrange
+        let message = Message::Ack(Ack::new(7));
+
+        // act
+        let actual = message.as_message().unwrap();
+
+        // assert
+        assert_eq!(actual.as_slice(), &[PROTOCOL_VERSION, 6, 7]);
+    }
+
+    #[test]
+    fn from_message_restores_all_message_types() {
+        // arrange
+        let messages = [
+            Message::ActionReport(ActionReport::new(0xB007, 3, 2, 3_600)),
+            Message::ActionReport(
+                ActionReport::new(0xB007, 4, 2, 60).with_finished_at(Some(1_700_000_000)),
+            ),
+            Message::Diagnostic(Diagnostic::InitializationFailed {
+                failure: InitializationFailure::Clock,
+                attempt: 2,
+            }),
+            Message::Log(LogRecord::error(LogEvent::SerialWriteFailed)),
+            Message::CrashReport(
+                CrashReport::new("oops".into(), "src/main.rs".into(), 42).with_origin(0xB007, 5),
+            ),
+            Message::BootAnnouncement(BootAnnouncement::new(ResetReason::Watchdog, 0xB007)),
+            Message::Heartbeat(Heartbeat::new(120, ControllerMode::Idle, 1)),
+            Message::Ack(Ack::new(1)),
+            Message::Command(Command::Ping),
+            Message::Command(Command::Identify),
+            Message::Command(Command::QueryState),
+            Message::Command(Command::SetCategory { category: 2 }),
+            Message::Command(Command::SetThresholds {
+                dim_after: 30,
+                off_after: 300,
+            }),
+            Message::Command(Command::Start),
+            Message::Command(Command::Stop),
+            Message::Command(Command::SyncTime { utc: 1_700_000_000 }),
+            Message::Hello(Hello::new(
+                FirmwareVersion::new(0, 1, 0),
+                0x0123_4567_89AB_CDEF,
+            )),
+            Message::State(ControllerState::new(
+                ControllerMode::Running { since: 120 },
+                2,
+            )),
+            Message::Pong,
+        ];
+
+        for message in messages {
+            // act
+            let actual = Message::from_message(&message.as_message().unwrap()).unwrap();
+
+            // assert
+            assert_eq!(actual, message);
+        }
+    }
+
+    #[test]
+    fn from_message_rejects_other_versions() {
+        // arrange
+        let data = [PROTOCOL_VERSION + 1, 6, 7];
+
+        // act
+        let actual = Message::from_message(&data);
+
+        // assert
+        assert!(matches!(
+            actual,
+            Err(Error::UnsupportedVersion { version }) if version == PROTOCOL_VERSION + 1
+        ));
+    }
 }
@@ -0,0 +1,92 @@
+use core::fmt::{Display, Formatter};
+use serde::{Deserialize, Serialize};
+
+/// a compact, structured log record emitted by the controller
+/// only the event (and its arguments) is transmitted, the text belongs to the receiving side
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LogRecord {
+    pub level: LogLevel,
+    pub event: LogEvent,
+}
+
+/// how severe a logged event is
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+/// everything the controller logs
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LogEvent {
+    /// the clock keeping track of the running time could not be initialized
+    ClockInitializationFailed,
+    /// writing data to the serial port failed
+    SerialWriteFailed,
+    /// a message could not be serialized
+    MessageSerializationFailed,
+    /// a time tracking ended before it started
+    IncoherentTimestamps { start: u64, end: u64 },
+    /// a received frame could not be decoded
+    ReceivedFrameRejected,
+    /// received bytes were dropped, as they were not handled in time
+    ReceiveBufferOverflow,
+    /// a report was dropped before it was acknowledged, as too many were waiting
+    UnacknowledgedReportDropped { sequence: u32 },
+}
+
+impl LogRecord {
+    /// create a new log record for an error
+    pub fn error(event: LogEvent) -> Self {
+        Self {
+            level: LogLevel::Error,
+            event,
+        }
+    }
+}
+
+// display the log record in a human-readable way
+impl Display for LogRecord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "[{}] {}", self.level, self.event)
+    }
+}
+
+// display the log level in a human-readable way
+impl Display for LogLevel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let text = match self {
+            LogLevel::Error => "ERROR",
+            LogLevel::Warn => "WARN",
+            LogLevel::Info => "INFO",
+            LogLevel::Debug => "DEBUG",
+        };
+        f.write_str(text)
+    }
+}
+
+// display the log event in a human-readable way
+impl Display for LogEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            LogEvent::ClockInitializationFailed => f.write_str("Failed to initialize the clock"),
+            LogEvent::SerialWriteFailed => f.write_str("Failed writing data to the serial port"),
+            LogEvent::MessageSerializationFailed => f.write_str("Failed to serialize message"),
+            LogEvent::IncoherentTimestamps { start, end } => write!(
+                f,
+                "Incoherent timestamps. Started at {} & ended at {}",
+                start, end
+            ),
+            LogEvent::ReceivedFrameRejected => f.write_str("Rejected a received frame"),
+            LogEvent::ReceiveBufferOverflow => f.write_str("Dropped received data"),
+            LogEvent::UnacknowledgedReportDropped { sequence } => {
+                write!(f, "Dropped report #{} before it was acknowledged", sequence)
+            }
+        }
+    }
+}
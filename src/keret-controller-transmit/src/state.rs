@@ -0,0 +1,47 @@
+use core::fmt::{Display, Formatter};
+use serde::{Deserialize, Serialize};
+
+/// what the controller is currently doing
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ControllerMode {
+    /// no time tracking is running
+    Idle,
+    /// a time tracking is running since the given running time (in seconds)
+    Running { since: u64 },
+    /// the controller ran into a recoverable error
+    Error,
+}
+
+/// current state of the controller, sent in reply to commands
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ControllerState {
+    pub mode: ControllerMode,
+    /// category reported with the next time tracking
+    pub category: u8,
+}
+
+impl ControllerState {
+    pub fn new(mode: ControllerMode, category: u8) -> Self {
+        Self { mode, category }
+    }
+}
+
+// display the mode in a human-readable way
+impl Display for ControllerMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ControllerMode::Idle => f.write_str("idle"),
+            ControllerMode::Running { since } => write!(f, "running since {}s", since),
+            ControllerMode::Error => f.write_str("error"),
+        }
+    }
+}
+
+// display the state in a human-readable way
+impl Display for ControllerState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Controller {}, category {}", self.mode, self.category)
+    }
+}
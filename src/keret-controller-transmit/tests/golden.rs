@@ -0,0 +1,247 @@
+//! golden test vectors of the wire protocol
+//! every message type is pinned to the exact bytes on the wire, in both transports.
+//! the vectors are checked against the encoder used by the controller and the decoder used by
+//! the adapter, so both sides are guaranteed to agree.
+//! if a vector changes, the wire protocol changed: increase `PROTOCOL_VERSION` and update all
+//! vectors
+
+use keret_controller_transmit::{
+    Ack, ActionReport, BootAnnouncement, Command, ControllerMode, ControllerState, CrashReport,
+    Diagnostic, FirmwareVersion, FrameDecoder, Heartbeat, Hello, InitializationFailure, LogEvent,
+    LogRecord, Message, ResetReason, Transport,
+};
+
+/// a message together with its encoding in both transports
+struct GoldenVector {
+    name: &'static str,
+    message: Message,
+    binary: &'static [u8],
+    text: &'static str,
+}
+
+fn golden_vectors() -> Vec<GoldenVector> {
+    vec![
+        GoldenVector {
+            name: "action_report",
+            message: Message::ActionReport(ActionReport::new(0xB007, 3, 2, 3_600)),
+            binary: &[
+                0x02, 0x07, 0x08, 0x87, 0xe0, 0x02, 0x03, 0x02, 0x90, 0x1c, 0x03, 0x64, 0x69, 0x00,
+            ],
+            text: "BwCH4AIDApAcAGRp\r\n",
+        },
+        GoldenVector {
+            name: "action_report_finished",
+            message: Message::ActionReport(
+                ActionReport::new(0xB007, 4, 0, 60).with_finished_at(Some(1_700_000_000)),
+            ),
+            binary: &[
+                0x02, 0x07, 0x05, 0x87, 0xe0, 0x02, 0x04, 0x0a, 0x3c, 0x01, 0x80, 0xe2, 0xcf, 0xaa,
+                0x06, 0x47, 0xc3, 0x00,
+            ],
+            text: "BwCH4AIEADwBgOLPqgZHww==\r\n",
+        },
+        GoldenVector {
+            name: "diagnostic",
+            message: Message::Diagnostic(Diagnostic::InitializationFailed {
+                failure: InitializationFailure::Clock,
+                attempt: 2,
+            }),
+            binary: &[0x03, 0x07, 0x01, 0x01, 0x04, 0x02, 0x2e, 0x20, 0x00],
+            text: "BwEAAAIuIA==\r\n",
+        },
+        GoldenVector {
+            name: "log",
+            message: Message::Log(LogRecord::error(LogEvent::IncoherentTimestamps {
+                start: 300,
+                end: 200,
+            })),
+            binary: &[
+                0x03, 0x07, 0x02, 0x08, 0x03, 0xac, 0x02, 0xc8, 0x01, 0xef, 0x3c, 0x00,
+            ],
+            text: "BwIAA6wCyAHvPA==\r\n",
+        },
+        GoldenVector {
+            name: "crash_report",
+            message: Message::CrashReport(
+                CrashReport::new("oops".into(), "src/main.rs".into(), 42).with_origin(0xB007, 5),
+            ),
+            binary: &[
+                0x1b, 0x07, 0x03, 0x87, 0xe0, 0x02, 0x05, 0x04, 0x6f, 0x6f, 0x70, 0x73, 0x0b, 0x73,
+                0x72, 0x63, 0x2f, 0x6d, 0x61, 0x69, 0x6e, 0x2e, 0x72, 0x73, 0x2a, 0x19, 0x34, 0x00,
+            ],
+            text: "BwOH4AIFBG9vcHMLc3JjL21haW4ucnMqGTQ=\r\n",
+        },
+        GoldenVector {
+            name: "boot_announcement",
+            message: Message::BootAnnouncement(BootAnnouncement::new(
+                ResetReason::Watchdog,
+                0xB007,
+            )),
+            binary: &[0x09, 0x07, 0x04, 0x02, 0x87, 0xe0, 0x02, 0x05, 0x2c, 0x00],
+            text: "BwQCh+ACBSw=\r\n",
+        },
+        GoldenVector {
+            name: "heartbeat",
+            message: Message::Heartbeat(Heartbeat::new(
+                120,
+                ControllerMode::Running { since: 100 },
+                1,
+            )),
+            binary: &[0x09, 0x07, 0x05, 0x78, 0x01, 0x64, 0x01, 0x4f, 0xc2, 0x00],
+            text: "BwV4AWQBT8I=\r\n",
+        },
+        GoldenVector {
+            name: "ack",
+            message: Message::Ack(Ack::new(7)),
+            binary: &[0x06, 0x07, 0x06, 0x07, 0x4d, 0x93, 0x00],
+            text: "BwYHTZM=\r\n",
+        },
+        GoldenVector {
+            name: "command_ping",
+            message: Message::Command(Command::Ping),
+            binary: &[0x03, 0x07, 0x07, 0x03, 0x9b, 0xd0, 0x00],
+            text: "BwcAm9A=\r\n",
+        },
+        GoldenVector {
+            name: "command_identify",
+            message: Message::Command(Command::Identify),
+            binary: &[0x06, 0x07, 0x07, 0x01, 0xba, 0xc0, 0x00],
+            text: "BwcBusA=\r\n",
+        },
+        GoldenVector {
+            name: "command_query_state",
+            message: Message::Command(Command::QueryState),
+            binary: &[0x06, 0x07, 0x07, 0x02, 0xd9, 0xf0, 0x00],
+            text: "BwcC2fA=\r\n",
+        },
+        GoldenVector {
+            name: "command_set_category",
+            message: Message::Command(Command::SetCategory { category: 2 }),
+            binary: &[0x07, 0x07, 0x07, 0x03, 0x02, 0x6c, 0x25, 0x00],
+            text: "BwcDAmwl\r\n",
+        },
+        GoldenVector {
+            name: "command_set_thresholds",
+            message: Message::Command(Command::SetThresholds {
+                dim_after: 30,
+                off_after: 300,
+            }),
+            binary: &[0x09, 0x07, 0x07, 0x04, 0x1e, 0xac, 0x02, 0x47, 0x4b, 0x00],
+            text: "BwcEHqwCR0s=\r\n",
+        },
+        GoldenVector {
+            name: "command_start",
+            message: Message::Command(Command::Start),
+            binary: &[0x06, 0x07, 0x07, 0x05, 0x3e, 0x80, 0x00],
+            text: "BwcFPoA=\r\n",
+        },
+        GoldenVector {
+            name: "command_stop",
+            message: Message::Command(Command::Stop),
+            binary: &[0x06, 0x07, 0x07, 0x06, 0x5d, 0xb0, 0x00],
+            text: "BwcGXbA=\r\n",
+        },
+        GoldenVector {
+            name: "command_sync_time",
+            message: Message::Command(Command::SyncTime { utc: 1_700_000_000 }),
+            binary: &[
+                0x0b, 0x07, 0x07, 0x07, 0x80, 0xe2, 0xcf, 0xaa, 0x06, 0xc4, 0xf4, 0x00,
+            ],
+            text: "BwcHgOLPqgbE9A==\r\n",
+        },
+        GoldenVector {
+            name: "hello",
+            message: Message::Hello(Hello::new(
+                FirmwareVersion::new(0, 1, 0),
+                0x0123_4567_89AB_CDEF,
+            )),
+            binary: &[
+                0x03, 0x07, 0x08, 0x02, 0x01, 0x0c, 0xef, 0x9b, 0xaf, 0xcd, 0xf8, 0xac, 0xd1, 0x91,
+                0x01, 0xd3, 0x8d, 0x00,
+            ],
+            text: "BwgAAQDvm6/N+KzRkQHTjQ==\r\n",
+        },
+        GoldenVector {
+            name: "state",
+            message: Message::State(ControllerState::new(ControllerMode::Idle, 2)),
+            binary: &[0x03, 0x07, 0x09, 0x04, 0x02, 0x3e, 0x6b, 0x00],
+            text: "BwkAAj5r\r\n",
+        },
+        GoldenVector {
+            name: "pong",
+            message: Message::Pong,
+            binary: &[0x05, 0x07, 0x0a, 0xd2, 0x25, 0x00],
+            text: "BwrSJQ==\r\n",
+        },
+    ]
+}
+
+/// decode all frames of the data, as the adapter does with the bytes read from the port
+fn decode_all(data: &[u8]) -> Vec<Message> {
+    let mut decoder = FrameDecoder::new();
+    decoder
+        .decode(data)
+        .map(|message| message.expect("golden vector must decode"))
+        .collect()
+}
+
+#[test]
+fn golden_vectors_match_binary_encoding() {
+    for vector in golden_vectors() {
+        // act
+        let actual = vector.message.as_frame(Transport::Binary).unwrap();
+
+        // assert
+        assert_eq!(actual.as_slice(), vector.binary, "{}", vector.name);
+    }
+}
+
+#[test]
+fn golden_vectors_match_text_encoding() {
+    for vector in golden_vectors() {
+        // act
+        let actual = vector.message.as_frame(Transport::Text).unwrap();
+
+        // assert
+        assert_eq!(actual.as_slice(), vector.text.as_bytes(), "{}", vector.name);
+    }
+}
+
+#[test]
+fn golden_vectors_decode_from_binary() {
+    for vector in golden_vectors() {
+        // act
+        let actual = decode_all(vector.binary);
+
+        // assert
+        assert_eq!(actual, vec![vector.message], "{}", vector.name);
+    }
+}
+
+#[test]
+fn golden_vectors_decode_from_text() {
+    for vector in golden_vectors() {
+        // act
+        let actual = decode_all(vector.text.as_bytes());
+
+        // assert
+        assert_eq!(actual, vec![vector.message], "{}", vector.name);
+    }
+}
+
+#[test]
+fn golden_vectors_decode_from_single_stream() {
+    // arrange
+    let vectors = golden_vectors();
+    let stream: Vec<u8> = vectors
+        .iter()
+        .flat_map(|vector| vector.binary.iter().copied())
+        .collect();
+
+    // act
+    let actual = decode_all(&stream);
+
+    // assert
+    let expected: Vec<Message> = vectors.into_iter().map(|vector| vector.message).collect();
+    assert_eq!(actual, expected);
+}
@@ -0,0 +1,224 @@
+//! property tests of the wire protocol: whatever the controller encodes, the adapter decodes
+//! run with and without the `std` feature, so both builds are guaranteed to agree
+
+use keret_controller_transmit::{
+    Ack, ActionReport, BootAnnouncement, Command, ControllerMode, ControllerState, CrashReport,
+    Diagnostic, FirmwareVersion, FrameDecoder, Heartbeat, Hello, InitializationFailure, LogEvent,
+    LogLevel, LogRecord, Message, ResetReason, Transport, CRASH_FILE_LENGTH, CRASH_MESSAGE_LENGTH,
+    FRAME_DELIMITER,
+};
+use proptest::prelude::*;
+
+// strategies generating every message type
+
+fn controller_mode() -> impl Strategy<Value = ControllerMode> {
+    prop_oneof![
+        Just(ControllerMode::Idle),
+        any::<u64>().prop_map(|since| ControllerMode::Running { since }),
+        Just(ControllerMode::Error),
+    ]
+}
+
+fn command() -> impl Strategy<Value = Command> {
+    prop_oneof![
+        Just(Command::Ping),
+        Just(Command::Identify),
+        Just(Command::QueryState),
+        any::<u8>().prop_map(|category| Command::SetCategory { category }),
+        (any::<u64>(), any::<u64>()).prop_map(|(dim_after, off_after)| Command::SetThresholds {
+            dim_after,
+            off_after
+        }),
+        Just(Command::Start),
+        Just(Command::Stop),
+        any::<u64>().prop_map(|utc| Command::SyncTime { utc }),
+    ]
+}
+
+fn log_record() -> impl Strategy<Value = LogRecord> {
+    let level = prop_oneof![
+        Just(LogLevel::Error),
+        Just(LogLevel::Warn),
+        Just(LogLevel::Info),
+        Just(LogLevel::Debug),
+    ];
+    let event = prop_oneof![
+        Just(LogEvent::ClockInitializationFailed),
+        Just(LogEvent::SerialWriteFailed),
+        Just(LogEvent::MessageSerializationFailed),
+        (any::<u64>(), any::<u64>())
+            .prop_map(|(start, end)| LogEvent::IncoherentTimestamps { start, end }),
+        Just(LogEvent::ReceivedFrameRejected),
+        Just(LogEvent::ReceiveBufferOverflow),
+        any::<u32>().prop_map(|sequence| LogEvent::UnacknowledgedReportDropped { sequence }),
+    ];
+    (level, event).prop_map(|(level, event)| LogRecord { level, event })
+}
+
+fn reset_reason() -> impl Strategy<Value = ResetReason> {
+    prop_oneof![
+        Just(ResetReason::PowerOn),
+        Just(ResetReason::ResetPin),
+        Just(ResetReason::Watchdog),
+        Just(ResetReason::SoftReset),
+        Just(ResetReason::CpuLockup),
+        Just(ResetReason::Other),
+    ]
+}
+
+fn crash_report() -> impl Strategy<Value = CrashReport> {
+    // printable ascii up to the full length, so the largest reports are covered as well
+    let message = proptest::string::string_regex(&format!("[ -~]{{0,{}}}", CRASH_MESSAGE_LENGTH))
+        .unwrap();
+    let file =
+        proptest::string::string_regex(&format!("[ -~]{{0,{}}}", CRASH_FILE_LENGTH)).unwrap();
+    (message, file, any::<u32>(), any::<u32>(), any::<u32>()).prop_map(
+        |(message, file, line, boot_id, sequence)| {
+            CrashReport::new(message.as_str().into(), file.as_str().into(), line)
+                .with_origin(boot_id, sequence)
+        },
+    )
+}
+
+fn message() -> impl Strategy<Value = Message> {
+    prop_oneof![
+        (
+            any::<u32>(),
+            any::<u32>(),
+            any::<u8>(),
+            any::<u64>(),
+            any::<Option<u64>>()
+        )
+            .prop_map(|(boot_id, sequence, category, duration, finished_at)| {
+                Message::ActionReport(
+                    ActionReport::new(boot_id, sequence, category, duration)
+                        .with_finished_at(finished_at),
+                )
+            }),
+        any::<u8>().prop_map(|attempt| Message::Diagnostic(Diagnostic::InitializationFailed {
+            failure: InitializationFailure::Clock,
+            attempt
+        })),
+        log_record().prop_map(Message::Log),
+        crash_report().prop_map(Message::CrashReport),
+        (reset_reason(), any::<u32>()).prop_map(|(reason, boot_id)| Message::BootAnnouncement(
+            BootAnnouncement::new(reason, boot_id)
+        )),
+        (any::<u64>(), controller_mode(), any::<u8>()).prop_map(
+            |(uptime, mode, unacknowledged)| {
+                Message::Heartbeat(Heartbeat::new(uptime, mode, unacknowledged))
+            }
+        ),
+        any::<u32>().prop_map(|sequence| Message::Ack(Ack::new(sequence))),
+        command().prop_map(Message::Command),
+        (any::<(u8, u8, u8)>(), any::<u64>()).prop_map(
+            |((major, minor, patch), serial_number)| {
+                Message::Hello(Hello::new(
+                    FirmwareVersion::new(major, minor, patch),
+                    serial_number,
+                ))
+            }
+        ),
+        (controller_mode(), any::<u8>())
+            .prop_map(|(mode, category)| Message::State(ControllerState::new(mode, category))),
+        Just(Message::Pong),
+    ]
+}
+
+fn transport() -> impl Strategy<Value = Transport> {
+    prop_oneof![Just(Transport::Binary), Just(Transport::Text)]
+}
+
+/// encode all messages into a single stream of frames
+fn encode_stream(messages: &[Message], transport: Transport) -> Vec<u8> {
+    messages
+        .iter()
+        .flat_map(|message| message.as_frame(transport).unwrap())
+        .collect()
+}
+
+// properties
+
+proptest! {
+    #[test]
+    fn message_survives_serialization(message in message()) {
+        // act
+        let actual = Message::from_message(&message.as_message().unwrap()).unwrap();
+
+        // assert
+        prop_assert_eq!(actual, message);
+    }
+
+    #[test]
+    fn message_survives_framing(message in message(), transport in transport()) {
+        // arrange
+        let frame = message.as_frame(transport).unwrap();
+        let mut decoder = FrameDecoder::new();
+
+        // act
+        let actual: Vec<_> = decoder.decode(&frame).map(Result::unwrap).collect();
+
+        // assert
+        prop_assert_eq!(actual, vec![message]);
+        prop_assert_eq!(decoder.transport(), Some(transport));
+    }
+
+    #[test]
+    fn stream_survives_arbitrary_chunks(
+        messages in prop::collection::vec(message(), 0..8),
+        transport in transport(),
+        chunk_sizes in prop::collection::vec(1..64_usize, 1..16),
+    ) {
+        // arrange
+        let stream = encode_stream(&messages, transport);
+        let mut decoder = FrameDecoder::new();
+        let mut actual = Vec::new();
+
+        // act -> feed the stream in chunks, as read from a serial port
+        let mut rest = stream.as_slice();
+        for size in chunk_sizes.iter().cycle() {
+            if rest.is_empty() {
+                break;
+            }
+            let (chunk, remaining) = rest.split_at((*size).min(rest.len()));
+            actual.extend(decoder.decode(chunk).map(Result::unwrap));
+            rest = remaining;
+        }
+
+        // assert
+        prop_assert_eq!(actual, messages);
+    }
+
+    #[test]
+    fn decoder_resynchronizes_after_garbage(
+        garbage in prop::collection::vec(any::<u8>(), 0..512),
+        message in message(),
+    ) {
+        // arrange -> the delimiter ends whatever the garbage started
+        let mut stream = garbage;
+        stream.push(FRAME_DELIMITER);
+        stream.extend(message.as_frame(Transport::Binary).unwrap());
+        let mut decoder = FrameDecoder::new();
+
+        // act
+        let actual = decoder.decode(&stream).last();
+
+        // assert
+        prop_assert_eq!(actual.map(Result::unwrap), Some(message));
+    }
+
+    #[test]
+    fn decoder_never_panics_on_arbitrary_input(
+        data in prop::collection::vec(any::<u8>(), 0..1024),
+    ) {
+        // arrange
+        let mut decoder = FrameDecoder::new();
+
+        // act
+        let results = decoder.decode(&data).count() as u64;
+
+        // assert -> every completed frame is counted exactly once
+        let statistics = decoder.statistics();
+        prop_assert_eq!(statistics.good + statistics.corrupted, results);
+    }
+}
@@ -40,4 +40,7 @@ fn main() {
 
     // Set the linker script to the one provided by cortex-m-rt.
     println!("cargo:rustc-link-arg=-Tlink.x");
+
+    // Add the linker script provided by defmt, placing the interned log strings
+    println!("cargo:rustc-link-arg=-Tdefmt.x");
 }
\ No newline at end of file
@@ -0,0 +1,130 @@
+use core::fmt::Write;
+use core::mem::MaybeUninit;
+use core::panic::PanicInfo;
+use core::ptr::{addr_of, addr_of_mut};
+use heapless::String;
+use keret_controller_transmit::{CrashReport, CRASH_FILE_LENGTH, CRASH_MESSAGE_LENGTH};
+use microbit::pac::SCB;
+
+/// marks the persisted panic as valid, anything else is random RAM content after power-on
+const VALID_MARKER: u32 = 0xDEAD_C0DE;
+
+/// the information about a panic, kept in RAM which is not initialized on boot
+/// this way it survives the reset following the panic
+#[repr(C)]
+struct PersistedPanic {
+    marker: u32,
+    line: u32,
+    message: TruncatingBuffer<CRASH_MESSAGE_LENGTH>,
+    file: TruncatingBuffer<CRASH_FILE_LENGTH>,
+}
+
+#[link_section = ".uninit.keret.PERSISTED_PANIC"]
+static mut PERSISTED_PANIC: MaybeUninit<PersistedPanic> = MaybeUninit::uninit();
+
+/// fixed size text buffer, silently cutting off everything which does not fit
+#[repr(C)]
+struct TruncatingBuffer<const N: usize> {
+    length: usize,
+    bytes: [u8; N],
+}
+
+impl<const N: usize> TruncatingBuffer<N> {
+    const fn new() -> Self {
+        Self {
+            length: 0,
+            bytes: [0; N],
+        }
+    }
+
+    /// the buffer content as heapless string
+    fn to_string(&self) -> String<N> {
+        let length = self.length.min(N);
+        let text = core::str::from_utf8(&self.bytes[..length]).unwrap_or_default();
+
+        let mut result = String::new();
+        // can't fail: the text is at most N bytes long
+        let _ = result.push_str(text);
+        result
+    }
+}
+
+impl<const N: usize> Write for TruncatingBuffer<N> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let mut end = s.len().min(N - self.length);
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        self.bytes[self.length..self.length + end].copy_from_slice(&s.as_bytes()[..end]);
+        self.length += end;
+
+        Ok(())
+    }
+}
+
+/// persist message and location of the panic, then reset the controller.
+/// the crash is reported after the reboot, where the serial bus is available again
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cortex_m::interrupt::disable();
+
+    // SAFETY: interrupts are disabled and this never returns, nothing else accesses the record
+    let persisted = unsafe { &mut *addr_of_mut!(PERSISTED_PANIC) }.write(PersistedPanic {
+        marker: 0,
+        line: 0,
+        message: TruncatingBuffer::new(),
+        file: TruncatingBuffer::new(),
+    });
+
+    let _ = write!(persisted.message, "{}", info.message());
+    if let Some(location) = info.location() {
+        let _ = persisted.file.write_str(location.file());
+        persisted.line = location.line();
+    }
+    persisted.marker = VALID_MARKER;
+
+    defmt::error!(
+        "Panicked at {=str}:{=u32}, resetting",
+        location_file(info),
+        persisted.line
+    );
+
+    SCB::sys_reset()
+}
+
+/// the file the panic happened in (if known)
+#[inline(always)]
+fn location_file<'a>(info: &'a PanicInfo) -> &'a str {
+    info.location().map(|l| l.file()).unwrap_or("<unknown>")
+}
+
+/// the crash report persisted by a panic before the last reset (if there was one)
+/// it stays persisted until it is cleared, so it survives another reset before being delivered
+pub(crate) fn persisted_crash_report() -> Option<CrashReport> {
+    // SAFETY: only called during initialization, before anything can panic.
+    // the marker is read without assuming the record to be initialized, as after power-on
+    // the RAM content is random
+    let persisted = unsafe { addr_of_mut!(PERSISTED_PANIC) as *mut PersistedPanic };
+    let marker = unsafe { addr_of!((*persisted).marker).read_volatile() };
+    if marker != VALID_MARKER {
+        return None;
+    }
+
+    // SAFETY: the valid marker is only written after the whole record was initialized
+    let persisted = unsafe { &*persisted };
+
+    Some(CrashReport::new(
+        persisted.message.to_string(),
+        persisted.file.to_string(),
+        persisted.line,
+    ))
+}
+
+/// forget the persisted crash report, once the outside acknowledged it
+pub(crate) fn clear_crash_report() {
+    // SAFETY: only the marker is written, which is valid for any content of the record;
+    // the panic handler never returns, so it can't be writing the record concurrently
+    let persisted = unsafe { addr_of_mut!(PERSISTED_PANIC) as *mut PersistedPanic };
+    unsafe { addr_of_mut!((*persisted).marker).write_volatile(0) };
+}
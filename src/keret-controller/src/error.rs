@@ -1,10 +1,13 @@
 use crate::infrastructure::serialize::SerialBusError;
-use rtt_target::rprintln;
+use crate::logging::log;
+use keret_controller_appservice::Error as AppServiceError;
+use keret_controller_transmit::{InitializationFailure, LogEvent, LogRecord};
 use snafu::Snafu;
 
 /// all errors which are generated inside this crate
 /// as enum, so handling code can easily match over it (if necessary)
-/// using snafu crate to auto-generate user-facing message which are sent over rtt
+/// using snafu crate to auto-generate user-facing message for debugging purposes,
+/// but reported as compact log records
 #[derive(Debug, Snafu)]
 #[snafu(visibility(pub(crate)))]
 pub(crate) enum InitializationError {
@@ -12,23 +15,52 @@ pub(crate) enum InitializationError {
     ClockInitializationFailed,
 }
 
-/// send details of a top-level error over the rtt
-pub(crate) fn report_error(err: &dyn snafu::Error) {
-    rprintln!("[ERROR] {}", err);
-    let mut source = err.source();
+// turn the error into the failure reported to the outside
+impl From<&InitializationError> for InitializationFailure {
+    fn from(value: &InitializationError) -> Self {
+        match value {
+            InitializationError::ClockInitializationFailed => InitializationFailure::Clock,
+        }
+    }
+}
 
-    if source.is_some() {
-        rprintln!("[CAUSE]:");
+// turn the error into the event to log
+impl From<&InitializationError> for LogEvent {
+    fn from(value: &InitializationError) -> Self {
+        match value {
+            InitializationError::ClockInitializationFailed => LogEvent::ClockInitializationFailed,
+        }
     }
-    while let Some(inner) = source {
-        rprintln!("{}", inner);
+}
 
-        source = inner.source();
+// turn the error into the event to log
+impl From<&SerialBusError> for LogEvent {
+    fn from(value: &SerialBusError) -> Self {
+        match value {
+            SerialBusError::WritingToSerialPortFailed { .. }
+            | SerialBusError::TransmitQueueFull => LogEvent::SerialWriteFailed,
+            SerialBusError::DeserializeMessageFailed { .. } => LogEvent::MessageSerializationFailed,
+        }
     }
 }
 
-// wrapper for app service errors
+/// log a top-level error as structured record instead of formatting it on the device
 #[inline]
-pub(crate) fn report_domain_error(err: &keret_controller_appservice::Error<SerialBusError>) {
-    report_error(err);
+pub(crate) fn report_error(event: impl Into<LogEvent>) {
+    log(LogRecord::error(event.into()));
+}
+
+// wrapper for app service errors, the root cause decides which event is logged
+pub(crate) fn report_domain_error(err: &AppServiceError<SerialBusError>) {
+    let event = match err {
+        AppServiceError::SendingMessageToOutsideFailed { source } => source.into(),
+        AppServiceError::DomainErrorOccurred {
+            source: keret_controller_domain::Error::IncoherentTimestamps { start, end },
+        } => LogEvent::IncoherentTimestamps {
+            start: start.into(),
+            end: end.into(),
+        },
+    };
+
+    report_error(event);
 }
@@ -0,0 +1,133 @@
+use crate::infrastructure::display::sprites::MAX_LEVEL;
+use core::sync::atomic::{compiler_fence, Ordering};
+use microbit::pac::{P0, SAADC};
+
+/// the dimmest level used as "full" brightness in a dark room
+const MIN_FULL_LEVEL: u8 = 3;
+
+/// how many display cycles to wait between two measurements
+const MEASURE_EVERY: u8 = 20;
+
+/// rows of the LED matrix (ROW1 to ROW5), all on port 0
+const ROW_PINS: [usize; 5] = [21, 22, 15, 24, 19];
+
+/// column of the LED matrix used for sensing (COL3), connected to analog input 7
+const SENSE_PIN: usize = 31;
+
+/// CPU cycles to wait for the LED to discharge (~1 ms at 64 MHz)
+const DISCHARGE_CYCLES: u32 = 64_000;
+
+/// highest value the SAADC returns in 10 bit resolution
+const MAX_SAMPLE: i32 = 1023;
+
+/// uses the LEDs of the matrix as light sensor, measuring the ambient light
+/// an LED is charged in reverse direction, then left floating. The brighter the ambient light
+/// the faster the photocurrent discharges it, so the voltage left after a fixed time
+/// indicates the brightness
+/// mirrors what the micro:bit DAL does, but only using a single column
+pub(crate) struct LightSensor {
+    saadc: SAADC,
+    level: u8,
+    cycles_until_measure: u8,
+}
+
+impl LightSensor {
+    /// create a new instance, configuring the SAADC for single-shot measurements
+    pub(crate) fn new(saadc: SAADC) -> Self {
+        saadc.resolution.write(|w| w.val()._10bit());
+        saadc.oversample.write(|w| w.oversample().bypass());
+        saadc.samplerate.write(|w| w.mode().task());
+        saadc.ch[0].config.write(|w| {
+            w.refsel()
+                .vdd1_4()
+                .gain()
+                .gain1_4()
+                .tacq()
+                ._10us()
+                .mode()
+                .se()
+                .resp()
+                .bypass()
+                .resn()
+                .bypass()
+        });
+        saadc.ch[0].pseln.write(|w| w.pseln().nc());
+
+        Self {
+            saadc,
+            level: MAX_LEVEL,
+            cycles_until_measure: 0,
+        }
+    }
+
+    /// the brightness level to use as "full" brightness in the current ambient light
+    /// measures the ambient light again every `MEASURE_EVERY` calls.
+    /// must be called without interrupts, as the pins are taken away from the display for a moment
+    pub(crate) fn full_level(&mut self) -> u8 {
+        if self.cycles_until_measure == 0 {
+            self.cycles_until_measure = MEASURE_EVERY;
+            let sample = self.measure().clamp(0, MAX_SAMPLE);
+
+            // high voltage -> slow discharge -> dark room -> dim display
+            let range = (MAX_LEVEL - MIN_FULL_LEVEL) as i32;
+            self.level = MIN_FULL_LEVEL + (range * (MAX_SAMPLE - sample) / MAX_SAMPLE) as u8;
+        }
+        self.cycles_until_measure -= 1;
+
+        self.level
+    }
+
+    /// charge the sensing LED in reverse direction and sample the voltage left after discharge
+    fn measure(&mut self) -> i32 {
+        // SAFETY: the display only writes OUT registers on refresh, so reconfiguring the pins
+        // with interrupts disabled does not interfere. Next refresh restores the matrix
+        let port = unsafe { &*P0::ptr() };
+
+        let row_mask = ROW_PINS.iter().fold(0_u32, |mask, pin| mask | (1 << pin));
+        port.outclr.write(|w| unsafe { w.bits(row_mask) });
+        port.outset.write(|w| unsafe { w.bits(1 << SENSE_PIN) });
+
+        port.pin_cnf[SENSE_PIN].write(|w| w.dir().input().input().disconnect().pull().disabled());
+        cortex_m::asm::delay(DISCHARGE_CYCLES);
+
+        let sample = self.sample();
+
+        port.pin_cnf[SENSE_PIN].write(|w| w.dir().output().input().disconnect());
+
+        sample
+    }
+
+    /// blocking single-shot sample of analog input 7 (the sensing column)
+    fn sample(&mut self) -> i32 {
+        let mut result: i16 = 0;
+
+        self.saadc.enable.write(|w| w.enable().enabled());
+        self.saadc.ch[0].pselp.write(|w| w.pselp().analog_input7());
+        self.saadc
+            .result
+            .ptr
+            .write(|w| unsafe { w.ptr().bits(&mut result as *mut i16 as u32) });
+        self.saadc
+            .result
+            .maxcnt
+            .write(|w| unsafe { w.maxcnt().bits(1) });
+
+        // the SAADC writes the result via DMA, don't let the compiler reorder around it
+        compiler_fence(Ordering::SeqCst);
+
+        self.saadc.tasks_start.write(|w| unsafe { w.bits(1) });
+        while self.saadc.events_started.read().bits() == 0 {}
+        self.saadc.events_started.reset();
+
+        self.saadc.tasks_sample.write(|w| unsafe { w.bits(1) });
+        while self.saadc.events_end.read().bits() == 0 {}
+        self.saadc.events_end.reset();
+
+        compiler_fence(Ordering::SeqCst);
+
+        self.saadc.ch[0].pselp.write(|w| w.pselp().nc());
+        self.saadc.enable.write(|w| w.enable().disabled());
+
+        result as i32
+    }
+}
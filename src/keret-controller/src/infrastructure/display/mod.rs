@@ -1,26 +1,62 @@
-use keret_controller_domain::AppMode;
+use keret_controller_domain::{AppMode, Brightness};
 use microbit::{
     display::nonblocking::Display as NonblockDisplay, gpio::DisplayPins, hal::timer::Instance,
 };
 use tiny_led_matrix::Render;
 
+#[cfg(feature = "ambient-light")]
+mod light_sensor;
 mod sprites;
 
-use crate::infrastructure::display::sprites::{ERROR_SPRITE, IDLE_SPRITE, RUNNING_SPRITE};
+#[cfg(feature = "ambient-light")]
+use crate::infrastructure::display::light_sensor::LightSensor;
+use crate::infrastructure::display::sprites::{
+    DisplayMode, ERROR_SPRITE, IDLE_SPRITE, MAX_LEVEL, RUNNING_SPRITE,
+};
 pub(crate) use sprites::FATAL_SPRITE;
 
+/// brightness level used while the display is dimmed
+const DIMMED_LEVEL: u8 = 2;
+
 /// convenience abstraction of the BSP display module
-#[repr(transparent)]
+/// keeps track of the shown sprite, so it can be re-rendered on brightness changes
 pub(crate) struct Display<T: Instance> {
     inner: NonblockDisplay<T>,
+    sprite: DisplayMode,
+    brightness: Brightness,
+    #[cfg(feature = "ambient-light")]
+    light_sensor: LightSensor,
 }
 
 impl<T: Instance> Display<T> {
     /// create a new instance, configuring the underlying Display element and unlocking the timer
+    #[cfg(not(feature = "ambient-light"))]
     pub(crate) fn new(board_timer: T, board_display: DisplayPins) -> Self {
         let display = NonblockDisplay::new(board_timer, board_display);
 
-        Self { inner: display }
+        Self {
+            inner: display,
+            sprite: IDLE_SPRITE,
+            brightness: Brightness::Full,
+        }
+    }
+
+    /// create a new instance, configuring the underlying Display element and unlocking the timer
+    /// the LED matrix is also used as light sensor to adapt the brightness to the ambient light
+    #[cfg(feature = "ambient-light")]
+    pub(crate) fn new(
+        board_timer: T,
+        board_display: DisplayPins,
+        board_saadc: microbit::pac::SAADC,
+    ) -> Self {
+        let display = NonblockDisplay::new(board_timer, board_display);
+
+        Self {
+            inner: display,
+            sprite: IDLE_SPRITE,
+            brightness: Brightness::Full,
+            light_sensor: LightSensor::new(board_saadc),
+        }
     }
 
     /// interrupt-triggered event handling inside the display
@@ -33,17 +69,50 @@ impl<T: Instance> Display<T> {
     pub(crate) fn show_sprite(&mut self, sprite: &impl Render) {
         self.inner.show(sprite);
     }
+
+    /// render the current sprite using the brightness level matching the current `Brightness`
+    fn render(&mut self) {
+        let level = match self.brightness {
+            Brightness::Full => self.full_level(),
+            Brightness::Dimmed => DIMMED_LEVEL,
+            Brightness::Off => 0,
+        };
+
+        let sprite = self.sprite.with_level(level);
+        self.inner.show(&sprite);
+    }
+
+    /// the level used for full brightness
+    #[cfg(not(feature = "ambient-light"))]
+    #[inline(always)]
+    fn full_level(&mut self) -> u8 {
+        MAX_LEVEL
+    }
+
+    /// the level used for full brightness, depending on the ambient light
+    #[cfg(feature = "ambient-light")]
+    #[inline(always)]
+    fn full_level(&mut self) -> u8 {
+        self.light_sensor.full_level().min(MAX_LEVEL)
+    }
 }
 
 impl<T: Instance> keret_controller_appservice::ports::Display for Display<T> {
     /// display a sprite associated with the given `AppMode`
     #[inline]
     fn show_mode(&mut self, app_mode: &AppMode) {
-        let sprite = match app_mode {
+        self.sprite = match app_mode {
             AppMode::Idle => IDLE_SPRITE,
             AppMode::Running(_) => RUNNING_SPRITE,
             AppMode::Error => ERROR_SPRITE,
         };
-        self.inner.show(&sprite);
+        self.render();
+    }
+
+    /// change the brightness the sprites are shown with
+    #[inline]
+    fn set_brightness(&mut self, brightness: Brightness) {
+        self.brightness = brightness;
+        self.render();
     }
 }
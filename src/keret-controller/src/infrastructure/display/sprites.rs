@@ -1,17 +1,44 @@
 use tiny_led_matrix::Render;
 
+/// the brightest level the LED matrix supports
+pub(crate) const MAX_LEVEL: u8 = 9;
+
 /// simple struct to hold a 5x5 byte matrix which can be shown on the Display
-#[repr(transparent)]
-pub(crate) struct DisplayMode([[u8; 5]; 5]);
+/// together with the brightness level the whole matrix is shown with
+#[derive(Copy, Clone)]
+pub(crate) struct DisplayMode {
+    pixels: [[u8; 5]; 5],
+    level: u8,
+}
+
+impl DisplayMode {
+    /// create a new sprite shown at the brightest level
+    const fn new(pixels: [[u8; 5]; 5]) -> Self {
+        Self {
+            pixels,
+            level: MAX_LEVEL,
+        }
+    }
+
+    /// create a copy of the sprite shown at the given brightness level (0 = off, `MAX_LEVEL` = brightest)
+    #[inline]
+    pub(crate) const fn with_level(self, level: u8) -> Self {
+        Self {
+            pixels: self.pixels,
+            level,
+        }
+    }
+}
 
 impl Render for DisplayMode {
+    /// the pixels are defined relative to `MAX_LEVEL`, scale them down to the current level
     fn brightness_at(&self, x: usize, y: usize) -> u8 {
-        self.0[y][x]
+        ((self.pixels[y][x] as u16 * self.level as u16) / MAX_LEVEL as u16) as u8
     }
 }
 
 /// the sprite to show while the app idles ("pause" icon)
-pub(super) const IDLE_SPRITE: DisplayMode = DisplayMode([
+pub(super) const IDLE_SPRITE: DisplayMode = DisplayMode::new([
     [5, 5, 0, 5, 5],
     [5, 5, 0, 5, 5],
     [5, 5, 0, 5, 5],
@@ -20,7 +47,7 @@ pub(super) const IDLE_SPRITE: DisplayMode = DisplayMode([
 ]);
 
 /// the sprite to show while the app is running ("play" icon)
-pub(super) const RUNNING_SPRITE: DisplayMode = DisplayMode([
+pub(super) const RUNNING_SPRITE: DisplayMode = DisplayMode::new([
     [0, 5, 0, 0, 0],
     [0, 5, 5, 0, 0],
     [0, 5, 5, 5, 0],
@@ -29,7 +56,7 @@ pub(super) const RUNNING_SPRITE: DisplayMode = DisplayMode([
 ]);
 
 /// the sprite to show if the app is in an error mode (exclamation mark)
-pub(super) const ERROR_SPRITE: DisplayMode = DisplayMode([
+pub(super) const ERROR_SPRITE: DisplayMode = DisplayMode::new([
     [5, 5, 5, 5, 5],
     [0, 5, 5, 5, 0],
     [0, 0, 5, 0, 0],
@@ -39,7 +66,7 @@ pub(super) const ERROR_SPRITE: DisplayMode = DisplayMode([
 
 /// the sprite to show if the app ran into a fatal error it can't recover from
 /// (a large X)
-pub(crate) const FATAL_SPRITE: DisplayMode = DisplayMode([
+pub(crate) const FATAL_SPRITE: DisplayMode = DisplayMode::new([
     [5, 0, 0, 0, 5],
     [0, 5, 0, 5, 0],
     [0, 0, 5, 0, 0],
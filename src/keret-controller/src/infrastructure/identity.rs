@@ -0,0 +1,40 @@
+use keret_controller_transmit::{FirmwareVersion, Hello};
+use microbit::{
+    hal::rng::Rng,
+    pac::{FICR, RNG},
+};
+
+/// version of this firmware, taken from the crate version at compile time
+const FIRMWARE_VERSION: FirmwareVersion = FirmwareVersion {
+    major: parse_version_part(env!("CARGO_PKG_VERSION_MAJOR")),
+    minor: parse_version_part(env!("CARGO_PKG_VERSION_MINOR")),
+    patch: parse_version_part(env!("CARGO_PKG_VERSION_PATCH")),
+};
+
+/// the hello identifying this controller, its firmware and the protocol it speaks
+/// the serial number is the device id, programmed into the FICR by Nordic during production
+pub(crate) fn identify(ficr: &FICR) -> Hello {
+    let low = ficr.deviceid[0].read().bits() as u64;
+    let high = ficr.deviceid[1].read().bits() as u64;
+
+    Hello::new(FIRMWARE_VERSION, high << 32 | low)
+}
+
+/// choose a random id for the current boot, which tells reports of different boots apart
+/// the RNG peripheral is released afterwards, as the id is needed only once
+pub(crate) fn generate_boot_id(board_rng: RNG) -> u32 {
+    Rng::new(board_rng).random_u32()
+}
+
+/// parse a decimal part of the version, usable in const context
+const fn parse_version_part(part: &str) -> u8 {
+    let digits = part.as_bytes();
+    let mut value: u8 = 0;
+    let mut index = 0;
+    while index < digits.len() {
+        value = value * 10 + (digits[index] - b'0');
+        index += 1;
+    }
+
+    value
+}
@@ -1,5 +1,8 @@
 // the "modules" of this app (think "package"/"namespace") in other languages
 pub(crate) mod controls;
 pub(crate) mod display;
+pub(crate) mod identity;
+pub(crate) mod recovery;
 pub(crate) mod serialize;
 pub(crate) mod time;
+pub(crate) mod watchdog;
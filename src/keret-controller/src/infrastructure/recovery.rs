@@ -0,0 +1,56 @@
+use microbit::pac::{POWER, SCB};
+
+/// time to wait before retrying after the first failed initialization
+const INITIAL_BACKOFF_MS: u32 = 1_000;
+
+/// the longest time to wait before retrying the initialization
+const MAX_BACKOFF_MS: u32 = 60_000;
+
+/// keeps track of failed initialization attempts in a retained register,
+/// as the initialization is retried by resetting the whole controller
+#[repr(transparent)]
+pub(crate) struct InitRetry {
+    power: POWER,
+}
+
+impl InitRetry {
+    /// create a new instance, using the general purpose retention register of the POWER peripheral
+    pub(crate) fn new(board_power: POWER) -> Self {
+        Self { power: board_power }
+    }
+
+    /// count another failed initialization, returning how many attempts failed in a row
+    pub(crate) fn record_failure(&mut self) -> u8 {
+        let attempts = self.failed_attempts().saturating_add(1);
+        self.power
+            .gpregret
+            .write(|w| unsafe { w.gpregret().bits(attempts) });
+
+        attempts
+    }
+
+    /// the initialization succeeded, start counting from scratch on the next failure
+    pub(crate) fn succeeded(&mut self) {
+        self.power
+            .gpregret
+            .write(|w| unsafe { w.gpregret().bits(0) });
+    }
+
+    /// how long to wait before the next attempt, doubling with every failed attempt in a row
+    pub(crate) fn backoff_ms(&self) -> u32 {
+        let doublings = self.failed_attempts().saturating_sub(1).min(6);
+
+        (INITIAL_BACKOFF_MS << doublings).min(MAX_BACKOFF_MS)
+    }
+
+    #[inline(always)]
+    fn failed_attempts(&self) -> u8 {
+        self.power.gpregret.read().gpregret().bits()
+    }
+}
+
+/// reset the whole controller, running the initialization again
+#[inline]
+pub(crate) fn soft_reset() -> ! {
+    SCB::sys_reset()
+}
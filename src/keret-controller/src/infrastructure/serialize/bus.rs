@@ -1,58 +1,232 @@
-use crate::infrastructure::serialize::error::{
-    DeserializeMessageFailedSnafu, SerialBusError, WritingToSerialPortFailedSnafu,
+use crate::crash::clear_crash_report;
+use crate::error::report_error;
+use crate::infrastructure::serialize::error::SerialBusError;
+use crate::infrastructure::serialize::receiver::{take_overflow, take_received_byte};
+use crate::infrastructure::serialize::transmitter::queue_message;
+use heapless::Deque;
+use keret_controller_appservice::ports::RemoteCommand;
+use keret_controller_domain::{AppMode, Category, DimmingPolicy, Instant, TrackResult, WallTime};
+use keret_controller_transmit::{
+    ActionReport, BootAnnouncement, Command, ControllerMode, ControllerState, CrashReport,
+    Diagnostic, FrameDecoder, Heartbeat, Hello, LogEvent, Message, ResetReason,
 };
-use keret_controller_domain::TrackResult;
-use keret_controller_transmit::ActionReport;
-use snafu::ResultExt;
 
-use microbit::{
-    board::UartPins,
-    hal::uarte::{Baudrate, Instance, Parity, Uarte},
-};
+/// how many reports are kept until they are acknowledged
+const OUTBOX_CAPACITY: usize = 8;
 
-/// convenience abstraction of the BSP serial bus
-#[repr(transparent)]
-pub(crate) struct SerialBus<T> {
-    serial: Uarte<T>,
+/// messages exchanged over the serial bus, the frames are written by the `Transmitter`
+/// numbers the sent reports, so the receiver can detect repeated ones,
+/// and keeps them until the receiver acknowledged them
+pub(crate) struct SerialBus {
+    boot_id: u32,
+    next_sequence: u32,
+    hello: Hello,
+    decoder: FrameDecoder,
+    outbox: Deque<ActionReport, OUTBOX_CAPACITY>,
+    crash: Option<CrashReport>,
 }
 
-impl<T: Instance> SerialBus<T> {
-    /// create a new instance and configure the UARTE-based serial bus
-    pub(crate) fn new(board_uarte: T, pins: UartPins) -> Self {
-        let serial = Uarte::new(
-            board_uarte,
-            pins.into(),
-            Parity::EXCLUDED,
-            Baudrate::BAUD115200,
-        );
+impl SerialBus {
+    /// create a new instance
+    /// the given boot id is announced at boot and part of every report,
+    /// the hello is sent whenever the outside asks the controller to identify itself
+    pub(crate) fn new(boot_id: u32, hello: Hello) -> Self {
+        Self {
+            boot_id,
+            next_sequence: 0,
+            hello,
+            decoder: FrameDecoder::new(),
+            outbox: Deque::new(),
+            crash: None,
+        }
+    }
+
+    /// announce the controller booted, because of the given reason
+    pub(crate) fn send_boot_announcement(
+        &mut self,
+        reason: ResetReason,
+    ) -> Result<(), SerialBusError> {
+        let announcement = BootAnnouncement::new(reason, self.boot_id);
+        self.send_message(Message::BootAnnouncement(announcement))
+    }
 
-        Self { serial }
+    /// identify the controller to the outside
+    pub(crate) fn send_hello(&mut self) -> Result<(), SerialBusError> {
+        self.send_message(Message::Hello(self.hello))
     }
 
-    /// serialize the message and send if over the bus
-    fn send_report(&mut self, report: ActionReport) -> Result<(), SerialBusError> {
-        let serialized_message = report.as_message().context(DeserializeMessageFailedSnafu)?;
+    /// inform the outside about a diagnostic event of the controller itself
+    pub(crate) fn send_diagnostic(&mut self, diagnostic: Diagnostic) -> Result<(), SerialBusError> {
+        self.send_message(Message::Diagnostic(diagnostic))
+    }
 
-        self.serial
-            .write(&serialized_message)
-            .context(WritingToSerialPortFailedSnafu)?;
+    /// inform the outside about a panic which happened before the last reset
+    /// numbered like the activity reports and sent again later on, until it is acknowledged;
+    /// only then the persisted panic is cleared
+    pub(crate) fn send_crash_report(&mut self, report: CrashReport) -> Result<(), SerialBusError> {
+        let report = report.with_origin(self.boot_id, self.next_sequence);
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+        self.crash = Some(report.clone());
 
-        let end_of_line = [b'\n'];
+        self.send_message(Message::CrashReport(report))
+    }
 
-        self.serial
-            .write(&end_of_line)
-            .context(WritingToSerialPortFailedSnafu)?;
+    /// keep the report until it is acknowledged, dropping the oldest one if there are too many
+    fn keep_until_acknowledged(&mut self, report: ActionReport) {
+        if self.outbox.is_full() {
+            if let Some(dropped) = self.outbox.pop_front() {
+                report_error(LogEvent::UnacknowledgedReportDropped {
+                    sequence: dropped.sequence(),
+                });
+            }
+        }
+        let _ = self.outbox.push_back(report);
+    }
 
-        Ok(())
+    /// forget the acknowledged report, it does not need to be sent again
+    fn acknowledge(&mut self, sequence: u32) {
+        if self.crash.as_ref().map(CrashReport::sequence) == Some(sequence) {
+            self.crash = None;
+            clear_crash_report();
+        }
+        for _ in 0..self.outbox.len() {
+            if let Some(report) = self.outbox.pop_front() {
+                if report.sequence() != sequence {
+                    let _ = self.outbox.push_back(report);
+                }
+            }
+        }
+    }
+
+    /// handle a message received from the outside
+    /// everything concerning the serial bus itself is answered right away,
+    /// the remaining commands are returned to be handled by the app service
+    fn handle_message(&mut self, message: Message) -> Option<RemoteCommand> {
+        let command = match message {
+            Message::Ack(ack) => {
+                self.acknowledge(ack.sequence);
+                return None;
+            }
+            Message::Command(command) => command,
+            // nothing else is meant for the controller
+            _ => return None,
+        };
+
+        let reply = match command {
+            Command::Ping => self.send_message(Message::Pong),
+            Command::Identify => self.send_hello(),
+            Command::QueryState => return Some(RemoteCommand::QueryState),
+            Command::SetCategory { category } => {
+                return Some(RemoteCommand::SetCategory(category.into()))
+            }
+            Command::SetThresholds {
+                dim_after,
+                off_after,
+            } => {
+                return Some(RemoteCommand::SetThresholds(DimmingPolicy::new(
+                    dim_after.into(),
+                    off_after.into(),
+                )))
+            }
+            Command::Start => return Some(RemoteCommand::Start),
+            Command::Stop => return Some(RemoteCommand::Stop),
+            Command::SyncTime { utc } => return Some(RemoteCommand::SyncTime(utc.into())),
+        };
+        if let Err(e) = reply {
+            report_error(&e);
+        }
+
+        None
+    }
+
+    /// queue the message to be sent as a single frame over the bus
+    fn send_message(&mut self, message: Message) -> Result<(), SerialBusError> {
+        queue_message(message)
     }
 }
 
-impl<T: Instance> keret_controller_appservice::ports::OutsideMessaging for SerialBus<T> {
+impl keret_controller_appservice::ports::OutsideMessaging for SerialBus {
     type Error = SerialBusError;
 
-    /// send the duration as message via the serial bus
-    fn send_result(&mut self, result: TrackResult) -> Result<(), Self::Error> {
-        let report = ActionReport::new(result.into());
-        self.send_report(report)
+    /// send the duration as message via the serial bus, numbered with the next sequence number
+    /// the report is sent again later on, until it is acknowledged
+    fn send_result(
+        &mut self,
+        result: TrackResult,
+        category: Category,
+        finished_at: Option<WallTime>,
+    ) -> Result<(), Self::Error> {
+        let report = ActionReport::new(
+            self.boot_id,
+            self.next_sequence,
+            category.into(),
+            result.into(),
+        )
+        .with_finished_at(finished_at.map(u64::from));
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+        self.keep_until_acknowledged(report);
+
+        self.send_message(Message::ActionReport(report))
+    }
+
+    /// send the current state via the serial bus
+    fn send_state(&mut self, mode: &AppMode, category: Category) -> Result<(), Self::Error> {
+        let state = ControllerState::new(controller_mode(mode), category.into());
+        self.send_message(Message::State(state))
+    }
+
+    /// signal the outside the controller is still alive
+    fn send_heartbeat(&mut self, mode: &AppMode, uptime: Instant) -> Result<(), Self::Error> {
+        // the outbox is way smaller than u8::MAX
+        let unacknowledged = (self.outbox.len() + usize::from(self.crash.is_some())) as u8;
+        let heartbeat = Heartbeat::new(u64::from(&uptime), controller_mode(mode), unacknowledged);
+        self.send_message(Message::Heartbeat(heartbeat))
+    }
+
+    /// send all reports again, which were not acknowledged yet
+    fn resend_unacknowledged(&mut self) -> Result<(), Self::Error> {
+        if let Some(crash) = self.crash.clone() {
+            self.send_message(Message::CrashReport(crash))?;
+        }
+        let pending = self.outbox.clone();
+        for report in pending.iter() {
+            self.send_message(Message::ActionReport(*report))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl keret_controller_appservice::ports::RemoteControl for SerialBus {
+    /// decode the bytes received so far, until a command for the app service is complete
+    fn next_command(&mut self) -> Option<RemoteCommand> {
+        if take_overflow() {
+            report_error(LogEvent::ReceiveBufferOverflow);
+        }
+
+        while let Some(byte) = take_received_byte() {
+            match self.decoder.push(byte) {
+                Some(Ok(message)) => {
+                    if let Some(command) = self.handle_message(message) {
+                        return Some(command);
+                    }
+                }
+                Some(Err(_)) => report_error(LogEvent::ReceivedFrameRejected),
+                None => {}
+            }
+        }
+
+        None
+    }
+}
+
+/// turn the mode of the domain into the one sent over the wire
+fn controller_mode(mode: &AppMode) -> ControllerMode {
+    match mode {
+        AppMode::Idle => ControllerMode::Idle,
+        AppMode::Running(since) => ControllerMode::Running {
+            since: since.into(),
+        },
+        AppMode::Error => ControllerMode::Error,
     }
 }
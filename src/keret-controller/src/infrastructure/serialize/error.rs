@@ -36,6 +36,8 @@ pub enum SerialBusError {
         #[snafu(source(from(microbit::hal::uarte::Error, UarteError::new)))]
         source: UarteError,
     },
+    #[snafu(display("Too many frames queued for the serial port"))]
+    TransmitQueueFull,
     #[snafu(display("Failed to deserialize message"))]
     DeserializeMessageFailed {
         source: keret_controller_transmit::Error,
@@ -1,5 +1,11 @@
 mod bus;
 mod error;
+mod receiver;
+mod transmitter;
 
 pub(crate) use bus::SerialBus;
 pub(crate) use error::SerialBusError;
+pub(crate) use receiver::{handle_receive_event, start_receiving};
+#[cfg(feature = "serial-log")]
+pub(crate) use transmitter::send_log;
+pub(crate) use transmitter::Transmitter;
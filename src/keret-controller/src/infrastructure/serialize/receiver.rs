@@ -0,0 +1,79 @@
+use core::cell::RefCell;
+use core::ptr::{addr_of, addr_of_mut};
+use core::sync::atomic::{compiler_fence, Ordering};
+use cortex_m::interrupt::{free, CriticalSection, Mutex};
+use heapless::Deque;
+use microbit::pac::UARTE0;
+
+/// how many received bytes are kept until the main loop decodes them
+/// large enough to hold a few command frames arriving between two cycles
+const RECEIVED_CAPACITY: usize = 256;
+
+/// bytes received by the interrupt, but not yet decoded by the main loop
+static RECEIVED: Mutex<RefCell<Received>> = Mutex::new(RefCell::new(Received::new()));
+
+/// target of the DMA transfer, the UARTE writes every received byte here
+/// only touched by the UARTE and the interrupt handler, once it was started
+static mut RX_BYTE: u8 = 0;
+
+/// bytes received so far, remembering if some had to be dropped
+struct Received {
+    bytes: Deque<u8, RECEIVED_CAPACITY>,
+    overflowed: bool,
+}
+
+impl Received {
+    const fn new() -> Self {
+        Self {
+            bytes: Deque::new(),
+            overflowed: false,
+        }
+    }
+}
+
+/// start receiving on the UARTE, one byte at a time, each one raising an interrupt
+/// the HAL only supports blocking reads, so the registers are accessed directly
+pub(crate) fn start_receiving() {
+    let uarte = unsafe { &*UARTE0::ptr() };
+
+    uarte
+        .rxd
+        .ptr
+        .write(|w| unsafe { w.ptr().bits(addr_of_mut!(RX_BYTE) as u32) });
+    uarte.rxd.maxcnt.write(|w| unsafe { w.maxcnt().bits(1) });
+    uarte.events_endrx.reset();
+    uarte.intenset.write(|w| w.endrx().set());
+
+    // the UARTE writes via DMA, don't let the compiler reorder around it
+    compiler_fence(Ordering::SeqCst);
+    uarte.tasks_startrx.write(|w| unsafe { w.bits(1) });
+}
+
+/// take the received byte and immediately receive the next one
+/// meant to be called from the UARTE interrupt
+pub(crate) fn handle_receive_event(cs: &CriticalSection) {
+    let uarte = unsafe { &*UARTE0::ptr() };
+    if uarte.events_endrx.read().bits() == 0 {
+        return;
+    }
+    uarte.events_endrx.reset();
+
+    compiler_fence(Ordering::SeqCst);
+    let byte = unsafe { addr_of!(RX_BYTE).read_volatile() };
+    uarte.tasks_startrx.write(|w| unsafe { w.bits(1) });
+
+    let mut received = RECEIVED.borrow(cs).borrow_mut();
+    if received.bytes.push_back(byte).is_err() {
+        received.overflowed = true;
+    }
+}
+
+/// take the oldest received byte, which still needs to be decoded
+pub(crate) fn take_received_byte() -> Option<u8> {
+    free(|cs| RECEIVED.borrow(cs).borrow_mut().bytes.pop_front())
+}
+
+/// check if received bytes were dropped since the last call
+pub(crate) fn take_overflow() -> bool {
+    free(|cs| core::mem::take(&mut RECEIVED.borrow(cs).borrow_mut().overflowed))
+}
@@ -0,0 +1,107 @@
+use crate::infrastructure::serialize::error::{
+    DeserializeMessageFailedSnafu, SerialBusError, TransmitQueueFullSnafu,
+    WritingToSerialPortFailedSnafu,
+};
+use core::cell::RefCell;
+use cortex_m::interrupt::{free, Mutex};
+use heapless::Deque;
+#[cfg(feature = "serial-log")]
+use keret_controller_transmit::LogRecord;
+use keret_controller_transmit::{Message, Transport};
+use microbit::{
+    board::UartPins,
+    hal::uarte::{Baudrate, Instance, Parity, Uarte},
+};
+use snafu::{ensure, ResultExt};
+
+/// how the messages are framed on the wire, chosen at build time
+#[cfg(not(feature = "ascii-transport"))]
+const TRANSPORT: Transport = Transport::Binary;
+#[cfg(feature = "ascii-transport")]
+const TRANSPORT: Transport = Transport::Text;
+
+/// how many bytes are kept until the main loop writes them
+/// large enough to resend all unacknowledged reports in one cycle, even as text
+const PENDING_CAPACITY: usize = 2048;
+
+/// how many bytes are written at once, taken from the pending ones in a single critical section
+const CHUNK_SIZE: usize = 64;
+
+/// frames queued from within critical sections, but not yet written
+static PENDING: Mutex<RefCell<Deque<u8, PENDING_CAPACITY>>> =
+    Mutex::new(RefCell::new(Deque::new()));
+
+/// serialize the message and queue it as a single frame, to be written by the main loop
+pub(crate) fn queue_message(message: Message) -> Result<(), SerialBusError> {
+    let frame = message
+        .as_frame(TRANSPORT)
+        .context(DeserializeMessageFailedSnafu)?;
+
+    free(|cs| {
+        let mut pending = PENDING.borrow(cs).borrow_mut();
+        // whole frames only, the receiver would reject a truncated one anyway
+        ensure!(
+            pending.capacity() - pending.len() >= frame.len(),
+            TransmitQueueFullSnafu
+        );
+        for byte in frame.iter() {
+            let _ = pending.push_back(*byte);
+        }
+
+        Ok(())
+    })
+}
+
+/// forward a log record of the controller to the outside
+#[cfg(feature = "serial-log")]
+pub(crate) fn send_log(record: LogRecord) -> Result<(), SerialBusError> {
+    queue_message(Message::Log(record))
+}
+
+/// writes the queued frames over the UARTE
+/// writing blocks until all bytes are sent, so it must not happen inside a critical section:
+/// the interrupt taking the received bytes would be held back meanwhile and miss some
+pub(crate) struct Transmitter<T> {
+    serial: Uarte<T>,
+}
+
+impl<T: Instance> Transmitter<T> {
+    /// create a new instance and configure the UARTE-based serial bus
+    pub(crate) fn new(board_uarte: T, pins: UartPins) -> Self {
+        let serial = Uarte::new(
+            board_uarte,
+            pins.into(),
+            Parity::EXCLUDED,
+            Baudrate::BAUD115200,
+        );
+
+        Self { serial }
+    }
+
+    /// write all queued bytes, including the ones queued meanwhile by interrupts
+    pub(crate) fn flush(&mut self) -> Result<(), SerialBusError> {
+        // the UARTE sends via DMA from RAM, which the stack is part of
+        let mut chunk = [0_u8; CHUNK_SIZE];
+        loop {
+            let len = free(|cs| {
+                let mut pending = PENDING.borrow(cs).borrow_mut();
+                let mut len = 0;
+                while len < CHUNK_SIZE {
+                    let Some(byte) = pending.pop_front() else {
+                        break;
+                    };
+                    chunk[len] = byte;
+                    len += 1;
+                }
+                len
+            });
+            if len == 0 {
+                return Ok(());
+            }
+
+            self.serial
+                .write(&chunk[..len])
+                .context(WritingToSerialPortFailedSnafu)?;
+        }
+    }
+}
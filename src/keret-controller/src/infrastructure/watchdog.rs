@@ -0,0 +1,74 @@
+use keret_controller_transmit::ResetReason;
+use microbit::{
+    hal::wdt::{count, handles::HdlN, Parts, Watchdog as HalWatchdog, WatchdogHandle},
+    pac::{POWER, WDT},
+};
+
+/// how long the main loop may stall before the controller is reset
+/// in ticks of the 32.768 kHz low frequency clock -> 5 seconds
+const TIMEOUT_TICKS: u32 = 5 * 32_768;
+
+/// convenience abstraction of the HAL watchdog, resetting the controller if it isn't fed in time
+#[repr(transparent)]
+pub(crate) struct Watchdog {
+    handle: WatchdogHandle<HdlN>,
+}
+
+impl Watchdog {
+    /// configure and start the watchdog
+    /// a watchdog still running from before a soft reset can't be stopped, so it is taken over
+    pub(crate) fn start(board_wdt: WDT) -> Self {
+        let handle = match HalWatchdog::try_new(board_wdt) {
+            Ok(mut watchdog) => {
+                watchdog.set_lfosc_ticks(TIMEOUT_TICKS);
+                watchdog.run_during_sleep(true);
+                watchdog.halt_during_debug(true);
+
+                let Parts {
+                    handles: (handle,), ..
+                } = watchdog.activate::<count::One>();
+                handle.degrade()
+            }
+            Err(board_wdt) => match HalWatchdog::try_recover::<count::One>(board_wdt) {
+                Ok(Parts {
+                    handles: (handle,), ..
+                }) => handle.degrade(),
+                // the running watchdog uses more than one handle, nobody would feed the others
+                Err(_) => panic!("Watchdog already running with an incompatible configuration"),
+            },
+        };
+
+        Self { handle }
+    }
+
+    /// signal the watchdog the main loop is still alive
+    #[inline]
+    pub(crate) fn feed(&mut self) {
+        self.handle.pet();
+    }
+}
+
+/// read why the controller was reset before the current boot.
+/// the reasons accumulate until cleared, so clear them for the next boot
+pub(crate) fn take_reset_reason(power: &POWER) -> ResetReason {
+    let reasons = power.resetreas.read();
+
+    let reason = if reasons.dog().is_detected() {
+        ResetReason::Watchdog
+    } else if reasons.lockup().is_detected() {
+        ResetReason::CpuLockup
+    } else if reasons.sreq().is_detected() {
+        ResetReason::SoftReset
+    } else if reasons.resetpin().is_detected() {
+        ResetReason::ResetPin
+    } else if reasons.bits() == 0 {
+        ResetReason::PowerOn
+    } else {
+        ResetReason::Other
+    };
+
+    // writing a 1 clears the corresponding reason
+    power.resetreas.write(|w| unsafe { w.bits(reasons.bits()) });
+
+    reason
+}
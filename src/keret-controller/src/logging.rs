@@ -0,0 +1,45 @@
+use keret_controller_transmit::{LogLevel, LogRecord};
+
+#[cfg(feature = "serial-log")]
+use core::cell::RefCell;
+#[cfg(feature = "serial-log")]
+use cortex_m::interrupt::{free, CriticalSection, Mutex};
+#[cfg(feature = "serial-log")]
+use heapless::Deque;
+
+/// how many log records are kept until they are sent over the serial bus
+#[cfg(feature = "serial-log")]
+const PENDING_LOGS_CAPACITY: usize = 8;
+
+/// log records waiting to be sent over the serial bus
+/// filled from wherever something is logged, drained by the main loop
+#[cfg(feature = "serial-log")]
+static PENDING_LOGS: Mutex<RefCell<Deque<LogRecord, PENDING_LOGS_CAPACITY>>> =
+    Mutex::new(RefCell::new(Deque::new()));
+
+/// emit a structured log record over rtt (formatted by defmt on the host)
+/// and queue it to be sent over the serial bus, if enabled
+pub(crate) fn log(record: LogRecord) {
+    match record.level {
+        LogLevel::Error => defmt::error!("{}", record.event),
+        LogLevel::Warn => defmt::warn!("{}", record.event),
+        LogLevel::Info => defmt::info!("{}", record.event),
+        LogLevel::Debug => defmt::debug!("{}", record.event),
+    }
+
+    #[cfg(feature = "serial-log")]
+    free(|cs| {
+        let mut pending = PENDING_LOGS.borrow(cs).borrow_mut();
+        // keep the newest records, the older ones are still visible via rtt
+        if pending.is_full() {
+            pending.pop_front();
+        }
+        let _ = pending.push_back(record);
+    });
+}
+
+/// take the oldest log record which still needs to be sent over the serial bus
+#[cfg(feature = "serial-log")]
+pub(crate) fn take_pending_log(cs: &CriticalSection) -> Option<LogRecord> {
+    PENDING_LOGS.borrow(cs).borrow_mut().pop_front()
+}
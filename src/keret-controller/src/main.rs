@@ -5,18 +5,24 @@
 //   so the "main" method is not indicator for code entry, but below you will find #[entry]
 //   also as there is no OS the Rust std lib can't be used, as it depends on libc/musl/something similar
 
+mod crash;
 mod error;
 mod infrastructure;
+mod logging;
 // importing elements (modules, structs, traits, ...) from other modules to be used in this file
 
 use crate::error::report_domain_error;
 use crate::infrastructure::serialize::SerialBusError;
 use crate::{
+    crash::persisted_crash_report,
     error::{report_error, InitializationError},
     infrastructure::{
         controls::InputControls,
         display::{Display, FATAL_SPRITE},
+        identity::{generate_boot_id, identify},
+        recovery::{soft_reset, InitRetry},
         time::RunningTimer,
+        watchdog::{take_reset_reason, Watchdog},
     },
 };
 use core::cell::RefCell;
@@ -25,11 +31,15 @@ use cortex_m::{
     prelude::_embedded_hal_blocking_delay_DelayMs,
 };
 use cortex_m_rt::entry;
-use infrastructure::serialize::SerialBus;
+#[cfg(feature = "serial-log")]
+use infrastructure::serialize::send_log;
+use infrastructure::serialize::{handle_receive_event, start_receiving, SerialBus, Transmitter};
 use keret_controller_appservice::{
-    ports::Display as _, ApplicationService, Error as AppServiceError,
+    ports::{Display as _, UserInterface as _},
+    ApplicationService, Error as AppServiceError,
 };
-use keret_controller_domain::AppMode;
+use keret_controller_domain::{AppMode, InteractionRequest};
+use keret_controller_transmit::Diagnostic;
 use microbit::{
     board::Board,
     hal::{
@@ -38,8 +48,14 @@ use microbit::{
     },
     pac::{interrupt, Interrupt, NVIC, RTC1, TIMER0, TIMER1, UARTE0},
 };
-use panic_rtt_target as _;
-use rtt_target::rtt_init_print;
+// defmt_rtt -> send the log records via USB to attached debugging tool, formatted on the host
+use defmt_rtt as _;
+
+/// how many main loop cycles pass until unacknowledged reports are sent again (5 seconds)
+const RESEND_CYCLES: u32 = 10;
+
+/// how many main loop cycles pass between two heartbeats (10 seconds)
+const HEARTBEAT_CYCLES: u32 = 20;
 
 /// convenience type alias to make code shorter/more readable
 /// meant for those static values which exist once and used from interrupts and inside domain layer
@@ -50,7 +66,7 @@ type AppService<'a> = ApplicationService<
     RunningTimer<RTC1>,
     Display<TIMER1>,
     InputControls,
-    SerialBus<UARTE0>,
+    SerialBus,
     fn(&AppServiceError<SerialBusError>),
 >;
 
@@ -67,16 +83,14 @@ static APP_SERVICE: Singleton<AppService> = Mutex::new(RefCell::new(None));
 /// as it drives the whole microcontroller
 #[entry]
 fn main() -> ! {
-    // rtt -> send debug information via USB to attached debugging tool
-    rtt_init_print!();
-
     // get the main "Board" instance from the HAL to access the hardware features
     let Some(board) = Board::take() else {
         panic!("Could not initialize board. Nothing left to do.");
     };
 
     let mut mode = AppMode::Idle;
-    let mut main_loop_timer = initialize_board(board);
+    let (mut main_loop_timer, mut watchdog, mut transmitter) = initialize_board(board);
+    let mut cycle: u32 = 0;
 
     // main execution loop, should never end
     loop {
@@ -89,26 +103,90 @@ fn main() -> ! {
                 panic!("App Service must exist by now. Needs hard restart");
             };
             mode = app_service.next_cycle(&mode);
+
+            if cycle % HEARTBEAT_CYCLES == 0 {
+                app_service.heartbeat(&mode);
+            }
+            if cycle % RESEND_CYCLES == 0 {
+                app_service.resend_unacknowledged();
+            }
         });
+
+        #[cfg(feature = "serial-log")]
+        while let Some(record) = free(logging::take_pending_log) {
+            // don't log the failure again, it would be queued for the serial bus once more
+            if send_log(record).is_err() {
+                break;
+            }
+        }
+        // the frames queued above are only written now, outside of the critical section,
+        // so the bytes received meanwhile are still taken by the interrupt
+        if let Err(e) = transmitter.flush() {
+            report_error(&e);
+        }
+        cycle = cycle.wrapping_add(1);
+        watchdog.feed();
         main_loop_timer.delay_ms(500_u32);
     }
 }
 
 /// initialize the board, creating all helper objects and put the main "app service" in the mutex
-/// also initializes the timer used to sleep on the main loop, as the passed in Board object
-/// needs to be used in one place only, so everything board "owning" happens here
-fn initialize_board(board: Board) -> Timer<TIMER0, Periodic> {
+/// also initializes the timer used to sleep on the main loop, the watchdog guarding it
+/// and the transmitter writing the queued frames,
+/// as the passed in Board object needs to be used in one place only, so everything board "owning"
+/// happens here
+fn initialize_board(board: Board) -> (Timer<TIMER0, Periodic>, Watchdog, Transmitter<UARTE0>) {
+    let reset_reason = take_reset_reason(&board.POWER);
+    let mut init_retry = InitRetry::new(board.POWER);
+
+    // start the watchdog first, so even a hanging initialization is retried
+    let watchdog = Watchdog::start(board.WDT);
+
+    #[cfg(not(feature = "ambient-light"))]
     let mut display = Display::new(board.TIMER1, board.display_pins);
+    #[cfg(feature = "ambient-light")]
+    let mut display = Display::new(board.TIMER1, board.display_pins, board.SAADC);
     display.show_mode(&AppMode::Idle);
 
-    let controls = InputControls::new(board.GPIOTE, board.buttons);
-    let serial_bus = SerialBus::new(board.UARTE0, board.uart);
+    let mut controls = InputControls::new(board.GPIOTE, board.buttons);
+    let boot_id = generate_boot_id(board.RNG);
+    let mut transmitter = Transmitter::new(board.UARTE0, board.uart);
+    let mut serial_bus = SerialBus::new(boot_id, identify(&board.FICR));
+    if let Err(e) = serial_bus.send_boot_announcement(reset_reason) {
+        report_error(&e);
+    }
+    if let Err(e) = serial_bus.send_hello() {
+        report_error(&e);
+    }
+    if let Some(crash_report) = persisted_crash_report() {
+        if let Err(e) = serial_bus.send_crash_report(crash_report) {
+            report_error(&e);
+        }
+    }
+    // interrupts are not enabled yet, so writing blocks no receiving
+    if let Err(e) = transmitter.flush() {
+        report_error(&e);
+    }
+
     let main_loop_timer = Timer::new(board.TIMER0).into_periodic();
 
     let running_timer = match RunningTimer::new(board.CLOCK, board.RTC1) {
         Ok(timer) => timer,
-        Err(e) => handle_init_error(display, e),
+        Err(e) => handle_init_error(
+            e,
+            display,
+            controls,
+            serial_bus,
+            transmitter,
+            main_loop_timer,
+            watchdog,
+            init_retry,
+        ),
     };
+    init_retry.succeeded();
+
+    // commands are only received once the main loop is able to handle them
+    start_receiving();
 
     // unmaking interrupts necessary for them to fire
     // needs to be done here to keep Display and RunningTimer flexible
@@ -117,6 +195,7 @@ fn initialize_board(board: Board) -> Timer<TIMER0, Periodic> {
         NVIC::unmask(Interrupt::TIMER1);
         NVIC::unmask(Interrupt::RTC1);
         NVIC::unmask(Interrupt::GPIOTE);
+        NVIC::unmask(Interrupt::UARTE0_UART0);
     }
     NVIC::unpend(Interrupt::GPIOTE);
 
@@ -130,18 +209,49 @@ fn initialize_board(board: Board) -> Timer<TIMER0, Periodic> {
         ));
     });
 
-    main_loop_timer
+    (main_loop_timer, watchdog, transmitter)
 }
 
-/// report an error that happened during initialization, don't even go into the main loop
-fn handle_init_error<T: Instance>(mut display: Display<T>, err: InitializationError) -> ! {
+/// report an error that happened during initialization, don't even go into the main loop.
+/// instead wait (longer with every failure in a row) and retry by resetting the controller.
+/// the user can skip the waiting by pressing any button
+fn handle_init_error<T: Instance>(
+    err: InitializationError,
+    mut display: Display<T>,
+    mut controls: InputControls,
+    mut serial_bus: SerialBus,
+    mut transmitter: Transmitter<UARTE0>,
+    mut timer: Timer<TIMER0, Periodic>,
+    mut watchdog: Watchdog,
+    mut retry: InitRetry,
+) -> ! {
     display.show_sprite(&FATAL_SPRITE);
     report_error(&err);
 
-    loop {
-        // don't let user interact if init failed
-        continue;
+    let attempt = retry.record_failure();
+    if let Err(e) = serial_bus.send_diagnostic(Diagnostic::InitializationFailed {
+        failure: (&err).into(),
+        attempt,
+    }) {
+        report_error(&e);
+    }
+    if let Err(e) = transmitter.flush() {
+        report_error(&e);
+    }
+
+    // interrupts are not enabled yet, so refresh the display and check the buttons manually
+    for _ in 0..retry.backoff_ms() {
+        display.handle_display_event();
+        controls.check_input();
+        if !matches!(controls.requested_interaction(), InteractionRequest::None) {
+            break;
+        }
+
+        watchdog.feed();
+        timer.delay_ms(1_u32);
     }
+
+    soft_reset()
 }
 
 // below here are the interrupt handlers
@@ -178,3 +288,9 @@ fn GPIOTE() {
         }
     })
 }
+
+/// take the byte received over the serial bus
+#[interrupt]
+fn UARTE0_UART0() {
+    free(handle_receive_event)
+}
@@ -6,6 +6,10 @@ use std::time::Duration;
 pub struct ActionReport {
     timestamp: DateTime<Utc>,
     duration: Duration,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    origin: Option<ReportOrigin>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    category: Option<u8>,
 }
 
 impl ActionReport {
@@ -13,8 +17,30 @@ impl ActionReport {
         Self {
             timestamp,
             duration,
+            origin: None,
+            category: None,
         }
     }
+
+    /// identify where the report originates from, making repeated deliveries detectable
+    pub fn with_origin(mut self, origin: ReportOrigin) -> Self {
+        self.origin = Some(origin);
+        self
+    }
+
+    pub fn origin(&self) -> Option<&ReportOrigin> {
+        self.origin.as_ref()
+    }
+
+    /// assign the tracked time to a category, as chosen on the controller
+    pub fn with_category(mut self, category: u8) -> Self {
+        self.category = Some(category);
+        self
+    }
+
+    pub fn category(&self) -> Option<u8> {
+        self.category
+    }
 }
 
 impl From<u64> for ActionReport {
@@ -22,6 +48,142 @@ impl From<u64> for ActionReport {
         Self {
             timestamp: Utc::now(),
             duration: Duration::from_secs(value),
+            origin: None,
+            category: None,
+        }
+    }
+}
+
+/// identifies a report uniquely: the n-th report (sequence) of a boot of a device
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ReportOrigin {
+    /// serial number of the controller
+    device: String,
+    boot_id: u32,
+    sequence: u32,
+}
+
+impl ReportOrigin {
+    pub fn new(device: String, boot_id: u32, sequence: u32) -> Self {
+        Self {
+            device,
+            boot_id,
+            sequence,
+        }
+    }
+}
+
+/// outcome of a report posted in a batch, in the order the reports were posted
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "result", rename_all = "lowercase")]
+pub enum BatchItemResult {
+    /// the report is stored at the index, possibly since an earlier delivery
+    Stored { index: usize },
+    /// the report was not stored, e.g. as it is malformed
+    Rejected { reason: String },
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct CrashReport {
+    timestamp: DateTime<Utc>,
+    message: String,
+    file: String,
+    line: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    origin: Option<ReportOrigin>,
+}
+
+impl CrashReport {
+    pub fn new(timestamp: DateTime<Utc>, message: String, file: String, line: u32) -> Self {
+        Self {
+            timestamp,
+            message,
+            file,
+            line,
+            origin: None,
         }
     }
+
+    /// identify the crashed controller and the report, making repeated deliveries detectable
+    pub fn with_origin(mut self, origin: ReportOrigin) -> Self {
+        self.origin = Some(origin);
+        self
+    }
+
+    pub fn origin(&self) -> Option<&ReportOrigin> {
+        self.origin.as_ref()
+    }
+}
+
+/// identity of a controller, reported whenever it connects to an adapter
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct DeviceIdentity {
+    timestamp: DateTime<Utc>,
+    serial_number: String,
+    firmware_version: String,
+    protocol_version: u8,
+}
+
+impl DeviceIdentity {
+    pub fn new(
+        timestamp: DateTime<Utc>,
+        serial_number: String,
+        firmware_version: String,
+        protocol_version: u8,
+    ) -> Self {
+        Self {
+            timestamp,
+            serial_number,
+            firmware_version,
+            protocol_version,
+        }
+    }
+}
+
+/// what a controller is currently doing
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceMode {
+    Idle,
+    Running,
+    Error,
+}
+
+/// liveness of a controller, as observed by its adapter through periodic heartbeats
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct DeviceStatus {
+    serial_number: String,
+    /// when the last heartbeat of the controller was received
+    last_seen: DateTime<Utc>,
+    /// whether heartbeats are still received
+    online: bool,
+    mode: DeviceMode,
+    /// seconds since the controller booted, as of the last heartbeat
+    uptime: u64,
+    /// reports sent by the controller, but not acknowledged by the adapter yet
+    unacknowledged: u8,
+}
+
+impl DeviceStatus {
+    pub fn new(
+        serial_number: String,
+        last_seen: DateTime<Utc>,
+        online: bool,
+        mode: DeviceMode,
+        uptime: u64,
+        unacknowledged: u8,
+    ) -> Self {
+        Self {
+            serial_number,
+            last_seen,
+            online,
+            mode,
+            uptime,
+            unacknowledged,
+        }
+    }
+
+    pub fn serial_number(&self) -> &str {
+        &self.serial_number
+    }
 }
@@ -1,28 +1,68 @@
 mod metrics;
 
 use crate::api::metrics::metrics_handler;
-use crate::repository::ToDoRepository;
+use crate::repository::{Entry, ToDoRepository};
 use axum::extract::State;
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
-use axum::routing::get;
+use axum::routing::{get, post};
 use axum::{Json, Router};
-use keret_service_transmit::ActionReport;
+use keret_service_transmit::{
+    ActionReport, BatchItemResult, CrashReport, DeviceIdentity, DeviceStatus,
+};
 use tracing::instrument;
 
-pub(crate) fn setup_api<T: ToDoRepository + 'static>(repo: T) -> Router {
-    // build our application with a route
-    let todo_routes = Router::new()
-        .route("/", get(list_entries::<T>).post(add_entry::<T>))
-        .with_state(repo);
+/// most entries accepted in a single batch
+const MAX_BATCH_SIZE: usize = 1000;
 
+pub(crate) fn setup_api<TReports, TCrashes, TDevices, TStatuses>(
+    reports: TReports,
+    crashes: TCrashes,
+    devices: TDevices,
+    statuses: TStatuses,
+) -> Router
+where
+    TReports: ToDoRepository<ActionReport> + 'static,
+    TCrashes: ToDoRepository<CrashReport> + 'static,
+    TDevices: ToDoRepository<DeviceIdentity> + 'static,
+    TStatuses: ToDoRepository<DeviceStatus> + 'static,
+{
+    // build our application with a route
     Router::new()
         .route("/metrics", get(metrics_handler))
-        .nest("/api/v1.0/report", todo_routes)
+        .nest(
+            "/api/v1.0/report",
+            entry_routes(reports.clone()).merge(batch_route(reports)),
+        )
+        .nest("/api/v1.0/crash", entry_routes(crashes))
+        .nest("/api/v1.0/device", entry_routes(devices))
+        .nest("/api/v1.0/status", entry_routes(statuses))
+}
+
+/// routes to list all entries of a repository and add new ones
+fn entry_routes<E, T>(repo: T) -> Router
+where
+    E: Entry,
+    T: ToDoRepository<E> + 'static,
+{
+    Router::new()
+        .route("/", get(list_entries::<E, T>).post(add_entry::<E, T>))
+        .with_state(repo)
+}
+
+/// route to add many entries at once, as piled up by an adapter during an outage
+fn batch_route<E, T>(repo: T) -> Router
+where
+    E: Entry,
+    T: ToDoRepository<E> + 'static,
+{
+    Router::new()
+        .route("/batch", post(add_entries::<E, T>))
+        .with_state(repo)
 }
 
 #[instrument(skip(repo))]
-async fn list_entries<T: ToDoRepository>(
+async fn list_entries<E: Entry, T: ToDoRepository<E>>(
     State(repo): State<T>,
 ) -> Result<impl IntoResponse, StatusCode> {
     match repo.list() {
@@ -32,12 +72,263 @@ async fn list_entries<T: ToDoRepository>(
 }
 
 #[instrument(skip(repo))]
-async fn add_entry<T: ToDoRepository>(
+async fn add_entry<E: Entry, T: ToDoRepository<E>>(
     State(repo): State<T>,
-    Json(entry): Json<ActionReport>,
+    Json(entry): Json<E>,
 ) -> Result<impl IntoResponse, StatusCode> {
     match repo.add(entry) {
         Ok(index) => Ok(Json(index)),
         Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
     }
 }
+
+/// add all entries of the batch, answering with the outcome of each one in their order
+/// a malformed entry is rejected on its own, the others are stored nevertheless
+#[instrument(skip(repo, batch))]
+async fn add_entries<E: Entry, T: ToDoRepository<E>>(
+    State(repo): State<T>,
+    Json(batch): Json<Vec<serde_json::Value>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    if batch.len() > MAX_BATCH_SIZE {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    // keep the place of the malformed entries, the others get theirs once stored
+    let mut entries = Vec::new();
+    let mut rejections = Vec::with_capacity(batch.len());
+    for item in batch {
+        match serde_json::from_value::<E>(item) {
+            Ok(entry) => {
+                entries.push(entry);
+                rejections.push(None);
+            }
+            Err(e) => rejections.push(Some(e.to_string())),
+        }
+    }
+
+    let Ok(indices) = repo.add_all(entries) else {
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+    let mut indices = indices.into_iter();
+    let results: Vec<BatchItemResult> = rejections
+        .into_iter()
+        .map(|rejection| match rejection {
+            Some(reason) => BatchItemResult::Rejected { reason },
+            None => BatchItemResult::Stored {
+                index: indices.next().expect("an index per stored entry"),
+            },
+        })
+        .collect();
+
+    Ok(Json(results))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::repository::{RepositoryError, RepositoryStorage, StorageBasedRepository};
+    use axum::body::Body;
+    use axum::http::Request;
+    use chrono::{DateTime, Utc};
+    use keret_service_transmit::ReportOrigin;
+    use serde_json::{json, Value};
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    /// storage kept in memory, serialized as the entries can't be cloned
+    struct MemoryStorage(Value);
+
+    impl<T: Entry> RepositoryStorage<T> for MemoryStorage {
+        fn list(&self) -> Result<Vec<T>, RepositoryError> {
+            Ok(serde_json::from_value(self.0.clone()).unwrap())
+        }
+
+        fn store(&mut self, list: Vec<T>) -> Result<(), RepositoryError> {
+            self.0 = serde_json::to_value(list).unwrap();
+            Ok(())
+        }
+    }
+
+    fn repository<T: Entry>() -> StorageBasedRepository<T> {
+        StorageBasedRepository::new(MemoryStorage(json!([])))
+    }
+
+    /// the api with empty repositories, and the repository of the activity reports
+    fn api() -> (Router, StorageBasedRepository<ActionReport>) {
+        let reports = repository();
+        let api = setup_api(reports.clone(), repository(), repository(), repository());
+        (api, reports)
+    }
+
+    fn report(sequence: u32) -> Value {
+        let report = ActionReport::new(
+            DateTime::<Utc>::from_timestamp(10, 0).unwrap(),
+            Duration::from_secs(60),
+        )
+        .with_origin(ReportOrigin::new("device".to_string(), 7, sequence));
+        serde_json::to_value(report).unwrap()
+    }
+
+    fn crash() -> Value {
+        let crash = CrashReport::new(
+            DateTime::<Utc>::from_timestamp(10, 0).unwrap(),
+            "oops".to_string(),
+            "src/main.rs".to_string(),
+            42,
+        )
+        .with_origin(ReportOrigin::new("0123456789abcdef".to_string(), 7, 0));
+        serde_json::to_value(crash).unwrap()
+    }
+
+    /// post the JSON to the api, returning the status and the parsed answer (if any)
+    async fn post_json(api: Router, uri: &str, body: &Value) -> (StatusCode, Value) {
+        let request = Request::post(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        call(api, request).await
+    }
+
+    /// get the JSON from the api, returning the status and the parsed answer (if any)
+    async fn get_json(api: Router, uri: &str) -> (StatusCode, Value) {
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        call(api, request).await
+    }
+
+    async fn call(api: Router, request: Request<Body>) -> (StatusCode, Value) {
+        let response = api.oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (
+            status,
+            serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        )
+    }
+
+    #[tokio::test]
+    async fn add_entries_rejects_malformed_entries_and_stores_the_others() {
+        // arrange
+        let (api, reports) = api();
+        let mut malformed = report(2);
+        malformed.as_object_mut().unwrap().remove("timestamp");
+        let batch = json!([report(0), malformed, report(1)]);
+
+        // act
+        let (status, answer) = post_json(api, "/api/v1.0/report/batch", &batch).await;
+
+        // assert
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(answer[0], json!({"result": "stored", "index": 0}));
+        assert_eq!(answer[1]["result"], "rejected");
+        assert_eq!(answer[1]["reason"], "missing field `timestamp`");
+        assert_eq!(answer[2], json!({"result": "stored", "index": 1}));
+        assert_eq!(reports.list().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn add_entries_stores_repeated_entry_once() {
+        // arrange
+        let (api, reports) = api();
+        let batch = json!([report(0), report(1), report(0)]);
+
+        // act
+        let (status, answer) = post_json(api, "/api/v1.0/report/batch", &batch).await;
+
+        // assert -> the repeated entry gets the index of the first one
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(answer[2], json!({"result": "stored", "index": 0}));
+        assert_eq!(reports.list().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn add_entries_with_too_many_entries_stores_nothing() {
+        // arrange
+        let (api, reports) = api();
+        let batch = Value::Array((0..=MAX_BATCH_SIZE as u32).map(report).collect());
+
+        // act
+        let (status, _) = post_json(api, "/api/v1.0/report/batch", &batch).await;
+
+        // assert
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(reports.list().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn add_entries_is_only_offered_for_reports() {
+        // arrange
+        let (api, _) = api();
+
+        // act
+        let (status, _) = post_json(api, "/api/v1.0/crash/batch", &json!([])).await;
+
+        // assert
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn add_crash_report_stores_it() {
+        // arrange
+        let crashes = repository();
+        let api = setup_api(repository(), crashes.clone(), repository(), repository());
+
+        // act
+        let (status, answer) = post_json(api, "/api/v1.0/crash", &crash()).await;
+
+        // assert
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(answer, json!(0));
+        assert_eq!(
+            serde_json::to_value(crashes.list().unwrap()).unwrap(),
+            json!([crash()])
+        );
+    }
+
+    #[tokio::test]
+    async fn add_repeated_crash_report_stores_it_once() {
+        // arrange
+        let crashes = repository();
+        let api = setup_api(repository(), crashes.clone(), repository(), repository());
+        let _ = post_json(api.clone(), "/api/v1.0/crash", &crash()).await;
+
+        // act
+        let (status, answer) = post_json(api, "/api/v1.0/crash", &crash()).await;
+
+        // assert -> the repeated delivery gets the index of the stored crash
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(answer, json!(0));
+        assert_eq!(crashes.list().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn add_malformed_crash_report_stores_nothing() {
+        // arrange
+        let crashes = repository();
+        let api = setup_api(repository(), crashes.clone(), repository(), repository());
+        let mut malformed = crash();
+        malformed.as_object_mut().unwrap().remove("line");
+
+        // act
+        let (status, _) = post_json(api, "/api/v1.0/crash", &malformed).await;
+
+        // assert
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(crashes.list().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn list_crash_reports_returns_stored_ones() {
+        // arrange
+        let (api, _) = api();
+        let _ = post_json(api.clone(), "/api/v1.0/crash", &crash()).await;
+
+        // act
+        let (status, answer) = get_json(api, "/api/v1.0/crash").await;
+
+        // assert
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(answer, json!([crash()]));
+    }
+}
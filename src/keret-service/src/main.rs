@@ -11,9 +11,11 @@ async fn main() {
     // initialize tracing
     tracing_subscriber::fmt::init();
 
-    let storage = YamlRepositoryStorage::new("repo.yaml");
-    let repo = StorageBasedRepository::new(storage);
-    let app = setup_api(repo);
+    let reports = StorageBasedRepository::new(YamlRepositoryStorage::new("repo.yaml"));
+    let crashes = StorageBasedRepository::new(YamlRepositoryStorage::new("crashes.yaml"));
+    let devices = StorageBasedRepository::new(YamlRepositoryStorage::new("devices.yaml"));
+    let statuses = StorageBasedRepository::new(YamlRepositoryStorage::new("statuses.yaml"));
+    let app = setup_api(reports, crashes, devices, statuses);
 
     // run our app with hyper, listening globally on port 3000
     let Ok(listener) = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", PORT)).await else {
@@ -1,6 +1,9 @@
-use keret_service_transmit::ActionReport;
+use keret_service_transmit::{ActionReport, CrashReport, DeviceIdentity, DeviceStatus};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use snafu::Snafu;
 use std::backtrace::Backtrace;
+use std::fmt::Debug;
 
 mod storage_based_repo;
 mod yaml_storage;
@@ -16,12 +19,12 @@ pub enum RepositoryError {
         #[snafu(source(from(std::io::Error, Box::new)))]
         source: Box<std::io::Error>,
     },
-    #[snafu(display("Deserialization of todos from repository failed"))]
+    #[snafu(display("Deserialization of entries from repository failed"))]
     DeserializationFailed {
         #[snafu(source(from(serde_yaml::Error, Box::new)))]
         source: Box<serde_yaml::Error>,
     },
-    #[snafu(display("Deserialization of todos into repository failed"))]
+    #[snafu(display("Serialization of entries into repository failed"))]
     SerializationFailed {
         #[snafu(source(from(serde_yaml::Error, Box::new)))]
         source: Box<serde_yaml::Error>,
@@ -33,12 +36,54 @@ pub enum RepositoryError {
     ErrorOnTest,
 }
 
-pub(crate) trait RepositoryStorage {
-    fn list(&self) -> Result<Vec<ActionReport>, RepositoryError>;
-    fn store(&mut self, list: Vec<ActionReport>) -> Result<(), RepositoryError>;
+/// everything which can be stored in a repository
+pub(crate) trait Entry:
+    Debug + Serialize + DeserializeOwned + Send + Sync + 'static
+{
+    /// whether this entry is a repeated delivery of the already stored `other` entry
+    /// duplicates are not stored again, making adding them idempotent
+    fn duplicates(&self, _other: &Self) -> bool {
+        false
+    }
+
+    /// whether this entry replaces the already stored `other` entry
+    /// used for entries where only the latest one is of interest
+    fn supersedes(&self, _other: &Self) -> bool {
+        false
+    }
+}
+
+// reports identified by their origin (controller, boot and sequence) are stored once,
+// even if a retry delivers them again
+impl Entry for ActionReport {
+    fn duplicates(&self, other: &Self) -> bool {
+        self.origin().is_some() && self.origin() == other.origin()
+    }
+}
+
+impl Entry for CrashReport {
+    fn duplicates(&self, other: &Self) -> bool {
+        self.origin().is_some() && self.origin() == other.origin()
+    }
+}
+
+impl Entry for DeviceIdentity {}
+
+// only the latest status of each device is kept
+impl Entry for DeviceStatus {
+    fn supersedes(&self, other: &Self) -> bool {
+        self.serial_number() == other.serial_number()
+    }
+}
+
+pub(crate) trait RepositoryStorage<T: Entry> {
+    fn list(&self) -> Result<Vec<T>, RepositoryError>;
+    fn store(&mut self, list: Vec<T>) -> Result<(), RepositoryError>;
 }
 
-pub(crate) trait ToDoRepository: Clone + Send + Sync {
-    fn list(&self) -> Result<Vec<ActionReport>, RepositoryError>;
-    fn add(&self, value: ActionReport) -> Result<usize, RepositoryError>;
+pub(crate) trait ToDoRepository<T: Entry>: Clone + Send + Sync {
+    fn list(&self) -> Result<Vec<T>, RepositoryError>;
+    fn add(&self, value: T) -> Result<usize, RepositoryError>;
+    /// add all values at once, returning the index of each one in their order
+    fn add_all(&self, values: Vec<T>) -> Result<Vec<usize>, RepositoryError>;
 }
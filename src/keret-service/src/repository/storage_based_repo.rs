@@ -1,17 +1,17 @@
-use crate::repository::{LockPoisonedSnafu, RepositoryError, RepositoryStorage, ToDoRepository};
-use keret_service_transmit::ActionReport;
+use crate::repository::{
+    Entry, LockPoisonedSnafu, RepositoryError, RepositoryStorage, ToDoRepository,
+};
 use std::sync::{Arc, RwLock};
 use tracing::instrument;
 
-#[derive(Clone)]
-pub(crate) struct StorageBasedRepository {
-    storage: Arc<RwLock<dyn RepositoryStorage + Send + Sync>>,
+pub(crate) struct StorageBasedRepository<T: Entry> {
+    storage: Arc<RwLock<dyn RepositoryStorage<T> + Send + Sync>>,
 }
 
-impl StorageBasedRepository {
-    pub(crate) fn new<T>(storage: T) -> Self
+impl<T: Entry> StorageBasedRepository<T> {
+    pub(crate) fn new<S>(storage: S) -> Self
     where
-        T: RepositoryStorage + Send + Sync + 'static,
+        S: RepositoryStorage<T> + Send + Sync + 'static,
     {
         Self {
             storage: Arc::new(RwLock::new(storage)),
@@ -19,9 +19,18 @@ impl StorageBasedRepository {
     }
 }
 
-impl ToDoRepository for StorageBasedRepository {
+// derive(Clone) would require T: Clone, but only the Arc is cloned
+impl<T: Entry> Clone for StorageBasedRepository<T> {
+    fn clone(&self) -> Self {
+        Self {
+            storage: self.storage.clone(),
+        }
+    }
+}
+
+impl<T: Entry> ToDoRepository<T> for StorageBasedRepository<T> {
     #[instrument(skip(self))]
-    fn list(&self) -> Result<Vec<ActionReport>, RepositoryError> {
+    fn list(&self) -> Result<Vec<T>, RepositoryError> {
         let Ok(repo) = self.storage.read() else {
             return LockPoisonedSnafu.fail();
         };
@@ -30,19 +39,56 @@ impl ToDoRepository for StorageBasedRepository {
     }
 
     #[instrument(skip(self))]
-    fn add(&self, value: ActionReport) -> Result<usize, RepositoryError> {
+    fn add(&self, value: T) -> Result<usize, RepositoryError> {
+        let Ok(mut repo) = self.storage.write() else {
+            return LockPoisonedSnafu.fail();
+        };
+
+        let mut list = repo.list()?;
+        let (index, changed) = insert(&mut list, value);
+        if changed {
+            repo.store(list)?;
+        }
+
+        Ok(index)
+    }
+
+    #[instrument(skip(self))]
+    fn add_all(&self, values: Vec<T>) -> Result<Vec<usize>, RepositoryError> {
         let Ok(mut repo) = self.storage.write() else {
             return LockPoisonedSnafu.fail();
         };
 
+        // the storage is read and written once for all values
         let mut list = repo.list()?;
+        let mut changed = false;
+        let mut indices = Vec::with_capacity(values.len());
+        for value in values {
+            let (index, inserted) = insert(&mut list, value);
+            indices.push(index);
+            changed |= inserted;
+        }
+        if changed {
+            repo.store(list)?;
+        }
 
-        let new_index = list.len();
-        list.push(value);
-        repo.store(list)?;
+        Ok(indices)
+    }
+}
 
-        Ok(new_index)
+/// put the value into the list, unless it duplicates an entry
+/// returns its index and whether the list changed
+fn insert<T: Entry>(list: &mut Vec<T>, value: T) -> (usize, bool) {
+    if let Some(index) = list.iter().position(|entry| value.duplicates(entry)) {
+        return (index, false);
     }
+    if let Some(index) = list.iter().position(|entry| value.supersedes(entry)) {
+        list[index] = value;
+        return (index, true);
+    }
+
+    list.push(value);
+    (list.len() - 1, true)
 }
 
 #[cfg(test)]
@@ -50,6 +96,9 @@ mod test {
     use super::*;
     use crate::repository::*;
     use chrono::{DateTime, Utc};
+    use keret_service_transmit::{
+        ActionReport, CrashReport, DeviceMode, DeviceStatus, ReportOrigin,
+    };
     use mockall::mock;
     use mockall::predicate::eq;
     use std::time::Duration;
@@ -57,12 +106,51 @@ mod test {
     mock! {
         MyRepositoryStorage {}
 
-        impl RepositoryStorage for MyRepositoryStorage {
+        impl RepositoryStorage<ActionReport> for MyRepositoryStorage {
             fn list(&self) -> Result<Vec<ActionReport>, RepositoryError>;
             fn store(&mut self, list: Vec<ActionReport>) -> Result<(), RepositoryError>;
         }
     }
 
+    mock! {
+        MyStatusStorage {}
+
+        impl RepositoryStorage<DeviceStatus> for MyStatusStorage {
+            fn list(&self) -> Result<Vec<DeviceStatus>, RepositoryError>;
+            fn store(&mut self, list: Vec<DeviceStatus>) -> Result<(), RepositoryError>;
+        }
+    }
+
+    mock! {
+        MyCrashStorage {}
+
+        impl RepositoryStorage<CrashReport> for MyCrashStorage {
+            fn list(&self) -> Result<Vec<CrashReport>, RepositoryError>;
+            fn store(&mut self, list: Vec<CrashReport>) -> Result<(), RepositoryError>;
+        }
+    }
+
+    fn crash(timestamp: i64, sequence: u32) -> CrashReport {
+        CrashReport::new(
+            DateTime::<Utc>::from_timestamp(timestamp, 0).unwrap(),
+            "oops".to_string(),
+            "src/main.rs".to_string(),
+            42,
+        )
+        .with_origin(ReportOrigin::new("device".to_string(), 7, sequence))
+    }
+
+    fn status(device: &str, last_seen: i64, online: bool) -> DeviceStatus {
+        DeviceStatus::new(
+            device.to_string(),
+            DateTime::<Utc>::from_timestamp(last_seen, 0).unwrap(),
+            online,
+            DeviceMode::Idle,
+            last_seen as u64,
+            0,
+        )
+    }
+
     #[test]
     fn list_with_empty_storage_returns_empty_vec() {
         // arrange
@@ -279,4 +367,285 @@ mod test {
         // assert
         assert!(matches!(actual, Err(RepositoryError::ErrorOnTest)));
     }
+
+    #[test]
+    fn add_duplicate_report_returns_index_of_stored_report() {
+        // arrange
+        let mut storage = MockMyRepositoryStorage::default();
+        storage.expect_list().once().returning(|| {
+            Ok(vec![
+                ActionReport::new(
+                    DateTime::<Utc>::from_timestamp(10, 0).unwrap(),
+                    Duration::new(1, 0),
+                )
+                .with_origin(ReportOrigin::new("device".to_string(), 7, 0)),
+                ActionReport::new(
+                    DateTime::<Utc>::from_timestamp(20, 0).unwrap(),
+                    Duration::new(1, 0),
+                )
+                .with_origin(ReportOrigin::new("device".to_string(), 7, 1)),
+            ])
+        });
+        storage.expect_store().never();
+        let repo = StorageBasedRepository::new(storage);
+
+        // act
+        let actual = repo.add(
+            ActionReport::new(
+                DateTime::<Utc>::from_timestamp(90, 0).unwrap(),
+                Duration::new(1, 0),
+            )
+            .with_origin(ReportOrigin::new("device".to_string(), 7, 1)),
+        );
+
+        // assert
+        assert!(actual.is_ok());
+        assert_eq!(actual.unwrap(), 1);
+    }
+
+    #[test]
+    fn add_report_of_other_boot_writes_all_plus_one_elements() {
+        // arrange
+        let mut storage = MockMyRepositoryStorage::default();
+        storage.expect_list().once().returning(|| {
+            Ok(vec![ActionReport::new(
+                DateTime::<Utc>::from_timestamp(10, 0).unwrap(),
+                Duration::new(1, 0),
+            )
+            .with_origin(ReportOrigin::new("device".to_string(), 7, 0))])
+        });
+        storage
+            .expect_store()
+            .once()
+            .withf(|list| list.len() == 2)
+            .returning(|_| Ok(()));
+        let repo = StorageBasedRepository::new(storage);
+
+        // act
+        let actual = repo.add(
+            ActionReport::new(
+                DateTime::<Utc>::from_timestamp(90, 0).unwrap(),
+                Duration::new(1, 0),
+            )
+            .with_origin(ReportOrigin::new("device".to_string(), 8, 0)),
+        );
+
+        // assert -> mockall
+        assert!(actual.is_ok());
+        assert_eq!(actual.unwrap(), 1);
+    }
+
+    #[test]
+    fn add_status_of_known_device_replaces_stored_status() {
+        // arrange
+        let mut storage = MockMyStatusStorage::default();
+        storage.expect_list().once().returning(|| {
+            Ok(vec![
+                status("device", 10, true),
+                status("other-device", 20, true),
+            ])
+        });
+        storage
+            .expect_store()
+            .once()
+            .with(eq(vec![
+                status("device", 10, true),
+                status("other-device", 90, false),
+            ]))
+            .returning(|_| Ok(()));
+        let repo = StorageBasedRepository::new(storage);
+
+        // act
+        let actual = repo.add(status("other-device", 90, false));
+
+        // assert -> + mockall
+        assert!(actual.is_ok());
+        assert_eq!(actual.unwrap(), 1);
+    }
+
+    #[test]
+    fn add_status_of_new_device_writes_all_plus_one_elements() {
+        // arrange
+        let mut storage = MockMyStatusStorage::default();
+        storage
+            .expect_list()
+            .once()
+            .returning(|| Ok(vec![status("device", 10, true)]));
+        storage
+            .expect_store()
+            .once()
+            .withf(|list| list.len() == 2)
+            .returning(|_| Ok(()));
+        let repo = StorageBasedRepository::new(storage);
+
+        // act
+        let actual = repo.add(status("other-device", 90, true));
+
+        // assert -> + mockall
+        assert!(actual.is_ok());
+        assert_eq!(actual.unwrap(), 1);
+    }
+
+    fn report(sequence: u32) -> ActionReport {
+        ActionReport::new(
+            DateTime::<Utc>::from_timestamp(10, 0).unwrap(),
+            Duration::new(1, 0),
+        )
+        .with_origin(ReportOrigin::new("device".to_string(), 7, sequence))
+    }
+
+    #[test]
+    fn add_all_stores_once_and_returns_index_of_each_element() {
+        // arrange
+        let mut storage = MockMyRepositoryStorage::default();
+        storage
+            .expect_list()
+            .once()
+            .returning(|| Ok(vec![report(0)]));
+        storage
+            .expect_store()
+            .once()
+            .with(eq(vec![report(0), report(1), report(2)]))
+            .returning(|_| Ok(()));
+        let repo = StorageBasedRepository::new(storage);
+
+        // act
+        let actual = repo.add_all(vec![report(1), report(2)]);
+
+        // assert -> + mockall
+        assert_eq!(actual.unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn add_all_with_duplicates_returns_index_of_stored_elements() {
+        // arrange -> the batch repeats a stored report and one of its own
+        let mut storage = MockMyRepositoryStorage::default();
+        storage
+            .expect_list()
+            .once()
+            .returning(|| Ok(vec![report(0)]));
+        storage
+            .expect_store()
+            .once()
+            .withf(|list| list.len() == 2)
+            .returning(|_| Ok(()));
+        let repo = StorageBasedRepository::new(storage);
+
+        // act
+        let actual = repo.add_all(vec![report(0), report(1), report(1)]);
+
+        // assert -> + mockall
+        assert_eq!(actual.unwrap(), vec![0, 1, 1]);
+    }
+
+    #[test]
+    fn add_all_of_stored_elements_does_not_store() {
+        // arrange
+        let mut storage = MockMyRepositoryStorage::default();
+        storage
+            .expect_list()
+            .once()
+            .returning(|| Ok(vec![report(0), report(1)]));
+        storage.expect_store().never();
+        let repo = StorageBasedRepository::new(storage);
+
+        // act
+        let actual = repo.add_all(vec![report(1), report(0)]);
+
+        // assert -> + mockall
+        assert_eq!(actual.unwrap(), vec![1, 0]);
+    }
+
+    #[test]
+    fn add_all_failing_store_in_storage_returns_error() {
+        // arrange
+        let mut storage = MockMyRepositoryStorage::default();
+        storage.expect_list().once().returning(|| Ok(vec![]));
+        storage
+            .expect_store()
+            .once()
+            .returning(|_| ErrorOnTestSnafu.fail());
+        let repo = StorageBasedRepository::new(storage);
+
+        // act
+        let actual = repo.add_all(vec![report(0)]);
+
+        // assert
+        assert!(matches!(actual, Err(RepositoryError::ErrorOnTest)));
+    }
+
+    #[test]
+    fn add_crash_report_writes_all_plus_one_elements() {
+        // arrange
+        let mut storage = MockMyCrashStorage::default();
+        storage
+            .expect_list()
+            .once()
+            .returning(|| Ok(vec![crash(10, 0)]));
+        storage
+            .expect_store()
+            .once()
+            .with(eq(vec![crash(10, 0), crash(90, 2)]))
+            .returning(|_| Ok(()));
+        let repo = StorageBasedRepository::new(storage);
+
+        // act
+        let actual = repo.add(crash(90, 2));
+
+        // assert -> + mockall
+        assert_eq!(actual.unwrap(), 1);
+    }
+
+    #[test]
+    fn add_duplicate_crash_report_returns_index_of_stored_one() {
+        // arrange
+        let mut storage = MockMyCrashStorage::default();
+        storage
+            .expect_list()
+            .once()
+            .returning(|| Ok(vec![crash(10, 0), crash(20, 1)]));
+        storage.expect_store().never();
+        let repo = StorageBasedRepository::new(storage);
+
+        // act
+        let actual = repo.add(crash(20, 1));
+
+        // assert -> + mockall
+        assert_eq!(actual.unwrap(), 1);
+    }
+
+    #[test]
+    fn list_crash_reports_returns_all_elements() {
+        // arrange
+        let mut storage = MockMyCrashStorage::default();
+        storage
+            .expect_list()
+            .once()
+            .returning(|| Ok(vec![crash(10, 0), crash(20, 1)]));
+        let repo = StorageBasedRepository::new(storage);
+
+        // act
+        let actual = repo.list();
+
+        // assert
+        assert_eq!(actual.unwrap(), vec![crash(10, 0), crash(20, 1)]);
+    }
+
+    #[test]
+    fn add_crash_report_failing_store_in_storage_returns_error() {
+        // arrange
+        let mut storage = MockMyCrashStorage::default();
+        storage.expect_list().once().returning(|| Ok(vec![]));
+        storage
+            .expect_store()
+            .once()
+            .returning(|_| ErrorOnTestSnafu.fail());
+        let repo = StorageBasedRepository::new(storage);
+
+        // act
+        let actual = repo.add(crash(90, 2));
+
+        // assert
+        assert!(matches!(actual, Err(RepositoryError::ErrorOnTest)));
+    }
 }
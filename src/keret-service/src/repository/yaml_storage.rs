@@ -1,27 +1,29 @@
 use crate::repository::{
-    CantOpenRepositorySnafu, DeserializationFailedSnafu, RepositoryError, RepositoryStorage,
+    CantOpenRepositorySnafu, DeserializationFailedSnafu, Entry, RepositoryError, RepositoryStorage,
     SerializationFailedSnafu,
 };
-use keret_service_transmit::ActionReport;
 use snafu::ResultExt;
+use std::marker::PhantomData;
 use tracing::instrument;
 
 #[derive(Debug)]
-pub(crate) struct YamlRepositoryStorage {
+pub(crate) struct YamlRepositoryStorage<T: Entry> {
     filename: String,
+    entries: PhantomData<fn() -> T>,
 }
 
-impl YamlRepositoryStorage {
+impl<T: Entry> YamlRepositoryStorage<T> {
     pub(crate) fn new(filename: impl Into<String>) -> Self {
         Self {
             filename: filename.into(),
+            entries: PhantomData,
         }
     }
 }
 
-impl RepositoryStorage for YamlRepositoryStorage {
+impl<T: Entry> RepositoryStorage<T> for YamlRepositoryStorage<T> {
     #[instrument(skip(self))]
-    fn list(&self) -> Result<Vec<ActionReport>, RepositoryError> {
+    fn list(&self) -> Result<Vec<T>, RepositoryError> {
         if std::fs::metadata(&self.filename).is_err() {
             return Ok(vec![]);
         }
@@ -33,7 +35,7 @@ impl RepositoryStorage for YamlRepositoryStorage {
     }
 
     #[instrument(skip(self))]
-    fn store(&mut self, list: Vec<ActionReport>) -> Result<(), RepositoryError> {
+    fn store(&mut self, list: Vec<T>) -> Result<(), RepositoryError> {
         let file = std::fs::File::create(&self.filename).context(CantOpenRepositorySnafu)?;
         serde_yaml::to_writer(file, &list).context(SerializationFailedSnafu)?;
 